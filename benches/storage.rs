@@ -0,0 +1,235 @@
+//! Compares the `rows` and `columns` storages on the work the editor does most: loading and
+//! writing a file, summarising, sorting and grouping columns, and editing cells, rows and
+//! columns. Run with `cargo bench`, optionally giving the number of rows to generate:
+//! `cargo bench -- 500000`.
+
+use bootleg_editor_3000::{
+    columnar_csv::ColumnarCsv,
+    csv_data_handle::{ColumnRef, CsvDataHandle, Index},
+    csv_io::{read_csv, write_csv, CsvFormat, QuotePolicy},
+    group::{group_by, AggregateFunction, Aggregation},
+    sort::{SortKey, SortMode},
+    stats::ColumnStats,
+};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+const DEFAULT_ROWS: usize = 200_000;
+/// Each operation is timed this many times and the fastest run is reported.
+const RUNS: usize = 5;
+
+/// The system allocator, keeping count of how many bytes are allocated at any moment.
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATED.fetch_add(new_size, Ordering::Relaxed);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// A sales ledger with a unique id, a few regions, a few dozen products and numeric columns.
+fn generate_csv(rows: usize) -> String {
+    const REGIONS: [&str; 6] = ["north", "south", "east", "west", "central", "islands"];
+    let mut seed = 0x2545_f491_4f6c_dd1du64;
+    let mut next = move |bound: u64| {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed % bound
+    };
+    let mut text = String::from("id,region,product,quantity,price,date\n");
+    for id in 1..rows + 1 {
+        text += &format!(
+            "{},{},product {},{},{}.{:02},2024-{:02}-{:02}\n",
+            id,
+            REGIONS[next(REGIONS.len() as u64) as usize],
+            next(40),
+            next(100),
+            next(500),
+            next(100),
+            next(12) + 1,
+            next(28) + 1,
+        );
+    }
+    text
+}
+
+/// The fastest of `RUNS` runs of `operation`, each on a fresh copy of `data`.
+fn time<H: Clone>(data: &H, mut operation: impl FnMut(&mut H)) -> Duration {
+    (0..RUNS)
+        .map(|_| {
+            let mut data = data.clone();
+            let start = Instant::now();
+            operation(&mut data);
+            start.elapsed()
+        })
+        .min()
+        .unwrap_or_default()
+}
+
+/// The time each operation takes on `data`, in the order of `OPERATIONS`.
+fn run_operations<H: CsvDataHandle + Clone>(data: &H) -> Vec<Duration> {
+    let format = CsvFormat::default();
+    let region = [ColumnRef::Name("region".to_string())];
+    let quantity = data.column_of_field("quantity").unwrap();
+    let price = data.column_of_field("price").unwrap();
+    let rows = data.row_count();
+    vec![
+        time(data, |data| {
+            let mut out = std::io::sink();
+            write_csv(&mut out, data, &format, QuotePolicy::Minimal).unwrap();
+        }),
+        time(data, |data| {
+            for row in 1..rows + 1 {
+                std::hint::black_box(data.row_cells(row).unwrap());
+            }
+        }),
+        time(data, |data| {
+            std::hint::black_box(ColumnStats::compute(data, quantity, 5).unwrap());
+        }),
+        time(data, |data| {
+            let keys = [SortKey {
+                column: price,
+                descending: false,
+                mode: SortMode::Numeric,
+            }];
+            let order = data.sorted_order(&keys).unwrap();
+            data.reorder_rows(&order).unwrap();
+        }),
+        time(data, |data| {
+            let aggregations = [Aggregation {
+                function: AggregateFunction::Sum,
+                column: Some(ColumnRef::Name("quantity".to_string())),
+            }];
+            std::hint::black_box(group_by(data, &region, &aggregations).unwrap());
+        }),
+        time(data, |data| {
+            for row in (1..rows + 1).step_by(7) {
+                let index = Index {
+                    row,
+                    column: quantity,
+                };
+                data.replace_data_at(index, "0".to_string()).unwrap();
+            }
+        }),
+        time(data, |data| {
+            let doubled = data
+                .column(quantity)
+                .unwrap()
+                .into_iter()
+                .map(|cell| (cell.parse::<u64>().unwrap_or_default() * 2).to_string())
+                .collect();
+            data.replace_column(quantity, doubled).unwrap();
+        }),
+        time(data, |data| {
+            let new_row = ["0", "north", "product 0", "1", "1.00", "2024-01-01"];
+            for _ in 0..100 {
+                let new_row = new_row.map(String::from).to_vec();
+                data.insert_row(rows / 2, new_row).unwrap();
+            }
+        }),
+        time(data, |data| {
+            for _ in 0..100 {
+                data.delete_row(rows / 2).unwrap();
+            }
+        }),
+        time(data, |data| data.delete_column(price).unwrap()),
+    ]
+}
+
+const OPERATIONS: [&str; 10] = [
+    "write",
+    "read every row",
+    "stats quantity",
+    "sort by price",
+    "group_by region",
+    "set every 7th cell",
+    "replace column",
+    "insert 100 rows",
+    "delete 100 rows",
+    "delete column",
+];
+
+/// Loads `text` with `load`, returning the sheet, the time it took and the bytes it keeps.
+fn load<H>(text: &str, load: impl Fn(&[u8]) -> H) -> (H, Duration, usize) {
+    let mut fastest = Duration::MAX;
+    for _ in 1..RUNS {
+        let start = Instant::now();
+        std::hint::black_box(load(text.as_bytes()));
+        fastest = fastest.min(start.elapsed());
+    }
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    let start = Instant::now();
+    let data = load(text.as_bytes());
+    let elapsed = start.elapsed();
+    let size = ALLOCATED.load(Ordering::Relaxed).saturating_sub(before);
+    (data, fastest.min(elapsed), size)
+}
+
+fn milliseconds(duration: Duration) -> String {
+    format!("{:.2} ms", duration.as_secs_f64() * 1000.0)
+}
+
+fn main() {
+    let rows = std::env::args()
+        .skip(1)
+        .find_map(|arg| arg.parse().ok())
+        .unwrap_or(DEFAULT_ROWS);
+    let text = generate_csv(rows);
+    let format = CsvFormat::default();
+    let (csv_holder, rows_load, rows_size) = load(&text, |bytes| read_csv(bytes, &format).unwrap());
+    let (columnar_csv, columns_load, columns_size) =
+        load(&text, |bytes| ColumnarCsv::read(bytes, &format).unwrap());
+
+    println!(
+        "{} rows, {:.1} MB of CSV, fastest of {} runs\n",
+        rows,
+        text.len() as f64 / 1e6,
+        RUNS
+    );
+    println!("{:<20} {:>12} {:>12}", "operation", "rows", "columns");
+    println!(
+        "{:<20} {:>12} {:>12}",
+        "load",
+        milliseconds(rows_load),
+        milliseconds(columns_load)
+    );
+    let rows_times = run_operations(&csv_holder);
+    let columns_times = run_operations(&columnar_csv);
+    for ((operation, rows_time), columns_time) in
+        OPERATIONS.iter().zip(rows_times).zip(columns_times)
+    {
+        println!(
+            "{:<20} {:>12} {:>12}",
+            operation,
+            milliseconds(rows_time),
+            milliseconds(columns_time)
+        );
+    }
+    println!(
+        "{:<20} {:>9.1} MB {:>9.1} MB",
+        "memory",
+        rows_size as f64 / 1e6,
+        columns_size as f64 / 1e6
+    );
+}
@@ -0,0 +1,146 @@
+use crate::{buffers::Buffers, csv_request};
+use std::{io::Write, path::Path};
+
+/// A command to run without a prompt, along with where it came from for error messages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptCommand {
+    pub location: String,
+    pub text: String,
+}
+
+/// Reads one command per line, skipping blank lines and lines starting with `#`.
+pub fn read_script(path: &Path) -> std::io::Result<Vec<ScriptCommand>> {
+    let script = std::fs::read_to_string(path)?;
+    Ok(script_commands(&path.display().to_string(), &script))
+}
+
+pub fn script_commands(name: &str, script: &str) -> Vec<ScriptCommand> {
+    script
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(line_number, line)| ScriptCommand {
+            location: format!("{} line {}", name, line_number),
+            text: line.to_string(),
+        })
+        .collect()
+}
+
+pub fn command_line_commands(commands: &[String]) -> Vec<ScriptCommand> {
+    commands
+        .iter()
+        .enumerate()
+        .map(|(i, command)| ScriptCommand {
+            location: format!("command {}", i + 1),
+            text: command.trim().to_string(),
+        })
+        .collect()
+}
+
+/// Runs `commands` in order and returns whether all of them succeeded. A `quit` command, or the
+/// first failure unless `keep_going` is set, stops the run.
+pub fn run_commands(
+    commands: &[ScriptCommand],
+    buffers: &mut Buffers,
+    keep_going: bool,
+    out: &mut impl Write,
+    err: &mut impl Write,
+) -> std::io::Result<bool> {
+    let mut all_succeeded = true;
+    for command in commands {
+        let format = &buffers.active().options.format;
+        let succeeded = match csv_request::parse_csv_request(&command.text, format) {
+            Ok(csv_request::CsvRequest::Quit) => break,
+            Err(e) => {
+                writeln!(
+                    err,
+                    "{}: CSV Request Entered Incorrectly: {}",
+                    command.location, e
+                )?;
+                for syntax in e.usage() {
+                    writeln!(err, "usage: {}", syntax)?;
+                }
+                false
+            }
+            Ok(csv_request) => {
+                match csv_request::handle_buffer_request(csv_request, buffers, out) {
+                    Ok(()) => true,
+                    Err(e) => {
+                        writeln!(err, "{}: CSV Request Failed: {}", command.location, e)?;
+                        if let Some(snippet) = e.snippet() {
+                            write!(err, "{}", snippet)?;
+                        }
+                        false
+                    }
+                }
+            }
+        };
+        all_succeeded &= succeeded;
+        if !succeeded && !keep_going {
+            break;
+        }
+    }
+    Ok(all_succeeded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffers::Buffer;
+    use crate::csv_data_handle::CsvDataHandle;
+    use crate::csv_holder::CsvHolder;
+
+    fn buffers() -> Buffers {
+        let csv_holder = CsvHolder::from_strs(&["a", "b"], &[&["1", "2"], &["3", "4"]], &[]);
+        Buffers::new(Buffer::new(
+            "sheet".to_string(),
+            csv_holder.into(),
+            csv_request::RequestOptions::default(),
+        ))
+    }
+
+    #[test]
+    fn test_script_commands() {
+        let commands = script_commands("edits.txt", "# tidy up\n\ndelete_row 1\n  dimensions \n");
+        assert_eq!(
+            commands,
+            vec![
+                ScriptCommand {
+                    location: "edits.txt line 3".to_string(),
+                    text: "delete_row 1".to_string(),
+                },
+                ScriptCommand {
+                    location: "edits.txt line 4".to_string(),
+                    text: "dimensions".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stops_on_first_failure() {
+        let mut buffers = buffers();
+        let commands = script_commands("edits.txt", "delete_row 5\ndelete_row 1\n");
+        let mut err = Vec::new();
+        let succeeded =
+            run_commands(&commands, &mut buffers, false, &mut Vec::new(), &mut err).unwrap();
+        assert!(!succeeded);
+        assert_eq!(buffers.active().data.row_count(), 2);
+        assert!(String::from_utf8(err)
+            .unwrap()
+            .starts_with("edits.txt line 1: CSV Request Failed"));
+    }
+
+    #[test]
+    fn test_keep_going() {
+        let mut buffers = buffers();
+        let commands = command_line_commands(&["bogus".to_string(), "delete_row 1".to_string()]);
+        let mut err = Vec::new();
+        let succeeded =
+            run_commands(&commands, &mut buffers, true, &mut Vec::new(), &mut err).unwrap();
+        assert!(!succeeded);
+        assert_eq!(buffers.active().data.row_count(), 1);
+        assert!(String::from_utf8(err).unwrap().starts_with("command 1:"));
+    }
+}
@@ -0,0 +1,179 @@
+use crate::{
+    csv_io::CsvIoError,
+    csv_request::{RequestOptions, Session},
+    sheet::Sheet,
+};
+use std::path::Path;
+
+/// A sheet open for editing, with everything that goes along with it.
+pub struct Buffer {
+    pub name: String,
+    pub data: Sheet,
+    pub session: Session,
+    /// Where `save` writes the sheet, and how it is laid out.
+    pub options: RequestOptions,
+}
+
+impl Buffer {
+    pub fn new(name: String, data: Sheet, options: RequestOptions) -> Self {
+        Self {
+            name,
+            session: Session::new(&data),
+            data,
+            options,
+        }
+    }
+
+    /// A name for a buffer holding the file at `path`.
+    pub fn name_for(path: &Path) -> String {
+        match path.file_stem() {
+            Some(stem) => stem.to_string_lossy().into_owned(),
+            None => "sheet".to_string(),
+        }
+    }
+
+    /// Whether the loaded sheet has changed since it was last saved; a result being worked on
+    /// has nowhere to be saved to.
+    pub fn has_unsaved_changes(&self) -> bool {
+        match &self.session.original {
+            Some(original) => original.0.has_unsaved_changes(),
+            None => self.data.has_unsaved_changes(),
+        }
+    }
+}
+
+/// The buffers open in a session, one of which requests act on.
+pub struct Buffers {
+    buffers: Vec<Buffer>,
+    active: usize,
+    /// Where the buffer the session started with is, unless it has been closed.
+    loaded: Option<usize>,
+}
+
+impl Buffers {
+    pub fn new(first: Buffer) -> Self {
+        Self {
+            buffers: vec![first],
+            active: 0,
+            loaded: Some(0),
+        }
+    }
+
+    pub fn active(&self) -> &Buffer {
+        &self.buffers[self.active]
+    }
+
+    pub fn active_mut(&mut self) -> &mut Buffer {
+        &mut self.buffers[self.active]
+    }
+
+    /// The buffer the session started with, whichever is active, unless it has been closed.
+    pub fn loaded(&self) -> Option<&Buffer> {
+        self.loaded.map(|loaded| &self.buffers[loaded])
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Buffer> {
+        self.buffers.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Buffer> {
+        self.buffers.iter_mut()
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Result<&mut Buffer, CsvIoError> {
+        let position = self.position(name)?;
+        Ok(&mut self.buffers[position])
+    }
+
+    /// Adds `buffer` and makes it the active one.
+    pub fn open(&mut self, buffer: Buffer) -> Result<(), CsvIoError> {
+        if self.position(&buffer.name).is_ok() {
+            return Err(CsvIoError::BufferExists(buffer.name));
+        }
+        self.buffers.push(buffer);
+        self.active = self.buffers.len() - 1;
+        Ok(())
+    }
+
+    pub fn switch(&mut self, name: &str) -> Result<(), CsvIoError> {
+        self.active = self.position(name)?;
+        Ok(())
+    }
+
+    /// Closes the buffer called `name`, refusing to throw away unsaved changes unless `discard`
+    /// is set. Closing the active buffer makes the one before it active.
+    pub fn close(&mut self, name: &str, discard: bool) -> Result<(), CsvIoError> {
+        let position = self.position(name)?;
+        if self.buffers.len() == 1 {
+            return Err(CsvIoError::LastBuffer);
+        }
+        if !discard && self.buffers[position].has_unsaved_changes() {
+            return Err(CsvIoError::UnsavedBuffer(name.to_string()));
+        }
+        self.buffers.remove(position);
+        if self.active >= position && self.active > 0 {
+            self.active -= 1;
+        }
+        self.loaded = match self.loaded {
+            Some(loaded) if loaded == position => None,
+            Some(loaded) if loaded > position => Some(loaded - 1),
+            loaded => loaded,
+        };
+        Ok(())
+    }
+
+    fn position(&self, name: &str) -> Result<usize, CsvIoError> {
+        self.buffers
+            .iter()
+            .position(|buffer| buffer.name == name)
+            .ok_or_else(|| CsvIoError::NoSuchBuffer(name.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{csv_data_handle::CsvDataHandle, csv_data_handle::Index, csv_holder::CsvHolder};
+
+    fn buffer(name: &str) -> Buffer {
+        let data = CsvHolder::from_strs(&["a"], &[&[name]], &[]);
+        Buffer::new(name.to_string(), data.into(), RequestOptions::default())
+    }
+
+    #[test]
+    fn test_open_switch_and_close() {
+        let mut buffers = Buffers::new(buffer("first"));
+        buffers.open(buffer("second")).unwrap();
+        assert_eq!(buffers.active().name, "second");
+        assert!(buffers.open(buffer("first")).is_err());
+        buffers.open(buffer("third")).unwrap();
+        buffers.switch("second").unwrap();
+        assert!(buffers.switch("fourth").is_err());
+        assert_eq!(buffers.loaded().unwrap().name, "first");
+
+        let index = Index { row: 1, column: 1 };
+        let second = buffers.get_mut("second").unwrap();
+        second
+            .data
+            .replace_data_at(index, "changed".to_string())
+            .unwrap();
+        assert!(buffers.close("second", false).is_err());
+        buffers.close("second", true).unwrap();
+        assert_eq!(buffers.active().name, "first");
+        assert_eq!(buffers.loaded().unwrap().name, "first");
+        buffers.close("first", false).unwrap();
+        assert_eq!(buffers.active().name, "third");
+        assert!(buffers.loaded().is_none());
+        assert!(buffers.close("third", false).is_err());
+        assert_eq!(buffers.iter().count(), 1);
+    }
+
+    #[test]
+    fn test_name_for() {
+        assert_eq!(
+            Buffer::name_for(Path::new("data/sales.2024.csv")),
+            "sales.2024"
+        );
+        assert_eq!(Buffer::name_for(Path::new("/")), "sheet");
+    }
+}
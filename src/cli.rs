@@ -0,0 +1,446 @@
+use bootleg_editor_3000::{
+    csv_io::{CsvFormat, Encoding, ParseMode},
+    dialect::{Dialect, EscapeStyle, LineTerminator},
+    sheet::Storage,
+};
+use std::{error::Error, fmt::Display, path::PathBuf};
+
+pub const USAGE: &str = "\
+Usage: bootleg_editor_3000 [options] <input>
+       bootleg_editor_3000 [options] (--script <file> | -c <command>...) <input>
+
+Arguments:
+  <input>                  CSV file to edit, or - to read it from stdin (needs --script or -c)
+
+Options:
+  -o, --output <file>      File that 'save' writes to (defaults to <input>)
+      --dialect <name>     csv, tsv, semicolon or pipe
+  -d, --delimiter <char>   Field delimiter, e.g. ';' or 'tab'
+      --quote <char>       Character that quotes fields (defaults to a double quote)
+      --escape <style>     doubled or backslash: how a quote is escaped inside quotes
+      --line-terminator <lf|crlf>
+                           Line ending to write
+                           If none of the five options above is given, the dialect is
+                           guessed from the input
+      --no-header          Treat the first line as data rather than column names
+      --encoding <name>    utf-8 or latin1 (defaults to utf-8)
+      --raw                Split on every delimiter and keep quotes as they are
+      --storage <rows|file|columns>
+                           rows reads the whole file into memory (the default); file reads
+                           rows from the file as they are needed, for files larger than memory;
+                           columns reads the whole file into memory column by column, storing
+                           each distinct value of a column once
+      --script <file>      Run the commands in <file>, one per line, then write the result
+  -c, --command <command>  Run <command>; may be repeated and combined with --script
+      --keep-going         Carry on past failed commands in a script
+  -h, --help               Print this message";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Input {
+    Stdin,
+    File(PathBuf),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CliOptions {
+    pub input: Input,
+    pub output: Option<PathBuf>,
+    pub format: CsvFormat,
+    pub storage: Storage,
+    /// Whether to guess `format.dialect` from the input because no dialect option was given.
+    pub sniff_dialect: bool,
+    pub script: Option<PathBuf>,
+    pub commands: Vec<String>,
+    pub keep_going: bool,
+}
+
+impl CliOptions {
+    /// Whether to run commands without a prompt instead of starting an interactive session.
+    pub fn is_batch(&self) -> bool {
+        self.script.is_some() || !self.commands.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CliCommand {
+    Run(CliOptions),
+    Help,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CliError {
+    MissingInput,
+    UnexpectedArgument(String),
+    UnknownOption(String),
+    MissingValue(String),
+    InvalidDelimiter(String),
+    InvalidQuote(String),
+    /// The delimiter is the character that quotes fields.
+    DelimiterIsQuote(char),
+    UnknownDialect(String),
+    UnknownEscapeStyle(String),
+    UnknownLineTerminator(String),
+    UnknownEncoding(String),
+    UnknownStorage(String),
+    /// The first option cannot be used along with the second.
+    IncompatibleOptions(String, String),
+    /// The input is read from stdin, which leaves nothing to read commands from.
+    InteractiveStdin,
+}
+
+impl Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CliError::MissingInput => write!(f, "No input file given"),
+            CliError::UnexpectedArgument(arg) => write!(f, "Unexpected argument: {}", arg),
+            CliError::UnknownOption(option) => write!(f, "Unknown option: {}", option),
+            CliError::MissingValue(option) => write!(f, "Option {} needs a value", option),
+            CliError::InvalidDelimiter(delimiter) => {
+                write!(f, "Delimiter must be a single character: {}", delimiter)
+            }
+            CliError::InvalidQuote(quote) => {
+                write!(f, "Quote must be a single character: {}", quote)
+            }
+            CliError::DelimiterIsQuote(delimiter) => write!(
+                f,
+                "Delimiter cannot be the character that quotes fields: {:?}",
+                delimiter
+            ),
+            CliError::UnknownDialect(dialect) => write!(f, "Unknown dialect: {}", dialect),
+            CliError::UnknownEscapeStyle(escape) => {
+                write!(f, "Unknown escape style: {}", escape)
+            }
+            CliError::UnknownLineTerminator(terminator) => {
+                write!(f, "Unknown line terminator: {}", terminator)
+            }
+            CliError::UnknownEncoding(encoding) => write!(f, "Unknown encoding: {}", encoding),
+            CliError::UnknownStorage(storage) => write!(f, "Unknown storage: {}", storage),
+            CliError::IncompatibleOptions(first, second) => {
+                write!(f, "{} cannot be used with {}", first, second)
+            }
+            CliError::InteractiveStdin => write!(
+                f,
+                "Reading the input from stdin needs --script or -c, as stdin then has no commands left to read"
+            ),
+        }
+    }
+}
+
+impl Error for CliError {}
+
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<CliCommand, CliError> {
+    let mut args = args.into_iter();
+    let mut input = None;
+    let mut output = None;
+    let mut format = CsvFormat::default();
+    let mut storage = Storage::default();
+    // `--dialect` gives every part of the dialect, and the other dialect options override
+    // single parts of it whichever order they come in.
+    let mut dialect = None;
+    let mut delimiter = None;
+    let mut quote = None;
+    let mut escape = None;
+    let mut line_terminator = None;
+    let mut script = None;
+    let mut commands = Vec::new();
+    let mut keep_going = false;
+    while let Some(arg) = args.next() {
+        let (option, inline_value) = match arg.split_once('=') {
+            Some((option, value)) if option.starts_with("--") => (option, Some(value.to_string())),
+            _ => (arg.as_str(), None),
+        };
+        let mut value = || {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| CliError::MissingValue(option.to_string()))
+        };
+        match option {
+            "-h" | "--help" => return Ok(CliCommand::Help),
+            "-o" | "--output" => output = Some(PathBuf::from(value()?)),
+            "--dialect" => {
+                let name = value()?;
+                dialect = Some(Dialect::from_name(&name).ok_or(CliError::UnknownDialect(name))?);
+            }
+            "-d" | "--delimiter" => delimiter = Some(parse_delimiter(&value()?)?),
+            "--quote" => {
+                let value = value()?;
+                quote = Some(
+                    single_char(&value)
+                        .filter(|&c| !matches!(c, '\n' | '\r'))
+                        .ok_or(CliError::InvalidQuote(value))?,
+                );
+            }
+            "--escape" => {
+                escape = Some(match value()?.as_str() {
+                    "doubled" => EscapeStyle::Doubled,
+                    "backslash" => EscapeStyle::Backslash,
+                    escape => return Err(CliError::UnknownEscapeStyle(escape.to_string())),
+                });
+            }
+            "--line-terminator" => {
+                line_terminator = Some(match value()?.as_str() {
+                    "lf" => LineTerminator::Lf,
+                    "crlf" => LineTerminator::CrLf,
+                    terminator => {
+                        return Err(CliError::UnknownLineTerminator(terminator.to_string()))
+                    }
+                });
+            }
+            "--encoding" => {
+                let name = value()?;
+                format.encoding =
+                    Encoding::from_name(&name).ok_or(CliError::UnknownEncoding(name))?;
+            }
+            "--no-header" => format.has_header = false,
+            "--raw" => format.parse_mode = ParseMode::Raw,
+            "--storage" => {
+                let name = value()?;
+                storage = Storage::from_name(&name).ok_or(CliError::UnknownStorage(name))?;
+            }
+            "--script" => script = Some(PathBuf::from(value()?)),
+            "-c" | "--command" => commands.push(value()?),
+            "--keep-going" => keep_going = true,
+            "-" if input.is_none() => input = Some(Input::Stdin),
+            option if option.starts_with('-') && option != "-" => {
+                return Err(CliError::UnknownOption(option.to_string()))
+            }
+            _ if input.is_none() => input = Some(Input::File(PathBuf::from(arg))),
+            _ => return Err(CliError::UnexpectedArgument(arg)),
+        }
+    }
+    let input = input.ok_or(CliError::MissingInput)?;
+    let sniff_dialect = dialect.is_none()
+        && delimiter.is_none()
+        && quote.is_none()
+        && escape.is_none()
+        && line_terminator.is_none();
+    format.dialect = dialect.unwrap_or_default();
+    format.dialect.delimiter = delimiter.unwrap_or(format.dialect.delimiter);
+    format.dialect.quote = quote.unwrap_or(format.dialect.quote);
+    format.dialect.escape = escape.unwrap_or(format.dialect.escape);
+    format.dialect.line_terminator = line_terminator.unwrap_or(format.dialect.line_terminator);
+    if format.dialect.delimiter == format.dialect.quote {
+        return Err(CliError::DelimiterIsQuote(format.dialect.delimiter));
+    }
+    if storage == Storage::File {
+        // Rows are read straight out of the file, so it has to be a UTF-8 file.
+        let incompatible = match input {
+            Input::Stdin => Some("reading from stdin".to_string()),
+            Input::File(_) if format.encoding != Encoding::Utf8 => {
+                Some(format!("--encoding {}", format.encoding))
+            }
+            Input::File(_) => None,
+        };
+        if let Some(option) = incompatible {
+            return Err(CliError::IncompatibleOptions(
+                "--storage file".to_string(),
+                option,
+            ));
+        }
+    }
+    if input == Input::Stdin && script.is_none() && commands.is_empty() {
+        return Err(CliError::InteractiveStdin);
+    }
+    Ok(CliCommand::Run(CliOptions {
+        input,
+        output,
+        format,
+        storage,
+        sniff_dialect,
+        script,
+        commands,
+        keep_going,
+    }))
+}
+
+fn parse_delimiter(delimiter: &str) -> Result<char, CliError> {
+    match delimiter {
+        "tab" | "\\t" => Ok('\t'),
+        _ => single_char(delimiter)
+            .filter(|&c| !matches!(c, '\n' | '\r'))
+            .ok_or_else(|| CliError::InvalidDelimiter(delimiter.to_string())),
+    }
+}
+
+fn single_char(s: &str) -> Option<char> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<CliCommand, CliError> {
+        parse_args(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn test_input_and_options() {
+        let CliCommand::Run(options) = parse(&[
+            "data.csv",
+            "--output",
+            "out.csv",
+            "--delimiter=;",
+            "--no-header",
+            "--encoding",
+            "latin1",
+        ])
+        .unwrap() else {
+            panic!("expected options");
+        };
+        assert_eq!(options.input, Input::File(PathBuf::from("data.csv")));
+        assert_eq!(options.output, Some(PathBuf::from("out.csv")));
+        assert_eq!(options.format.dialect.delimiter, ';');
+        assert!(!options.format.has_header);
+        assert_eq!(options.format.encoding, Encoding::Latin1);
+        assert_eq!(options.storage, Storage::Rows);
+        assert!(!options.sniff_dialect);
+    }
+
+    #[test]
+    fn test_dialect_options() {
+        let CliCommand::Run(options) = parse(&[
+            "data.csv",
+            "--dialect",
+            "pipe",
+            "--quote",
+            "'",
+            "--escape",
+            "backslash",
+            "--line-terminator",
+            "crlf",
+        ])
+        .unwrap() else {
+            panic!("expected options");
+        };
+        assert_eq!(
+            options.format.dialect,
+            Dialect {
+                delimiter: '|',
+                quote: '\'',
+                escape: EscapeStyle::Backslash,
+                line_terminator: LineTerminator::CrLf,
+            }
+        );
+        let CliCommand::Run(options) = parse(&[
+            "--line-terminator=crlf",
+            "--delimiter",
+            ";",
+            "--dialect",
+            "tsv",
+            "data.csv",
+        ])
+        .unwrap() else {
+            panic!("expected options");
+        };
+        assert_eq!(
+            options.format.dialect,
+            Dialect {
+                delimiter: ';',
+                line_terminator: LineTerminator::CrLf,
+                ..Dialect::default()
+            }
+        );
+        let CliCommand::Run(options) = parse(&["data.csv"]).unwrap() else {
+            panic!("expected options");
+        };
+        assert!(options.sniff_dialect);
+    }
+
+    #[test]
+    fn test_stdin_and_tab() {
+        let CliCommand::Run(options) = parse(&["-d", "tab", "-", "-c", "display"]).unwrap() else {
+            panic!("expected options");
+        };
+        assert_eq!(options.input, Input::Stdin);
+        assert_eq!(options.format.dialect.delimiter, '\t');
+    }
+
+    #[test]
+    fn test_batch_options() {
+        let CliCommand::Run(options) = parse(&[
+            "in.csv",
+            "-c",
+            "delete_row 1",
+            "--command=dimensions",
+            "--keep-going",
+        ])
+        .unwrap() else {
+            panic!("expected options");
+        };
+        assert!(options.is_batch());
+        assert_eq!(options.commands, vec!["delete_row 1", "dimensions"]);
+        assert!(options.keep_going);
+        let CliCommand::Run(options) = parse(&["in.csv", "--storage", "file"]).unwrap() else {
+            panic!("expected options");
+        };
+        assert!(!options.is_batch());
+        assert_eq!(options.storage, Storage::File);
+        let CliCommand::Run(options) =
+            parse(&["-", "--storage", "columns", "-c", "display"]).unwrap()
+        else {
+            panic!("expected options");
+        };
+        assert_eq!(options.storage, Storage::Columns);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(parse(&[]), Err(CliError::MissingInput));
+        assert_eq!(
+            parse(&["a.csv", "b.csv"]),
+            Err(CliError::UnexpectedArgument("b.csv".to_string()))
+        );
+        assert_eq!(
+            parse(&["a.csv", "--output"]),
+            Err(CliError::MissingValue("--output".to_string()))
+        );
+        assert_eq!(
+            parse(&["a.csv", "-d", ";;"]),
+            Err(CliError::InvalidDelimiter(";;".to_string()))
+        );
+        assert_eq!(
+            parse(&["a.csv", "-d", "\""]),
+            Err(CliError::DelimiterIsQuote('"'))
+        );
+        assert_eq!(
+            parse(&["a.csv", "--quote", "'", "--delimiter", "'"]),
+            Err(CliError::DelimiterIsQuote('\''))
+        );
+        assert_eq!(
+            parse(&["a.csv", "--dialect", "xml"]),
+            Err(CliError::UnknownDialect("xml".to_string()))
+        );
+        assert_eq!(
+            parse(&["a.csv", "--encoding", "ebcdic"]),
+            Err(CliError::UnknownEncoding("ebcdic".to_string()))
+        );
+        assert_eq!(
+            parse(&["a.csv", "--storage", "disk"]),
+            Err(CliError::UnknownStorage("disk".to_string()))
+        );
+        assert_eq!(
+            parse(&["--storage=file", "-"]),
+            Err(CliError::IncompatibleOptions(
+                "--storage file".to_string(),
+                "reading from stdin".to_string()
+            ))
+        );
+        assert_eq!(
+            parse(&["a.csv", "--storage", "file", "--encoding", "latin1"])
+                .unwrap_err()
+                .to_string(),
+            "--storage file cannot be used with --encoding latin1"
+        );
+        assert_eq!(parse(&["-"]), Err(CliError::InteractiveStdin));
+        assert_eq!(
+            parse(&["--frobnicate"]),
+            Err(CliError::UnknownOption("--frobnicate".to_string()))
+        );
+    }
+}
@@ -0,0 +1,366 @@
+use crate::{
+    csv_data_handle::*,
+    csv_io::{read_records, CsvFormat, CsvIoError},
+};
+use std::{collections::HashMap, io::BufRead, sync::Arc};
+
+/// A sheet kept column by column rather than row by row. Each column holds every distinct
+/// value it has once, and a code for each of its cells, so columns that repeat a few values
+/// take little memory, and going down a whole column reads it in order.
+///
+/// Values a column no longer holds stay in its dictionary until the column is replaced.
+#[derive(Debug, Clone, Default)]
+pub struct ColumnarCsv {
+    headers: Vec<String>,
+    columns: Vec<Column>,
+    row_count: usize,
+    /// Whether the data has changed since it was loaded or last saved.
+    modified: bool,
+}
+
+/// The code of a cell that holds no value at all.
+const NULL: u32 = u32::MAX;
+
+#[derive(Debug, Clone, Default)]
+struct Column {
+    /// The code of each cell's value in `values`, or `NULL`.
+    cells: Vec<u32>,
+    values: Vec<Arc<str>>,
+    codes: HashMap<Arc<str>, u32>,
+}
+
+impl Column {
+    fn from_cells(cells: impl IntoIterator<Item = Option<String>>) -> Self {
+        let mut column = Column::default();
+        column.cells = cells.into_iter().map(|cell| column.code(cell)).collect();
+        column
+    }
+
+    /// The code for `cell`, adding it to the dictionary if it is new.
+    fn code(&mut self, cell: Option<String>) -> u32 {
+        let Some(value) = cell else {
+            return NULL;
+        };
+        if let Some(&code) = self.codes.get(value.as_str()) {
+            return code;
+        }
+        let code = u32::try_from(self.values.len())
+            .ok()
+            .filter(|&code| code != NULL)
+            .expect("a column has fewer distinct values than there are codes");
+        let value = Arc::<str>::from(value);
+        self.values.push(Arc::clone(&value));
+        self.codes.insert(value, code);
+        code
+    }
+
+    fn cell(&self, row: usize) -> Option<&str> {
+        match self.cells[row - 1] {
+            NULL => None,
+            code => Some(&self.values[code as usize]),
+        }
+    }
+}
+
+impl ColumnarCsv {
+    /// Reads a sheet the way `read_csv` does, storing it column by column.
+    pub fn read(reader: impl BufRead, format: &CsvFormat) -> Result<Self, CsvIoError> {
+        let mut columns = Vec::<Column>::new();
+        let mut row_count = 0;
+        let headers = read_records(reader, format, |cells| {
+            if row_count == 0 {
+                columns = vec![Column::default(); cells.len()];
+            }
+            for (column, cell) in columns.iter_mut().zip(cells) {
+                let code = column.code(cell);
+                column.cells.push(code);
+            }
+            row_count += 1;
+            Ok(())
+        })?;
+        if row_count == 0 {
+            columns = vec![Column::default(); headers.len()];
+        }
+        Ok(Self {
+            headers,
+            columns,
+            row_count,
+            modified: false,
+        })
+    }
+
+    pub fn row_exists(&self, row: usize) -> bool {
+        row > 0 && row <= self.row_count
+    }
+
+    pub fn column_exists(&self, column: usize) -> bool {
+        column > 0 && column <= self.headers.len()
+    }
+
+    pub fn index_exists(&self, index: Index) -> bool {
+        let Index { row, column } = index;
+        self.column_exists(column) && self.row_exists(row)
+    }
+
+    pub fn has_unsaved_changes(&self) -> bool {
+        self.modified
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.modified = false;
+    }
+}
+
+impl CsvDataHandle for ColumnarCsv {
+    fn data_at(&self, index: Index) -> CsvResult<&str> {
+        Ok(self.cell(index)?.unwrap_or_default())
+    }
+
+    fn is_null(&self, index: Index) -> CsvResult<bool> {
+        Ok(self.cell(index)?.is_none())
+    }
+
+    fn cell(&self, index: Index) -> CsvResult<Option<&str>> {
+        if !self.index_exists(index) {
+            return Err(CsvError::NoSuchIndex(index));
+        }
+        Ok(self.columns[index.column - 1].cell(index.row))
+    }
+
+    fn row(&self, row: usize) -> CsvResult<Vec<&str>> {
+        let cells = self.row_cells(row)?;
+        Ok(cells.into_iter().map(Option::unwrap_or_default).collect())
+    }
+
+    fn row_cells(&self, row: usize) -> CsvResult<Vec<Option<&str>>> {
+        if !self.row_exists(row) {
+            return Err(CsvError::NoSuchRow(row));
+        }
+        Ok(self.columns.iter().map(|column| column.cell(row)).collect())
+    }
+
+    fn column(&self, column: usize) -> CsvResult<Vec<&str>> {
+        let cells = self.column_cells(column)?;
+        Ok(cells.into_iter().map(Option::unwrap_or_default).collect())
+    }
+
+    fn column_cells(&self, column: usize) -> CsvResult<Vec<Option<&str>>> {
+        if !self.column_exists(column) {
+            return Err(CsvError::NoSuchColumn(column));
+        }
+        let column = &self.columns[column - 1];
+        Ok(column
+            .cells
+            .iter()
+            .map(|&code| match code {
+                NULL => None,
+                code => Some(&*column.values[code as usize]),
+            })
+            .collect())
+    }
+
+    fn headers(&self) -> Vec<&str> {
+        self.headers.iter().map(|s| s.as_str()).collect()
+    }
+
+    fn property_count(&self) -> usize {
+        self.headers.len()
+    }
+
+    fn row_count(&self) -> usize {
+        self.row_count
+    }
+
+    fn column_count(&self) -> usize {
+        self.headers.len()
+    }
+
+    fn column_of_field(&self, field: &str) -> CsvResult<usize> {
+        self.headers
+            .iter()
+            .position(|s| s == field)
+            .map(|s| s + 1)
+            .ok_or(CsvError::NoSuchField(field.to_string()))
+    }
+
+    fn replace_data_at(&mut self, index: Index, new_data: String) -> CsvResult<()> {
+        if !self.index_exists(index) {
+            return Err(CsvError::NoSuchIndex(index));
+        }
+        let column = &mut self.columns[index.column - 1];
+        column.cells[index.row - 1] = column.code(Some(new_data));
+        self.modified = true;
+        Ok(())
+    }
+
+    fn set_null(&mut self, index: Index) -> CsvResult<()> {
+        if !self.index_exists(index) {
+            return Err(CsvError::NoSuchIndex(index));
+        }
+        self.columns[index.column - 1].cells[index.row - 1] = NULL;
+        self.modified = true;
+        Ok(())
+    }
+
+    fn replace_column(&mut self, column: usize, new_data: Vec<String>) -> CsvResult<()> {
+        if !self.column_exists(column) {
+            return Err(CsvError::NoSuchColumn(column));
+        }
+        if new_data.len() != self.row_count {
+            return Err(CsvError::FailedToReplaceColumn(column));
+        }
+        self.columns[column - 1] = Column::from_cells(new_data.into_iter().map(Some));
+        self.modified = true;
+        Ok(())
+    }
+
+    fn replace_row(&mut self, row: usize, new_data: Vec<String>) -> CsvResult<()> {
+        if !self.row_exists(row) {
+            return Err(CsvError::NoSuchRow(row));
+        }
+        if new_data.len() != self.property_count() {
+            return Err(CsvError::FailedToReplaceRow(row));
+        }
+        for (column, new_value) in self.columns.iter_mut().zip(new_data) {
+            column.cells[row - 1] = column.code(Some(new_value));
+        }
+        self.modified = true;
+        Ok(())
+    }
+
+    fn insert_row(&mut self, row: usize, new_data: Vec<String>) -> CsvResult<()> {
+        if row == 0 || row > self.row_count + 1 {
+            return Err(CsvError::NoSuchRow(row));
+        }
+        if new_data.len() != self.property_count() {
+            return Err(CsvError::FailedToInsertRow(row));
+        }
+        for (column, new_value) in self.columns.iter_mut().zip(new_data) {
+            let code = column.code(Some(new_value));
+            column.cells.insert(row - 1, code);
+        }
+        self.row_count += 1;
+        self.modified = true;
+        Ok(())
+    }
+
+    fn insert_column(
+        &mut self,
+        column: usize,
+        header: String,
+        new_data: Vec<String>,
+    ) -> CsvResult<()> {
+        if column == 0 || column > self.column_count() + 1 {
+            return Err(CsvError::NoSuchColumn(column));
+        }
+        if new_data.len() != self.row_count {
+            return Err(CsvError::FailedToInsertColumn(column));
+        }
+        self.headers.insert(column - 1, header);
+        let new_column = Column::from_cells(new_data.into_iter().map(Some));
+        self.columns.insert(column - 1, new_column);
+        self.modified = true;
+        Ok(())
+    }
+
+    fn reorder_rows(&mut self, order: &[usize]) -> CsvResult<()> {
+        let mut seen = vec![false; self.row_count];
+        for &row in order {
+            if !self.row_exists(row) || std::mem::replace(&mut seen[row - 1], true) {
+                return Err(CsvError::FailedToReorderRows);
+            }
+        }
+        if order.len() != self.row_count {
+            return Err(CsvError::FailedToReorderRows);
+        }
+        for column in &mut self.columns {
+            column.cells = order.iter().map(|&row| column.cells[row - 1]).collect();
+        }
+        self.modified = true;
+        Ok(())
+    }
+
+    fn delete_row(&mut self, row: usize) -> CsvResult<()> {
+        if !self.row_exists(row) {
+            return Err(CsvError::NoSuchRow(row));
+        }
+        for column in &mut self.columns {
+            column.cells.remove(row - 1);
+        }
+        self.row_count -= 1;
+        self.modified = true;
+        Ok(())
+    }
+
+    fn delete_column(&mut self, column: usize) -> CsvResult<()> {
+        if !self.column_exists(column) {
+            return Err(CsvError::NoSuchColumn(column));
+        }
+        self.headers.remove(column - 1);
+        self.columns.remove(column - 1);
+        self.modified = true;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{csv_data_handle::tests::cells, csv_io::read_csv};
+
+    const TEXT: &str = "city,rank\nCork,1\nDublin,\nCork,\"\"\n\nGalway,2\n";
+
+    #[test]
+    fn test_reads_like_read_csv() {
+        let format = CsvFormat::default();
+        let columnar_csv = ColumnarCsv::read(TEXT.as_bytes(), &format).unwrap();
+        assert_eq!(
+            cells(&columnar_csv),
+            cells(&read_csv(TEXT.as_bytes(), &format).unwrap())
+        );
+        assert_eq!(columnar_csv.columns[0].values.len(), 3);
+        assert_eq!(
+            columnar_csv.column_cells(2).unwrap(),
+            [Some("1"), None, Some(""), Some("2")]
+        );
+        assert!(ColumnarCsv::read("a,b\n1\n".as_bytes(), &format).is_err());
+        let empty = ColumnarCsv::read("a,b\n".as_bytes(), &format).unwrap();
+        assert_eq!((empty.row_count(), empty.column_count()), (0, 2));
+
+        let sheet = crate::sheet::Sheet::from(columnar_csv);
+        let row_count = std::thread::spawn(move || sheet.row_count());
+        assert_eq!(row_count.join().unwrap(), 4);
+    }
+
+    #[test]
+    fn test_edits_match_csv_holder() {
+        let format = CsvFormat::default();
+        let mut columnar_csv = ColumnarCsv::read(TEXT.as_bytes(), &format).unwrap();
+        let mut csv_holder = read_csv(TEXT.as_bytes(), &format).unwrap();
+        let edits: [fn(&mut dyn CsvDataHandle) -> CsvResult<()>; 9] = [
+            |data| data.replace_data_at(Index { row: 2, column: 1 }, "Cork".to_string()),
+            |data| data.set_null(Index { row: 1, column: 1 }),
+            |data| data.insert_row(5, vec!["Sligo".to_string(), "3".to_string()]),
+            |data| data.reorder_rows(&[5, 4, 3, 2, 1]),
+            |data| data.delete_row(1),
+            |data| data.replace_row(2, vec!["Ennis".to_string(), "".to_string()]),
+            |data| {
+                data.insert_column(
+                    1,
+                    "id".to_string(),
+                    ["1", "2", "3", "4"].map(String::from).to_vec(),
+                )
+            },
+            |data| data.replace_column(3, ["a", "a", "b", "a"].map(String::from).to_vec()),
+            |data| data.delete_column(2),
+        ];
+        for edit in edits {
+            edit(&mut columnar_csv).unwrap();
+            edit(&mut csv_holder).unwrap();
+            assert_eq!(cells(&columnar_csv), cells(&csv_holder));
+        }
+        assert!(columnar_csv.has_unsaved_changes());
+        assert!(columnar_csv.delete_row(5).is_err());
+        assert!(columnar_csv.replace_column(1, Vec::new()).is_err());
+    }
+}
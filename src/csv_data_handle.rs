@@ -1,19 +1,82 @@
+use crate::sort::{self, SortKey};
 use std::{error::Error, fmt::Display};
 
-#[derive(Debug, Clone, Copy)]
+/// The position of a cell, counting rows and columns from 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Index {
     pub row: usize,
     pub column: usize,
 }
 
+impl Index {
+    /// Parses a spreadsheet-style reference such as `C5`, meaning column 3 of row 5.
+    pub fn from_a1(reference: &str) -> Option<Index> {
+        let digits_start = reference.find(|c: char| c.is_ascii_digit())?;
+        let (letters, digits) = reference.split_at(digits_start);
+        if letters.is_empty() || !letters.chars().all(|c| c.is_ascii_alphabetic()) {
+            return None;
+        }
+        let column = letters.chars().try_fold(0usize, |column, c| {
+            let digit = (c.to_ascii_uppercase() as u8 - b'A' + 1) as usize;
+            column.checked_mul(26)?.checked_add(digit)
+        })?;
+        let row = digits.parse::<usize>().ok()?;
+        Some(Index { row, column })
+    }
+}
+
+/// A column given either by its 1-based position or by its header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ColumnRef {
+    Index(usize),
+    Name(String),
+}
+
+impl ColumnRef {
+    pub fn parse(s: &str) -> Self {
+        match s.parse::<usize>() {
+            Ok(column) => ColumnRef::Index(column),
+            Err(_) => ColumnRef::Name(s.to_string()),
+        }
+    }
+
+    pub fn resolve(&self, data_handle: &(impl CsvDataHandle + ?Sized)) -> CsvResult<usize> {
+        match self {
+            ColumnRef::Index(column) if *column > 0 && *column <= data_handle.column_count() => {
+                Ok(*column)
+            }
+            ColumnRef::Index(column) => Err(CsvError::NoSuchColumn(*column)),
+            ColumnRef::Name(name) => data_handle.column_of_field(name),
+        }
+    }
+}
+
+/// Why a change to a sheet, or a look at part of one, failed.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum CsvError {
     NoSuchRow(usize),
     FailedToReplaceRow(usize),
     NoSuchColumn(usize),
     FailedToReplaceColumn(usize),
+    FailedToInsertRow(usize),
+    FailedToInsertColumn(usize),
+    FailedToReorderRows,
     NoSuchField(String),
     NoSuchIndex(Index),
+    NotANumber(Index),
+    /// An expression could not be worked out for row `row`.
+    EvaluationFailed {
+        row: usize,
+        message: String,
+    },
+    /// The value written to the cell at `index` does not fit its column's schema.
+    SchemaViolation {
+        index: Index,
+        expected: String,
+    },
+    /// A file being read from as it is needed no longer holds what it did when it was opened.
+    FileChanged,
 }
 
 impl Display for CsvError {
@@ -25,10 +88,25 @@ impl Display for CsvError {
             CsvError::FailedToReplaceColumn(column) => {
                 write!(f, "Failed to replace column: {}", column)
             }
+            CsvError::FailedToInsertRow(row) => write!(f, "Failed to insert row: {}", row),
+            CsvError::FailedToInsertColumn(column) => {
+                write!(f, "Failed to insert column: {}", column)
+            }
+            CsvError::FailedToReorderRows => write!(f, "Failed to reorder rows"),
             CsvError::NoSuchField(field) => write!(f, "No such field: {}", field),
             CsvError::NoSuchIndex(index) => {
                 write!(f, "Index ({},{}) does not exist", index.row, index.column)
             }
+            CsvError::NotANumber(index) => {
+                write!(f, "Cell ({},{}) is not a number", index.row, index.column)
+            }
+            CsvError::EvaluationFailed { row, message } => write!(f, "Row {}: {}", row, message),
+            CsvError::SchemaViolation { index, expected } => write!(
+                f,
+                "Cell ({},{}) must be {}",
+                index.row, index.column, expected
+            ),
+            CsvError::FileChanged => write!(f, "The file has changed since it was opened"),
         }
     }
 }
@@ -37,30 +115,151 @@ impl Error for CsvError {}
 
 pub type CsvResult<T> = Result<T, CsvError>;
 
+/// A sheet of cells under a row of headers, whichever way it is stored. Rows and columns are
+/// counted from 1. Every method that is given a row, column or cell that does not exist fails
+/// rather than panicking.
 pub trait CsvDataHandle {
+    /// The text of the cell at `index`.
     fn data_at(&self, index: Index) -> CsvResult<&str>;
+    /// Whether the cell holds no value at all; `data_at` reports such a cell as `""`.
+    fn is_null(&self, index: Index) -> CsvResult<bool>;
+    /// The text of the cell at `index`, or `None` if it holds no value.
+    fn cell(&self, index: Index) -> CsvResult<Option<&str>> {
+        if self.is_null(index)? {
+            Ok(None)
+        } else {
+            self.data_at(index).map(Some)
+        }
+    }
+    /// Every cell of `row`, from the first column to the last.
     fn row(&self, row: usize) -> CsvResult<Vec<&str>>;
+    /// Like `row`, with `None` for the cells that hold no value.
+    fn row_cells(&self, row: usize) -> CsvResult<Vec<Option<&str>>> {
+        self.row(row)?
+            .into_iter()
+            .enumerate()
+            .map(|(i, value)| {
+                let index = Index { row, column: i + 1 };
+                Ok((!self.is_null(index)?).then_some(value))
+            })
+            .collect()
+    }
+    /// Every cell of `column`, from the first row to the last.
     fn column(&self, column: usize) -> CsvResult<Vec<&str>>;
+    /// Like `column`, with `None` for the cells that hold no value.
+    fn column_cells(&self, column: usize) -> CsvResult<Vec<Option<&str>>> {
+        self.column(column)?
+            .into_iter()
+            .enumerate()
+            .map(|(i, value)| {
+                let index = Index { row: i + 1, column };
+                Ok((!self.is_null(index)?).then_some(value))
+            })
+            .collect()
+    }
+    /// The header of each column.
     fn headers(&self) -> Vec<&str>;
+    /// The number of cells in each row, which is the number of columns.
     fn property_count(&self) -> usize;
+    /// The number of rows, not counting the headers.
     fn row_count(&self) -> usize;
     fn column_count(&self) -> usize;
+    /// The first column headed `field`.
     fn column_of_field(&self, field: &str) -> CsvResult<usize>;
 
+    /// Sets the cell at `index` to `new_data`.
     fn replace_data_at(&mut self, index: Index, new_data: String) -> CsvResult<()>;
+    /// Makes the cell at `index` hold no value.
+    fn set_null(&mut self, index: Index) -> CsvResult<()>;
+    /// Sets every cell of `column`; `new_data` must have a cell for each row.
     fn replace_column(&mut self, column: usize, new_data: Vec<String>) -> CsvResult<()>;
+    /// Like `replace_column`, for the column headed `field`.
     fn replace_column_by_field(&mut self, field: &str, new_data: Vec<String>) -> CsvResult<()> {
         let column = self.column_of_field(field)?;
         self.replace_column(column, new_data)?;
         Ok(())
     }
+    /// Sets every cell of `row`; `new_data` must have a cell for each column.
     fn replace_row(&mut self, row: usize, new_data: Vec<String>) -> CsvResult<()>;
 
+    /// Inserts `new_data` so that it becomes row `row`; `row` may be one past the last row.
+    fn insert_row(&mut self, row: usize, new_data: Vec<String>) -> CsvResult<()>;
+    /// Inserts a column so that it becomes column `column`; `column` may be one past the last
+    /// column.
+    fn insert_column(
+        &mut self,
+        column: usize,
+        header: String,
+        new_data: Vec<String>,
+    ) -> CsvResult<()>;
+    /// Adds `new_data` after the last row.
+    fn append_row(&mut self, new_data: Vec<String>) -> CsvResult<()> {
+        self.insert_row(self.row_count() + 1, new_data)
+    }
+    /// Adds a column after the last one.
+    fn append_column(&mut self, header: String, new_data: Vec<String>) -> CsvResult<()> {
+        self.insert_column(self.column_count() + 1, header, new_data)
+    }
+
+    /// The rows as `keys` would order them, given by their current indexes.
+    fn sorted_order(&self, keys: &[SortKey]) -> CsvResult<Vec<usize>> {
+        sort::sorted_order(self, keys)
+    }
+    /// Moves the rows around so that row `i` holds what is now row `order[i - 1]`; `order` must
+    /// list every row exactly once.
+    fn reorder_rows(&mut self, order: &[usize]) -> CsvResult<()>;
+
+    /// Removes `row`, moving the rows after it up by one.
     fn delete_row(&mut self, row: usize) -> CsvResult<()>;
+    /// Removes `column`, moving the columns after it left by one.
     fn delete_column(&mut self, column: usize) -> CsvResult<()>;
+    /// Like `delete_column`, for the column headed `field`.
     fn delete_column_by_field(&mut self, field: &str) -> CsvResult<()> {
         let column = self.column_of_field(field)?;
         self.delete_column(column)?;
         Ok(())
     }
+
+    /// Whether rows are read from a file as they are asked for, so that reading every one of
+    /// them is too slow to do just to work something out about the sheet.
+    fn is_file_backed(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Every cell of `data_handle`, headers first.
+    pub(crate) fn cells(data_handle: &impl CsvDataHandle) -> Vec<Vec<Option<String>>> {
+        let headers = data_handle.headers().into_iter().map(Some).collect();
+        let rows = (1..data_handle.row_count() + 1).map(|row| data_handle.row_cells(row).unwrap());
+        std::iter::once(headers)
+            .chain(rows)
+            .map(|cells| {
+                cells
+                    .into_iter()
+                    .map(|cell| cell.map(String::from))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_index_from_a1() {
+        assert_eq!(Index::from_a1("C5"), Some(Index { row: 5, column: 3 }));
+        assert_eq!(Index::from_a1("a1"), Some(Index { row: 1, column: 1 }));
+        assert_eq!(
+            Index::from_a1("AA10"),
+            Some(Index {
+                row: 10,
+                column: 27
+            })
+        );
+        assert_eq!(Index::from_a1("5"), None);
+        assert_eq!(Index::from_a1("C"), None);
+        assert_eq!(Index::from_a1("C5x"), None);
+        assert_eq!(Index::from_a1("Ä5"), None);
+    }
 }
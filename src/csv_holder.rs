@@ -1,21 +1,71 @@
 use crate::csv_data_handle::*;
 
+/// A sheet kept in memory row by row, as `read_csv` loads it. The constructors refuse rows that
+/// do not have a cell for each header, and the fields are only changed through
+/// `CsvDataHandle`, so that the rows always fit the headers.
+#[derive(Debug, Clone)]
 pub struct CsvHolder {
-    pub headers: Vec<String>,
-    pub data: Vec<Vec<String>>,
+    headers: Vec<String>,
+    data: Vec<Vec<String>>,
+    /// Marks the cells of `data` that hold no value at all, as opposed to an empty string.
+    nulls: Vec<Vec<bool>>,
+    /// Whether the data has changed since it was loaded or last saved.
+    modified: bool,
 }
 
+impl PartialEq for CsvHolder {
+    fn eq(&self, other: &Self) -> bool {
+        self.headers == other.headers && self.data == other.data && self.nulls == other.nulls
+    }
+}
+
+impl Eq for CsvHolder {}
+
 impl CsvHolder {
     pub fn new(headers: Vec<String>, data: Vec<Vec<String>>) -> Option<Self> {
+        let nulls = data.iter().map(|row| vec![false; row.len()]).collect();
+        Self::with_nulls(headers, data, nulls)
+    }
+
+    /// Builds a sheet from the cells of each row, with `None` for a null cell.
+    pub fn from_cells(headers: Vec<String>, rows: Vec<Vec<Option<String>>>) -> Option<Self> {
+        let nulls = rows
+            .iter()
+            .map(|row| row.iter().map(Option::is_none).collect())
+            .collect();
+        let data = rows
+            .into_iter()
+            .map(|row| row.into_iter().map(Option::unwrap_or_default).collect())
+            .collect();
+        Self::with_nulls(headers, data, nulls)
+    }
+
+    pub fn with_nulls(
+        headers: Vec<String>,
+        data: Vec<Vec<String>>,
+        nulls: Vec<Vec<bool>>,
+    ) -> Option<Self> {
         let header_count = headers.len();
-        let rows_of_correct_size = data.iter().all(|row| row.len() == header_count);
+        let rows_of_correct_size = data.iter().all(|row| row.len() == header_count)
+            && nulls.len() == data.len()
+            && nulls.iter().all(|row| row.len() == header_count);
         if rows_of_correct_size {
-            Some(Self { headers, data })
+            Some(Self {
+                headers,
+                data,
+                nulls,
+                modified: false,
+            })
         } else {
             None
         }
     }
 
+    /// The text of every cell, row by row; a null cell holds an empty string.
+    pub fn rows(&self) -> &[Vec<String>] {
+        &self.data
+    }
+
     pub fn row_exists(&self, row: usize) -> bool {
         row > 0 && row <= self.data.len()
     }
@@ -28,6 +78,34 @@ impl CsvHolder {
         let Index { row, column } = index;
         self.column_exists(column) && self.row_exists(row)
     }
+
+    pub fn has_unsaved_changes(&self) -> bool {
+        self.modified
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.modified = false;
+    }
+}
+
+#[cfg(test)]
+impl CsvHolder {
+    /// A sheet to test with, from the text of each cell; the cells at `nulls` are then made null.
+    pub(crate) fn from_strs(headers: &[&str], rows: &[&[&str]], nulls: &[Index]) -> Self {
+        let mut csv_holder = Self::new(
+            headers.iter().map(|header| header.to_string()).collect(),
+            rows.iter()
+                .map(|row| row.iter().map(|cell| cell.to_string()).collect())
+                .collect(),
+        )
+        .expect("every row fits the headers");
+        for &index in nulls {
+            csv_holder
+                .set_null(index)
+                .expect("every null is in the sheet");
+        }
+        csv_holder
+    }
 }
 
 impl CsvDataHandle for CsvHolder {
@@ -39,6 +117,14 @@ impl CsvDataHandle for CsvHolder {
         Ok(self.data.index_one_based(row).index_one_based(column))
     }
 
+    fn is_null(&self, index: Index) -> CsvResult<bool> {
+        if !self.index_exists(index) {
+            return Err(CsvError::NoSuchIndex(index));
+        }
+        let Index { row, column } = index;
+        Ok(*self.nulls.index_one_based(row).index_one_based(column))
+    }
+
     fn row(&self, row: usize) -> CsvResult<Vec<&str>> {
         if !self.row_exists(row) {
             return Err(CsvError::NoSuchRow(row));
@@ -95,6 +181,28 @@ impl CsvDataHandle for CsvHolder {
             .data
             .index_one_based_mut(row)
             .index_one_based_mut(column) = new_data;
+        *self
+            .nulls
+            .index_one_based_mut(row)
+            .index_one_based_mut(column) = false;
+        self.modified = true;
+        Ok(())
+    }
+
+    fn set_null(&mut self, index: Index) -> CsvResult<()> {
+        if !self.index_exists(index) {
+            return Err(CsvError::NoSuchIndex(index));
+        }
+        let Index { row, column } = index;
+        self.data
+            .index_one_based_mut(row)
+            .index_one_based_mut(column)
+            .clear();
+        *self
+            .nulls
+            .index_one_based_mut(row)
+            .index_one_based_mut(column) = true;
+        self.modified = true;
         Ok(())
     }
 
@@ -108,6 +216,10 @@ impl CsvDataHandle for CsvHolder {
         for (row, new_value) in self.data.iter_mut().zip(new_data) {
             *row.index_one_based_mut(column) = new_value;
         }
+        for row in &mut self.nulls {
+            *row.index_one_based_mut(column) = false;
+        }
+        self.modified = true;
         Ok(())
     }
 
@@ -119,6 +231,69 @@ impl CsvDataHandle for CsvHolder {
             return Err(CsvError::FailedToReplaceRow(row));
         }
         *self.data.index_one_based_mut(row) = new_data;
+        *self.nulls.index_one_based_mut(row) = vec![false; self.property_count()];
+        self.modified = true;
+        Ok(())
+    }
+
+    fn insert_row(&mut self, row: usize, new_data: Vec<String>) -> CsvResult<()> {
+        if row == 0 || row > self.row_count() + 1 {
+            return Err(CsvError::NoSuchRow(row));
+        }
+        if new_data.len() != self.property_count() {
+            return Err(CsvError::FailedToInsertRow(row));
+        }
+        self.data.insert(row - 1, new_data);
+        self.nulls
+            .insert(row - 1, vec![false; self.property_count()]);
+        self.modified = true;
+        Ok(())
+    }
+
+    fn insert_column(
+        &mut self,
+        column: usize,
+        header: String,
+        new_data: Vec<String>,
+    ) -> CsvResult<()> {
+        if column == 0 || column > self.column_count() + 1 {
+            return Err(CsvError::NoSuchColumn(column));
+        }
+        if new_data.len() != self.row_count() {
+            return Err(CsvError::FailedToInsertColumn(column));
+        }
+        self.headers.insert(column - 1, header);
+        for (row, new_value) in self.data.iter_mut().zip(new_data) {
+            row.insert(column - 1, new_value);
+        }
+        for row in &mut self.nulls {
+            row.insert(column - 1, false);
+        }
+        self.modified = true;
+        Ok(())
+    }
+
+    fn reorder_rows(&mut self, order: &[usize]) -> CsvResult<()> {
+        let mut seen = vec![false; self.row_count()];
+        for &row in order {
+            if !self.row_exists(row) || std::mem::replace(&mut seen[row - 1], true) {
+                return Err(CsvError::FailedToReorderRows);
+            }
+        }
+        if order.len() != self.row_count() {
+            return Err(CsvError::FailedToReorderRows);
+        }
+        let mut data = std::mem::take(&mut self.data);
+        let mut nulls = std::mem::take(&mut self.nulls);
+        self.data = order
+            .iter()
+            .map(|&row| std::mem::take(&mut data[row - 1]))
+            .collect();
+        self.nulls = order
+            .iter()
+            .map(|&row| std::mem::take(&mut nulls[row - 1]))
+            .collect();
+        self.modified = true;
         Ok(())
     }
 
@@ -127,6 +302,8 @@ impl CsvDataHandle for CsvHolder {
             return Err(CsvError::NoSuchRow(row));
         }
         self.data.remove(row - 1);
+        self.nulls.remove(row - 1);
+        self.modified = true;
         Ok(())
     }
 
@@ -138,6 +315,10 @@ impl CsvDataHandle for CsvHolder {
         for row in &mut self.data {
             row.remove(column - 1);
         }
+        for row in &mut self.nulls {
+            row.remove(column - 1);
+        }
+        self.modified = true;
         Ok(())
     }
 }
@@ -208,14 +389,47 @@ mod tests {
         assert!(csv_holder.is_none());
     }
 
+    #[test]
+    fn test_nulls_are_distinct_from_empty_strings() {
+        let headers = vec!["a".to_string(), "b".to_string()];
+        let data = vec![vec!["".to_string(), "".to_string()]];
+        let nulls = vec![vec![true, false]];
+        let mut csv_holder = CsvHolder::with_nulls(headers, data, nulls).unwrap();
+        assert_eq!(csv_holder.cell(Index { row: 1, column: 1 }).unwrap(), None);
+        assert_eq!(
+            csv_holder.cell(Index { row: 1, column: 2 }).unwrap(),
+            Some("")
+        );
+        csv_holder
+            .replace_data_at(Index { row: 1, column: 1 }, "".to_string())
+            .unwrap();
+        assert!(!csv_holder.is_null(Index { row: 1, column: 1 }).unwrap());
+        assert!(csv_holder.is_null(Index { row: 2, column: 1 }).is_err());
+
+        let headers = vec!["a".to_string(), "b".to_string()];
+        let rows = vec![vec![None, Some("x".to_string())]];
+        let csv_holder = CsvHolder::from_cells(headers.clone(), rows).unwrap();
+        assert_eq!(csv_holder.row_cells(1).unwrap(), [None, Some("x")]);
+        assert_eq!(csv_holder.rows(), [vec!["", "x"]]);
+        assert!(CsvHolder::from_cells(headers, vec![vec![None]]).is_none());
+    }
+
+    #[test]
+    fn test_unsaved_changes() {
+        let mut csv_holder = CsvHolder::from_strs(&["a", "b"], &[&["1", "2"], &["3", "4"]], &[]);
+        assert!(!csv_holder.has_unsaved_changes());
+        assert!(csv_holder.delete_row(3).is_err());
+        assert!(!csv_holder.has_unsaved_changes());
+        csv_holder.delete_row(1).unwrap();
+        assert!(csv_holder.has_unsaved_changes());
+        csv_holder.mark_saved();
+        assert!(!csv_holder.has_unsaved_changes());
+    }
+
     #[test]
     fn test_row_exists() {
-        let headers = vec!["a".to_string(), "b".to_string(), "c".to_string()];
-        let data = vec![
-            vec!["1".to_string(), "2".to_string(), "3".to_string()],
-            vec!["4".to_string(), "5".to_string(), "6".to_string()],
-        ];
-        let csv_holder = CsvHolder::new(headers, data).unwrap();
+        let csv_holder =
+            CsvHolder::from_strs(&["a", "b", "c"], &[&["1", "2", "3"], &["4", "5", "6"]], &[]);
         assert!(csv_holder.row_exists(1));
         assert!(csv_holder.row_exists(2));
         assert!(!csv_holder.row_exists(3));
@@ -223,12 +437,8 @@ mod tests {
 
     #[test]
     fn test_get_row() {
-        let headers = vec!["a".to_string(), "b".to_string(), "c".to_string()];
-        let data = vec![
-            vec!["1".to_string(), "2".to_string(), "3".to_string()],
-            vec!["4".to_string(), "5".to_string(), "6".to_string()],
-        ];
-        let csv_holder = CsvHolder::new(headers, data).unwrap();
+        let csv_holder =
+            CsvHolder::from_strs(&["a", "b", "c"], &[&["1", "2", "3"], &["4", "5", "6"]], &[]);
         assert_eq!(
             csv_holder.row(1).unwrap(),
             vec!["1".to_string(), "2".to_string(), "3".to_string()]
@@ -241,24 +451,16 @@ mod tests {
 
     #[test]
     fn test_get_row_with_invalid_row() {
-        let headers = vec!["a".to_string(), "b".to_string(), "c".to_string()];
-        let data = vec![
-            vec!["1".to_string(), "2".to_string(), "3".to_string()],
-            vec!["4".to_string(), "5".to_string(), "6".to_string()],
-        ];
-        let csv_holder = CsvHolder::new(headers, data).unwrap();
+        let csv_holder =
+            CsvHolder::from_strs(&["a", "b", "c"], &[&["1", "2", "3"], &["4", "5", "6"]], &[]);
         assert!(csv_holder.row(0).is_err());
         assert!(csv_holder.row(3).is_err());
     }
 
     #[test]
     fn test_get_column() {
-        let headers = vec!["a".to_string(), "b".to_string(), "c".to_string()];
-        let data = vec![
-            vec!["1".to_string(), "2".to_string(), "3".to_string()],
-            vec!["4".to_string(), "5".to_string(), "6".to_string()],
-        ];
-        let csv_holder = CsvHolder::new(headers, data).unwrap();
+        let csv_holder =
+            CsvHolder::from_strs(&["a", "b", "c"], &[&["1", "2", "3"], &["4", "5", "6"]], &[]);
         assert_eq!(
             csv_holder.column(1).unwrap(),
             vec!["1".to_string(), "4".to_string()]
@@ -275,71 +477,132 @@ mod tests {
 
     #[test]
     fn test_get_column_with_invalid_column() {
-        let headers = vec!["a".to_string(), "b".to_string(), "c".to_string()];
-        let data = vec![
-            vec!["1".to_string(), "2".to_string(), "3".to_string()],
-            vec!["4".to_string(), "5".to_string(), "6".to_string()],
-        ];
-        let csv_holder = CsvHolder::new(headers, data).unwrap();
+        let csv_holder =
+            CsvHolder::from_strs(&["a", "b", "c"], &[&["1", "2", "3"], &["4", "5", "6"]], &[]);
         assert!(csv_holder.column(0).is_err());
         assert!(csv_holder.column(4).is_err());
     }
 
     #[test]
     fn test_delete_row() {
-        let headers = vec!["a".to_string(), "b".to_string(), "c".to_string()];
-        let mut data = vec![
-            vec!["1".to_string(), "2".to_string(), "3".to_string()],
-            vec!["4".to_string(), "5".to_string(), "6".to_string()],
-            vec!["7".to_string(), "8".to_string(), "9".to_string()],
-        ];
-        let mut csv_holder = CsvHolder::new(headers, data.clone()).unwrap();
+        let mut csv_holder = CsvHolder::from_strs(
+            &["a", "b", "c"],
+            &[&["1", "2", "3"], &["4", "5", "6"], &["7", "8", "9"]],
+            &[],
+        );
         csv_holder.delete_row(2).unwrap();
-        data.remove(1);
-        assert_eq!(csv_holder.data, data);
+        assert_eq!(
+            csv_holder.data,
+            vec![vec!["1", "2", "3"], vec!["7", "8", "9"]]
+        );
     }
 
     #[test]
     fn test_delete_row_with_invalid_row() {
-        let headers = vec!["a".to_string(), "b".to_string(), "c".to_string()];
-        let data = vec![
-            vec!["1".to_string(), "2".to_string(), "3".to_string()],
-            vec!["4".to_string(), "5".to_string(), "6".to_string()],
-            vec!["7".to_string(), "8".to_string(), "9".to_string()],
-        ];
-        let mut csv_holder = CsvHolder::new(headers, data.clone()).unwrap();
+        let mut csv_holder = CsvHolder::from_strs(
+            &["a", "b", "c"],
+            &[&["1", "2", "3"], &["4", "5", "6"], &["7", "8", "9"]],
+            &[],
+        );
+        let unchanged = csv_holder.clone();
         assert!(csv_holder.delete_row(0).is_err());
         assert!(csv_holder.delete_row(4).is_err());
-        assert_eq!(csv_holder.data, data);
+        assert_eq!(csv_holder, unchanged);
     }
 
     #[test]
     fn test_delete_column() {
-        let headers = vec!["a".to_string(), "b".to_string(), "c".to_string()];
-        let mut data = vec![
-            vec!["1".to_string(), "2".to_string(), "3".to_string()],
-            vec!["4".to_string(), "5".to_string(), "6".to_string()],
-            vec!["7".to_string(), "8".to_string(), "9".to_string()],
-        ];
-        let mut csv_holder = CsvHolder::new(headers, data.clone()).unwrap();
+        let mut csv_holder = CsvHolder::from_strs(
+            &["a", "b", "c"],
+            &[&["1", "2", "3"], &["4", "5", "6"], &["7", "8", "9"]],
+            &[],
+        );
         csv_holder.delete_column(2).unwrap();
-        data.iter_mut().for_each(|row| {
-            row.remove(1);
-        });
-        assert_eq!(csv_holder.data, data);
+        assert_eq!(
+            csv_holder.data,
+            vec![vec!["1", "3"], vec!["4", "6"], vec!["7", "9"]]
+        );
+    }
+
+    #[test]
+    fn test_insert_row() {
+        let mut csv_holder = CsvHolder::from_strs(&["a", "b"], &[&["1", "2"]], &[]);
+        csv_holder
+            .insert_row(1, vec!["3".to_string(), "4".to_string()])
+            .unwrap();
+        csv_holder
+            .insert_row(3, vec!["5".to_string(), "6".to_string()])
+            .unwrap();
+        assert_eq!(
+            csv_holder.data,
+            vec![vec!["3", "4"], vec!["1", "2"], vec!["5", "6"]]
+        );
+        assert!(csv_holder
+            .insert_row(0, vec!["7".to_string(), "8".to_string()])
+            .is_err());
+        assert!(csv_holder
+            .insert_row(5, vec!["7".to_string(), "8".to_string()])
+            .is_err());
+        assert!(csv_holder.insert_row(1, vec!["7".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_insert_column() {
+        let mut csv_holder = CsvHolder::from_strs(&["a", "b"], &[&["1", "2"], &["3", "4"]], &[]);
+        csv_holder
+            .insert_column(2, "c".to_string(), vec!["x".to_string(), "y".to_string()])
+            .unwrap();
+        assert_eq!(csv_holder.headers, vec!["a", "c", "b"]);
+        assert_eq!(
+            csv_holder.data,
+            vec![vec!["1", "x", "2"], vec!["3", "y", "4"]]
+        );
+        assert!(csv_holder
+            .insert_column(5, "d".to_string(), vec!["x".to_string(), "y".to_string()])
+            .is_err());
+        assert!(csv_holder
+            .insert_column(1, "d".to_string(), vec!["x".to_string()])
+            .is_err());
+    }
+
+    #[test]
+    fn test_append_row_and_column() {
+        let mut csv_holder = CsvHolder::from_strs(&["a"], &[&["1"]], &[]);
+        csv_holder.append_row(vec!["2".to_string()]).unwrap();
+        csv_holder
+            .append_column("b".to_string(), vec!["x".to_string(), "y".to_string()])
+            .unwrap();
+        assert_eq!(csv_holder.headers, vec!["a", "b"]);
+        assert_eq!(csv_holder.data, vec![vec!["1", "x"], vec!["2", "y"]]);
+        assert!(csv_holder.append_row(vec!["3".to_string()]).is_err());
     }
 
     #[test]
     fn test_delete_column_with_invalid_column() {
-        let headers = vec!["a".to_string(), "b".to_string(), "c".to_string()];
-        let data = vec![
-            vec!["1".to_string(), "2".to_string(), "3".to_string()],
-            vec!["4".to_string(), "5".to_string(), "6".to_string()],
-            vec!["7".to_string(), "8".to_string(), "9".to_string()],
-        ];
-        let mut csv_holder = CsvHolder::new(headers, data.clone()).unwrap();
+        let mut csv_holder = CsvHolder::from_strs(
+            &["a", "b", "c"],
+            &[&["1", "2", "3"], &["4", "5", "6"], &["7", "8", "9"]],
+            &[],
+        );
+        let unchanged = csv_holder.clone();
         assert!(csv_holder.delete_column(0).is_err());
         assert!(csv_holder.delete_column(4).is_err());
-        assert_eq!(csv_holder.data, data);
+        assert_eq!(csv_holder, unchanged);
+    }
+
+    #[test]
+    fn test_reorder_rows() {
+        let mut csv_holder = CsvHolder::from_strs(
+            &["a"],
+            &[&["1"], &["2"], &["3"]],
+            &[Index { row: 3, column: 1 }],
+        );
+        csv_holder.reorder_rows(&[3, 1, 2]).unwrap();
+        assert_eq!(csv_holder.data, vec![vec![""], vec!["1"], vec!["2"]]);
+        assert!(csv_holder.is_null(Index { row: 1, column: 1 }).unwrap());
+        assert!(csv_holder.reorder_rows(&[1, 1, 2]).is_err());
+        assert!(csv_holder.reorder_rows(&[1, 2]).is_err());
+        assert!(csv_holder.reorder_rows(&[1, 2, 4]).is_err());
+        assert_eq!(csv_holder.data, vec![vec![""], vec!["1"], vec!["2"]]);
     }
 }
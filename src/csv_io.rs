@@ -1,27 +1,99 @@
 use crate::csv_data_handle::*;
 use crate::csv_holder::CsvHolder;
+pub use crate::csv_tokenizer::ParseMode;
+use crate::csv_tokenizer::{Field, RecordReader};
+use crate::dialect::{Dialect, EscapeStyle};
+use crate::parse_error::ParseError;
 use std::{
     error::Error,
     fmt::Display,
-    io::{BufRead, Write},
+    fs::File,
+    io::{BufRead, BufWriter, Write},
+    path::Path,
 };
 
+/// Why a file could not be read or written, or a request could not be carried out.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum CsvIoError {
-    InvalidCsv,
+    /// The text read could not be made into a sheet.
+    Parse(ParseError),
     IoError(std::io::Error),
+    UnencodableCharacter(char, Encoding),
+    NoOutputFile,
+    NoResult,
+    /// A `group_by` or `join` result was to be written over the file the sheet was loaded from.
+    ResultNeedsOwnFile,
+    NoOriginalSheet,
+    NoSuchBuffer(String),
+    BufferExists(String),
+    UnsavedBuffer(String),
+    LastBuffer,
+    /// The request switches sheets or buffers, and was given only a sheet to act on.
+    CannotSwitchSheets,
+    /// Rows with `found` cells cannot go in a sheet with `expected` columns.
+    MismatchedColumns {
+        expected: usize,
+        found: usize,
+    },
+    /// A problem with the sheet, shown as it is since it says which cells are involved.
+    Data(CsvError),
 }
 
 impl Display for CsvIoError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            CsvIoError::InvalidCsv => write!(f, "invalid csv"),
+            CsvIoError::Parse(e) => write!(f, "{}", e),
             CsvIoError::IoError(e) => write!(f, "io error: {}", e),
+            CsvIoError::UnencodableCharacter(c, encoding) => {
+                write!(f, "character {:?} cannot be written as {}", c, encoding)
+            }
+            CsvIoError::NoOutputFile => write!(f, "no output file; use write_to_file instead"),
+            CsvIoError::NoResult => write!(f, "no group_by or join result to use"),
+            CsvIoError::ResultNeedsOwnFile => write!(
+                f,
+                "a group_by or join result has to be saved to a file of its own with write_to_file"
+            ),
+            CsvIoError::NoOriginalSheet => write!(f, "already working on the original sheet"),
+            CsvIoError::NoSuchBuffer(name) => write!(f, "no buffer called {}", name),
+            CsvIoError::BufferExists(name) => write!(f, "a buffer called {} is already open", name),
+            CsvIoError::UnsavedBuffer(name) => write!(
+                f,
+                "{} has unsaved changes; save it or close it with --discard",
+                name
+            ),
+            CsvIoError::LastBuffer => write!(f, "cannot close the only open buffer"),
+            CsvIoError::CannotSwitchSheets => write!(f, "cannot switch to another sheet here"),
+            CsvIoError::MismatchedColumns { expected, found } => write!(
+                f,
+                "rows with {} cells do not fit a sheet with {} columns",
+                found, expected
+            ),
+            CsvIoError::Data(e) => write!(f, "{}", e),
         }
     }
 }
 
-impl Error for CsvIoError {}
+impl CsvIoError {
+    /// For an error found in some text, the line it was found on with a caret under where.
+    pub fn snippet(&self) -> Option<String> {
+        match self {
+            CsvIoError::Parse(e) => Some(e.snippet()),
+            _ => None,
+        }
+    }
+}
+
+impl Error for CsvIoError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CsvIoError::Parse(e) => Some(e),
+            CsvIoError::IoError(e) => Some(e),
+            CsvIoError::Data(e) => Some(e),
+            _ => None,
+        }
+    }
+}
 
 impl From<std::io::Error> for CsvIoError {
     fn from(e: std::io::Error) -> Self {
@@ -30,74 +102,583 @@ impl From<std::io::Error> for CsvIoError {
 }
 
 impl From<CsvError> for CsvIoError {
-    fn from(_e: CsvError) -> Self {
-        CsvIoError::InvalidCsv
-    }
-}
-
-pub fn read_csv(reader: impl BufRead) -> Result<CsvHolder, CsvIoError> {
-    let mut lines = reader.lines();
-    let header_line = lines.next().ok_or(CsvIoError::InvalidCsv)??;
-    let rows = lines
-        .map(|line| -> std::io::Result<Vec<String>> {
-            let strs = line?
-                .split(',')
-                .map(|s| s.to_string())
-                .filter(|s| !s.is_empty())
-                .collect();
-            Ok(strs)
-        })
-        .collect::<std::io::Result<Vec<_>>>()?;
-    let rows = rows
-        .into_iter()
-        .filter(|r| !r.is_empty())
-        .collect::<Vec<_>>();
-    CsvHolder::new(
-        header_line.split(',').map(|s| s.to_string()).collect(),
-        rows,
-    )
-    .ok_or(CsvIoError::InvalidCsv)
+    fn from(e: CsvError) -> Self {
+        CsvIoError::Data(e)
+    }
+}
+
+/// The character encoding of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    Latin1,
+}
+
+impl Encoding {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" => Some(Encoding::Utf8),
+            "latin1" | "latin-1" | "iso-8859-1" => Some(Encoding::Latin1),
+            _ => None,
+        }
+    }
+
+    pub fn encode(self, text: &str) -> Result<Vec<u8>, CsvIoError> {
+        match self {
+            Encoding::Utf8 => Ok(text.as_bytes().to_vec()),
+            Encoding::Latin1 => text
+                .chars()
+                .map(|c| u8::try_from(c).map_err(|_| CsvIoError::UnencodableCharacter(c, self)))
+                .collect(),
+        }
+    }
+}
+
+impl Display for Encoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Encoding::Utf8 => write!(f, "utf-8"),
+            Encoding::Latin1 => write!(f, "latin1"),
+        }
+    }
+}
+
+/// How a CSV file is laid out on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CsvFormat {
+    pub dialect: Dialect,
+    pub has_header: bool,
+    pub parse_mode: ParseMode,
+    pub encoding: Encoding,
+}
+
+impl Default for CsvFormat {
+    fn default() -> Self {
+        Self {
+            dialect: Dialect::default(),
+            has_header: true,
+            parse_mode: ParseMode::Rfc4180,
+            encoding: Encoding::Utf8,
+        }
+    }
+}
+
+/// Reads a whole file laid out as `format` says into memory. The first line holds the headers
+/// unless `format.has_header` is unset, in which case columns are named `column_1`, `column_2`,
+/// and so on. Blank lines are skipped, except in a sheet of one column where they hold a null
+/// cell, and every row must have as many cells as there are headers.
+pub fn read_csv(reader: impl BufRead, format: &CsvFormat) -> Result<CsvHolder, CsvIoError> {
+    let mut rows = Vec::new();
+    let headers = read_records(reader, format, |cells| {
+        rows.push(cells);
+        Ok(())
+    })?;
+    Ok(CsvHolder::from_cells(headers, rows).expect("every row fits the headers"))
+}
+
+/// Reads the rows of a file one at a time, handing each row's cells to `on_row` with `None`
+/// for a null cell, and returns the headers. Every row has a cell for each header.
+pub fn read_records(
+    mut reader: impl BufRead,
+    format: &CsvFormat,
+    on_row: impl FnMut(Vec<Option<String>>) -> Result<(), CsvIoError>,
+) -> Result<Vec<String>, CsvIoError> {
+    match format.encoding {
+        Encoding::Utf8 => read_text_records(reader, format, on_row),
+        Encoding::Latin1 => {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes)?;
+            let text = bytes.into_iter().map(char::from).collect::<String>();
+            read_text_records(text.as_bytes(), format, on_row).map_err(|e| match e {
+                // Every character was a single byte in the file.
+                CsvIoError::Parse(mut e) => {
+                    let offset = &mut e.position.byte_offset;
+                    *offset = text[..*offset].chars().count();
+                    CsvIoError::Parse(e)
+                }
+                e => e,
+            })
+        }
+    }
 }
 
+fn read_text_records(
+    reader: impl BufRead,
+    format: &CsvFormat,
+    mut on_row: impl FnMut(Vec<Option<String>>) -> Result<(), CsvIoError>,
+) -> Result<Vec<String>, CsvIoError> {
+    let mut records = RecordReader::new(reader, format.parse_mode, format.dialect);
+    let header_line = if format.has_header {
+        records
+            .next_record()?
+            .ok_or_else(|| records.empty_error())?
+    } else {
+        Vec::new()
+    };
+    let mut first_row_len = format.has_header.then_some(header_line.len());
+    while let Some(record) = records.next_record()? {
+        if is_blank_line(&record) && first_row_len != Some(1) {
+            continue;
+        }
+        let expected = *first_row_len.get_or_insert(record.len());
+        if record.len() != expected {
+            return Err(records.field_count_error(expected, record.len()));
+        }
+        let cells = record.into_iter().map(|field| {
+            let is_null = is_null_field(&field);
+            (!is_null).then_some(field.text)
+        });
+        on_row(cells.collect())?;
+    }
+    if format.has_header {
+        return Ok(header_line.into_iter().map(|field| field.text).collect());
+    }
+    let column_count = first_row_len.ok_or_else(|| records.empty_error())?;
+    Ok((1..column_count + 1)
+        .map(|column| format!("column_{}", column))
+        .collect())
+}
+
+/// Splits one line of text into cells the way `read_csv` would split a row of a file.
+pub fn split_record(text: &str, format: &CsvFormat) -> Result<Vec<String>, ParseError> {
+    let mut records = RecordReader::new(text.as_bytes(), format.parse_mode, format.dialect);
+    match records.next_record() {
+        Ok(record) => Ok(record
+            .unwrap_or_default()
+            .into_iter()
+            .map(|field| field.text)
+            .collect()),
+        Err(CsvIoError::Parse(e)) => Err(e),
+        Err(e) => unreachable!("text in memory can only fail to parse: {}", e),
+    }
+}
+
+fn is_null_field(field: &Field) -> bool {
+    !field.quoted && field.text.is_empty()
+}
+
+fn is_blank_line(record: &[Field]) -> bool {
+    matches!(record, [field] if is_null_field(field))
+}
+
+/// Which cells are quoted when a sheet is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QuotePolicy {
+    /// Quote only the cells that would not otherwise read back unchanged.
+    #[default]
+    Minimal,
+    Always,
+    NonNumeric,
+    /// Never quote; cells containing commas, quotes or line breaks will not round-trip.
+    Never,
+}
+
+impl QuotePolicy {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "minimal" => Some(QuotePolicy::Minimal),
+            "always" => Some(QuotePolicy::Always),
+            "non_numeric" => Some(QuotePolicy::NonNumeric),
+            "never" => Some(QuotePolicy::Never),
+            _ => None,
+        }
+    }
+}
+
+/// Writes the sheet as UTF-8, headers first unless `format.has_header` is unset. A null cell is
+/// written as an empty unquoted field and an empty string as `""`, so each reads back as it was.
 pub fn write_csv(
     writer: &mut impl Write,
     data_provider: &impl CsvDataHandle,
+    format: &CsvFormat,
+    policy: QuotePolicy,
 ) -> Result<(), CsvIoError> {
-    write_line(writer, &data_provider.headers())?;
+    if format.has_header {
+        write_headers_line(writer, data_provider, format, policy)?;
+    }
     let row_count = data_provider.row_count();
     for row_index in 1..row_count + 1 {
-        let row = data_provider.row(row_index)?;
-        write_line(writer, &row)?;
+        let row = data_provider.row_cells(row_index)?;
+        write_line(writer, &row, format, policy)?;
     }
     Ok(())
 }
 
+/// Like `write_csv`, but in the encoding given by `format` rather than always UTF-8.
+pub fn write_csv_encoded(
+    writer: &mut impl Write,
+    data_provider: &impl CsvDataHandle,
+    format: &CsvFormat,
+    policy: QuotePolicy,
+) -> Result<(), CsvIoError> {
+    if format.encoding == Encoding::Utf8 {
+        let mut writer = BufWriter::new(writer);
+        write_csv(&mut writer, data_provider, format, policy)?;
+        writer.flush()?;
+        return Ok(());
+    }
+    let mut written = Vec::new();
+    write_csv(&mut written, data_provider, format, policy)?;
+    let text = String::from_utf8_lossy(&written);
+    writer.write_all(&format.encoding.encode(&text)?)?;
+    Ok(())
+}
+
+/// Writes the sheet to the file at `path` with `write_csv_encoded`. The sheet is written to a
+/// new file that then takes the place of the old one, which is left as it was if writing fails
+/// and may be the very file a sheet is reading from.
+pub fn write_csv_file(
+    path: &Path,
+    data_provider: &impl CsvDataHandle,
+    format: &CsvFormat,
+    policy: QuotePolicy,
+) -> Result<(), CsvIoError> {
+    let file_name = path.file_name().ok_or(CsvIoError::NoOutputFile)?;
+    let temporary_path = path.with_file_name(format!(".{}.tmp", file_name.to_string_lossy()));
+    let written = File::create(&temporary_path)
+        .map_err(CsvIoError::from)
+        .and_then(|mut file| write_csv_encoded(&mut file, data_provider, format, policy))
+        .and_then(|()| Ok(std::fs::rename(&temporary_path, path)?));
+    if written.is_err() {
+        let _ = std::fs::remove_file(&temporary_path);
+    }
+    written
+}
+
 pub fn write_headers(
     writer: &mut impl Write,
     data_provider: &impl CsvDataHandle,
+    format: &CsvFormat,
 ) -> Result<(), CsvIoError> {
-    write_line(writer, &data_provider.headers())?;
+    write_headers_line(writer, data_provider, format, QuotePolicy::Minimal)?;
     Ok(())
 }
 
 pub fn write_csv_row_range(
     writer: &mut impl Write,
     data_provider: &impl CsvDataHandle,
+    format: &CsvFormat,
     first_row: usize,
     last_row: usize,
 ) -> Result<(), CsvIoError> {
-    for row_index in first_row..last_row + 1 {
-        let row = data_provider.row(row_index)?;
-        write_line(writer, &row)?;
+    write_csv_rows(writer, data_provider, format, first_row..=last_row)
+}
+
+pub fn write_csv_rows(
+    writer: &mut impl Write,
+    data_provider: &impl CsvDataHandle,
+    format: &CsvFormat,
+    rows: impl IntoIterator<Item = usize>,
+) -> Result<(), CsvIoError> {
+    for row_index in rows {
+        let row = data_provider.row_cells(row_index)?;
+        write_line(writer, &row, format, QuotePolicy::Minimal)?;
     }
     Ok(())
 }
 
-fn write_line(writer: &mut impl Write, line: &[&str]) -> std::io::Result<()> {
+fn write_headers_line(
+    writer: &mut impl Write,
+    data_provider: &impl CsvDataHandle,
+    format: &CsvFormat,
+    policy: QuotePolicy,
+) -> std::io::Result<()> {
+    let headers = data_provider
+        .headers()
+        .into_iter()
+        .map(Some)
+        .collect::<Vec<_>>();
+    write_line(writer, &headers, format, policy)
+}
+
+fn write_line(
+    writer: &mut impl Write,
+    line: &[Option<&str>],
+    format: &CsvFormat,
+    policy: QuotePolicy,
+) -> std::io::Result<()> {
+    let dialect = &format.dialect;
     let line_string = line
         .iter()
-        .map(|s| s.to_string())
+        .map(|cell| match cell {
+            Some(cell) => quote_cell(cell, dialect, policy),
+            None => String::new(),
+        })
         .collect::<Vec<_>>()
-        .join(",");
-    writeln!(writer, "{}", line_string)
+        .join(&dialect.delimiter.to_string());
+    write!(
+        writer,
+        "{}{}",
+        line_string,
+        dialect.line_terminator.as_str()
+    )
+}
+
+fn quote_cell(cell: &str, dialect: &Dialect, policy: QuotePolicy) -> String {
+    let needs_quotes = match policy {
+        QuotePolicy::Minimal => {
+            cell.is_empty() || cell.contains([dialect.delimiter, dialect.quote, '\n', '\r'])
+        }
+        QuotePolicy::Always => true,
+        QuotePolicy::NonNumeric => !is_numeric(cell),
+        QuotePolicy::Never => false,
+    };
+    if !needs_quotes {
+        return cell.to_string();
+    }
+    let quote = dialect.quote;
+    let mut quoted = String::from(quote);
+    for c in cell.chars() {
+        match dialect.escape {
+            EscapeStyle::Doubled if c == quote => quoted.push(quote),
+            EscapeStyle::Backslash if c == quote || c == '\\' => quoted.push('\\'),
+            _ => {}
+        }
+        quoted.push(c);
+    }
+    quoted.push(quote);
+    quoted
+}
+
+fn is_numeric(cell: &str) -> bool {
+    let unsigned = cell.strip_prefix(['-', '+']).unwrap_or(cell);
+    let (mantissa, exponent) = match unsigned.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, Some(exponent)),
+        None => (unsigned, None),
+    };
+    let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let all_digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+    let exponent_valid = exponent.is_none_or(|e| {
+        let e = e.strip_prefix(['-', '+']).unwrap_or(e);
+        !e.is_empty() && all_digits(e)
+    });
+    !(whole.is_empty() && fraction.is_empty())
+        && all_digits(whole)
+        && all_digits(fraction)
+        && exponent_valid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dialect::LineTerminator;
+    use crate::parse_error::ParseErrorKind;
+
+    fn round_trip(csv_holder: &CsvHolder, policy: QuotePolicy) -> CsvHolder {
+        let mut written = Vec::new();
+        write_csv(&mut written, csv_holder, &CsvFormat::default(), policy).unwrap();
+        read_csv(written.as_slice(), &CsvFormat::default()).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let csv_holder = CsvHolder::from_strs(
+            &["name", "city, country", "\"quoted\""],
+            &[
+                &["Brendan", "Dublin, Ireland", "say \"hi\""],
+                &["", "multi\nline", "12.5"],
+                &["-3e7", " padded ", "\r"],
+                &["crlf", "windows\r\nline", "\r\n"],
+            ],
+            &[],
+        );
+        for policy in [
+            QuotePolicy::Minimal,
+            QuotePolicy::Always,
+            QuotePolicy::NonNumeric,
+        ] {
+            assert_eq!(round_trip(&csv_holder, policy), csv_holder);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_with_nulls() {
+        let csv_holder = CsvHolder::from_strs(
+            &["a", "b", "c"],
+            &[&["1", "", ""]],
+            &[Index { row: 1, column: 2 }],
+        );
+        for policy in [
+            QuotePolicy::Minimal,
+            QuotePolicy::Always,
+            QuotePolicy::NonNumeric,
+        ] {
+            assert_eq!(round_trip(&csv_holder, policy), csv_holder);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_one_column_nulls() {
+        let csv_holder =
+            CsvHolder::from_strs(&["a"], &[&[""], &[""]], &[Index { row: 1, column: 1 }]);
+        let mut written = Vec::new();
+        write_csv(
+            &mut written,
+            &csv_holder,
+            &CsvFormat::default(),
+            QuotePolicy::Minimal,
+        )
+        .unwrap();
+        assert_eq!(written, b"a\n\n\"\"\n");
+        assert_eq!(round_trip(&csv_holder, QuotePolicy::Minimal), csv_holder);
+
+        let format = CsvFormat {
+            has_header: false,
+            ..CsvFormat::default()
+        };
+        let csv_holder = read_csv("\n1\n\n".as_bytes(), &format).unwrap();
+        assert_eq!(csv_holder.row_cells(1).unwrap(), vec![Some("1")]);
+        assert_eq!(csv_holder.row_cells(2).unwrap(), vec![None]);
+    }
+
+    #[test]
+    fn test_empty_cells_are_kept() {
+        let csv_holder =
+            read_csv("a,b,c\n1,,3\n,\"\",\n".as_bytes(), &CsvFormat::default()).unwrap();
+        assert_eq!(csv_holder.row(1).unwrap(), vec!["1", "", "3"]);
+        assert_eq!(csv_holder.row_cells(2).unwrap(), vec![None, Some(""), None]);
+    }
+
+    #[test]
+    fn test_quote_policies() {
+        let csv_holder = CsvHolder::from_strs(&["a", "b"], &[&["1", "x,y"]], &[]);
+        let written = |policy| {
+            let mut written = Vec::new();
+            write_csv(&mut written, &csv_holder, &CsvFormat::default(), policy).unwrap();
+            String::from_utf8(written).unwrap()
+        };
+        assert_eq!(written(QuotePolicy::Minimal), "a,b\n1,\"x,y\"\n");
+        assert_eq!(written(QuotePolicy::Always), "\"a\",\"b\"\n\"1\",\"x,y\"\n");
+        assert_eq!(written(QuotePolicy::NonNumeric), "\"a\",\"b\"\n1,\"x,y\"\n");
+        assert_eq!(written(QuotePolicy::Never), "a,b\n1,x,y\n");
+    }
+
+    #[test]
+    fn test_no_header() {
+        let format = CsvFormat {
+            dialect: Dialect::from_name("semicolon").unwrap(),
+            has_header: false,
+            ..CsvFormat::default()
+        };
+        let csv_holder = read_csv("1;2\n3;4\n".as_bytes(), &format).unwrap();
+        assert_eq!(csv_holder.headers(), vec!["column_1", "column_2"]);
+        assert_eq!(csv_holder.row_count(), 2);
+        let mut written = Vec::new();
+        write_csv(&mut written, &csv_holder, &format, QuotePolicy::Minimal).unwrap();
+        assert_eq!(written, b"1;2\n3;4\n");
+    }
+
+    fn read_error(text: &[u8], format: &CsvFormat) -> ParseError {
+        match read_csv(text, format) {
+            Err(CsvIoError::Parse(error)) => error,
+            _ => panic!("{:?} should not read", text),
+        }
+    }
+
+    #[test]
+    fn test_read_errors() {
+        let format = CsvFormat::default();
+        let error = read_error(b"a,b\r\n\r\n1,2\r\n3\r\n", &format);
+        let kind = ParseErrorKind::FieldCount {
+            expected: 2,
+            found: 1,
+        };
+        assert_eq!(error.kind, kind);
+        assert_eq!((error.position.line, error.position.column), (4, 1));
+        assert_eq!(error.position.byte_offset, 13);
+        assert_eq!(error.line_text, "3");
+        // Too many fields are pointed out from the first extra one.
+        let error = read_error(b"a,b\n1,2,\"x\"\n", &format);
+        assert_eq!((error.position.line, error.position.column), (2, 4));
+        assert_eq!(read_error(b"", &format).kind, ParseErrorKind::Empty);
+        let no_header = CsvFormat {
+            has_header: false,
+            ..format
+        };
+        assert_eq!(read_error(b"\n", &no_header).kind, ParseErrorKind::Empty);
+        let latin1 = CsvFormat {
+            encoding: Encoding::Latin1,
+            ..format
+        };
+        let error = read_error(b"city\nM\xfcnchen,x\n", &latin1);
+        assert_eq!(error.position.byte_offset, 13);
+        assert_eq!(error.line_text, "M\u{fc}nchen,x");
+    }
+
+    #[test]
+    fn test_latin1() {
+        let format = CsvFormat {
+            encoding: Encoding::Latin1,
+            ..CsvFormat::default()
+        };
+        let csv_holder = read_csv(&b"city\nM\xfcnchen\n"[..], &format).unwrap();
+        assert_eq!(csv_holder.row(1).unwrap(), vec!["M\u{fc}nchen"]);
+        assert_eq!(
+            Encoding::Latin1.encode("M\u{fc}nchen").unwrap(),
+            b"M\xfcnchen"
+        );
+        assert!(Encoding::Latin1.encode("\u{20ac}").is_err());
+    }
+
+    #[test]
+    fn test_round_trip_in_other_dialects() {
+        let csv_holder = CsvHolder::from_strs(
+            &["name", "quote"],
+            &[&["it's", "say \"hi\"\tnow"], &["back\\slash", "x;y|z"]],
+            &[],
+        );
+        let dialects = [
+            Dialect::from_name("tsv").unwrap(),
+            Dialect::from_name("semicolon").unwrap(),
+            Dialect {
+                delimiter: '|',
+                quote: '\'',
+                escape: EscapeStyle::Backslash,
+                line_terminator: LineTerminator::CrLf,
+            },
+        ];
+        for dialect in dialects {
+            let format = CsvFormat {
+                dialect,
+                ..CsvFormat::default()
+            };
+            for policy in [
+                QuotePolicy::Minimal,
+                QuotePolicy::Always,
+                QuotePolicy::NonNumeric,
+            ] {
+                let mut written = Vec::new();
+                write_csv(&mut written, &csv_holder, &format, policy).unwrap();
+                let read = read_csv(written.as_slice(), &format).unwrap();
+                assert_eq!(read, csv_holder, "{:?} {:?}", dialect, policy);
+            }
+        }
+    }
+
+    #[test]
+    fn test_write_csv_file() {
+        let dir = std::env::temp_dir().join(format!("write-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("out.csv");
+        std::fs::write(&path, "old\n").unwrap();
+        let csv_holder = CsvHolder::from_strs(&["a"], &[&["1"]], &[]);
+        let format = CsvFormat::default();
+        write_csv_file(&path, &csv_holder, &format, QuotePolicy::Minimal).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a\n1\n");
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        let missing = dir.join("missing").join("out.csv");
+        assert!(write_csv_file(&missing, &csv_holder, &format, QuotePolicy::Minimal).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_is_numeric() {
+        for numeric in ["0", "-12", "+3.5", ".5", "5.", "1e10", "2.5E-3"] {
+            assert!(is_numeric(numeric), "{}", numeric);
+        }
+        for non_numeric in ["", "-", ".", "1e", "abc", "NaN", "inf", "1,000", " 1"] {
+            assert!(!is_numeric(non_numeric), "{}", non_numeric);
+        }
+    }
 }
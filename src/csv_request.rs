@@ -1,11 +1,130 @@
-use crate::{csv_data_handle::CsvDataHandle, csv_io::*};
-use std::{fs::File, io::Write};
+use crate::{
+    buffers::{Buffer, Buffers},
+    csv_data_handle::{ColumnRef, CsvDataHandle, CsvError, CsvResult, Index},
+    csv_holder::CsvHolder,
+    csv_io::*,
+    dialect::sniff_dialect,
+    edit_history::{Edit, EditHistory},
+    expression::{Expression, ExpressionError},
+    group::{self, AggregateFunction, Aggregation},
+    join::{self, JoinKind, DEFAULT_SUFFIXES},
+    parse_error::ParseError,
+    schema::{ColumnSchema, ColumnType, Schema, SchemaChecked},
+    search::{self, Pattern, Scope},
+    sheet::Sheet,
+    sort::{SortKey, SortMode},
+    stats::{self, ColumnStats, DEFAULT_TOP_VALUES},
+    table::{write_table, DEFAULT_MAX_COLUMN_WIDTH},
+};
+use std::{
+    error::Error,
+    fmt::Display,
+    fs::File,
+    io::{BufReader, Write},
+    path::{Path, PathBuf},
+};
 
+/// Settings that stay the same for every request in a session.
+#[derive(Debug, Clone, Default)]
+pub struct RequestOptions {
+    pub format: CsvFormat,
+    /// Where `save` writes to.
+    pub save_path: Option<PathBuf>,
+}
+
+/// What a session remembers between requests, apart from the data itself.
+#[derive(Default)]
+pub struct Session {
+    pub history: EditHistory,
+    /// Set by `set_filter`; `display` and `display_row_range` only show the rows it accepts.
+    pub filter: Option<Expression>,
+    /// Edits that do not fit it are refused.
+    pub schema: Schema,
+    /// What the last `group_by` or `join` produced, for `use_result`.
+    pub result: Option<Sheet>,
+    /// The sheet that was being worked on before `use_result`, along with its session.
+    pub original: Option<Box<(Sheet, Session)>>,
+}
+
+impl Session {
+    /// Starts a session on `data_handle`, with a schema inferred from the data in it.
+    pub fn new(data_handle: &impl CsvDataHandle) -> Self {
+        Self {
+            schema: Schema::infer(data_handle),
+            ..Self::default()
+        }
+    }
+
+    /// Makes the last result the sheet being worked on, with a session of its own.
+    /// The sheet it replaces is kept for `use_original`, unless it is itself a result.
+    pub fn use_result(&mut self, sheet: &mut Sheet) -> Result<(), CsvIoError> {
+        let result = self.result.take().ok_or(CsvIoError::NoResult)?;
+        let mut session = Session::new(&result);
+        let replaced = std::mem::replace(sheet, result);
+        let replaced_session = std::mem::take(self);
+        session.original = match replaced_session.original {
+            Some(original) => Some(original),
+            None => Some(Box::new((replaced, replaced_session))),
+        };
+        *self = session;
+        Ok(())
+    }
+
+    /// The sheet that was loaded, which is `sheet` unless a result is being worked on.
+    pub fn original_sheet<'a>(&'a mut self, sheet: &'a mut Sheet) -> &'a mut Sheet {
+        match &mut self.original {
+            Some(original) => &mut original.0,
+            None => sheet,
+        }
+    }
+
+    /// Goes back to the sheet that was being worked on before `use_result`. The result being
+    /// worked on, with any changes made to it, becomes the one `use_result` brings back.
+    pub fn use_original(&mut self, sheet: &mut Sheet) -> Result<(), CsvIoError> {
+        let (original, session) = *self.original.take().ok_or(CsvIoError::NoOriginalSheet)?;
+        let result = std::mem::replace(sheet, original);
+        *self = session;
+        self.result = Some(result);
+        Ok(())
+    }
+}
+
+/// How `display` and `display_row_range` lay out the rows they show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisplayStyle {
+    Table {
+        max_column_width: usize,
+    },
+    /// The rows as they would be written to a file.
+    Raw,
+}
+
+impl Default for DisplayStyle {
+    fn default() -> Self {
+        DisplayStyle::Table {
+            max_column_width: DEFAULT_MAX_COLUMN_WIDTH,
+        }
+    }
+}
+
+/// One of the columns named in a `sort` request, before it is looked up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortColumn {
+    pub column: ColumnRef,
+    pub descending: bool,
+    pub mode: SortMode,
+}
+
+/// One of the editor's commands, as read by `parse_csv_request`.
+#[non_exhaustive]
 pub enum CsvRequest {
     Help,
-    Display,
+    Display(DisplayStyle),
     DisplayHeaders,
-    DisplayRowRange(usize, usize),
+    DisplayRowRange(usize, usize, DisplayStyle),
+    Filter(Expression),
+    SetFilter(Expression),
+    ClearFilter,
     ModifyRow {
         row: usize,
         new_data: Vec<String>,
@@ -21,98 +140,1357 @@ pub enum CsvRequest {
         new_data: Vec<String>,
     },
     DeleteColumnByName(String),
+    InsertRow {
+        row: usize,
+        new_data: Vec<String>,
+    },
+    AppendRow(Vec<String>),
+    InsertColumn {
+        column: usize,
+        header: String,
+        new_data: Vec<String>,
+    },
+    AppendColumn {
+        header: String,
+        new_data: Vec<String>,
+    },
+    /// Shows a sheet summarising each group of rows, and keeps it for `use_result`.
+    GroupBy {
+        columns: Vec<ColumnRef>,
+        aggregations: Vec<Aggregation>,
+    },
+    /// Joins the rows of another file to those of the sheet, and works on the result.
+    Join {
+        /// The other file, read when the request is handled.
+        path: PathBuf,
+        /// Pairs of columns, of the sheet and of the other file, that must match.
+        keys: Vec<(ColumnRef, ColumnRef)>,
+        kind: JoinKind,
+        suffixes: (String, String),
+    },
+    UseResult,
+    UseOriginal,
+    /// Loads a file into a new buffer, named after the file unless given a name.
+    Open {
+        path: PathBuf,
+        name: Option<String>,
+    },
+    ListBuffers,
+    Switch(String),
+    Close {
+        name: String,
+        discard: bool,
+    },
+    /// Copies rows of the active buffer into another, before row `at` or after its last row.
+    CopyRows {
+        first_row: usize,
+        last_row: usize,
+        to: String,
+        at: Option<usize>,
+    },
+    /// Appends a column whose cells are worked out from each row.
+    AddColumn {
+        header: String,
+        expression: Expression,
+    },
+    /// Overwrites a column with what an expression works out to for each row.
+    UpdateColumn {
+        column: ColumnRef,
+        expression: Expression,
+    },
+    Sort(Vec<SortColumn>),
+    Find {
+        pattern: Pattern,
+        scope: Scope,
+    },
+    Replace {
+        pattern: Pattern,
+        replacement: String,
+        scope: Scope,
+        dry_run: bool,
+    },
+    Get {
+        row: usize,
+        column: ColumnRef,
+    },
+    Set {
+        row: usize,
+        column: ColumnRef,
+        value: String,
+    },
+    ShowSchema,
+    SetSchema {
+        column: ColumnRef,
+        column_schema: ColumnSchema,
+    },
+    /// Summarises one column, or every column; `top` is how many of the most common values to
+    /// list.
+    Stats {
+        column: Option<ColumnRef>,
+        top: usize,
+    },
+    Sum(ColumnRef),
+    Average(ColumnRef),
+    /// Counts the cells of a column that hold a value, or the rows.
+    Count(Option<ColumnRef>),
     Dimensions,
-    WriteToFile(File),
+    WriteToFile(PathBuf, QuotePolicy),
+    Save,
+    Quit,
+    Undo,
+    Redo,
+    History,
 }
 
-pub fn parse_csv_request(s: &str) -> Option<CsvRequest> {
-    match s {
-        "display" => {
-            return Some(CsvRequest::Display);
+impl CsvRequest {
+    /// Whether a successful request leaves the data written out in full to `save_path`, the
+    /// file that `save` writes to. Writing a copy anywhere else does not save it.
+    pub fn saves_data(&self, save_path: Option<&Path>) -> bool {
+        match self {
+            CsvRequest::Save => true,
+            CsvRequest::WriteToFile(path, _) => Some(path.as_path()) == save_path,
+            _ => false,
         }
-        "help" => {
-            return Some(CsvRequest::Help);
+    }
+}
+
+/// Why a line could not be read as a request.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestError {
+    /// The first word of the line, whether or not it names a command.
+    pub command: String,
+    pub kind: RequestErrorKind,
+}
+
+/// What is wrong with a line that `parse_csv_request` could not read.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum RequestErrorKind {
+    /// There is no command of that name; `suggestions` are the known commands closest to it.
+    UnknownCommand {
+        suggestions: Vec<&'static str>,
+    },
+    /// The argument, as named in the command's syntax, was left out.
+    MissingArgument(&'static str),
+    /// More was given than the command takes, or an option it does not have.
+    UnexpectedArgument(String),
+    NotANumber {
+        argument: &'static str,
+        found: String,
+    },
+    /// `found` is not one of the values `argument` can take.
+    InvalidArgument {
+        argument: &'static str,
+        found: String,
+    },
+    /// A quote in the arguments is never closed.
+    UnclosedQuote,
+    /// The cells given could not be split the way a line of the file is.
+    InvalidData(ParseError),
+    InvalidExpression(ExpressionError),
+    InvalidPattern(regex::Error),
+}
+
+impl Display for RequestErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RequestErrorKind::UnknownCommand { .. } => write!(f, "unknown command"),
+            RequestErrorKind::MissingArgument(argument) => write!(f, "missing {}", argument),
+            RequestErrorKind::UnexpectedArgument(found) => write!(f, "unexpected {:?}", found),
+            RequestErrorKind::NotANumber { argument, found } => {
+                write!(f, "{} must be a number, not {:?}", argument, found)
+            }
+            RequestErrorKind::InvalidArgument { argument, found } => {
+                write!(f, "{:?} is not a valid {}", found, argument)
+            }
+            RequestErrorKind::UnclosedQuote => write!(f, "quote is never closed"),
+            RequestErrorKind::InvalidData(e) => write!(
+                f,
+                "invalid <new_data>: {} at character {}",
+                e.kind,
+                e.line_text[..e.position.column.min(e.line_text.len())]
+                    .chars()
+                    .count()
+                    + 1
+            ),
+            RequestErrorKind::InvalidExpression(e) => write!(f, "invalid expression: {}", e),
+            RequestErrorKind::InvalidPattern(e) => write!(f, "invalid pattern: {}", e),
         }
-        "dimensions" => {
-            return Some(CsvRequest::Dimensions);
+    }
+}
+
+impl Display for RequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.kind {
+            RequestErrorKind::UnknownCommand { .. } if self.command.is_empty() => {
+                write!(f, "no command given")
+            }
+            RequestErrorKind::UnknownCommand { suggestions } => {
+                write!(f, "unknown command {:?}", self.command)?;
+                if let Some((last, rest)) = suggestions.split_last() {
+                    let rest = rest.iter().map(|name| format!("{:?}", name));
+                    let rest = rest.collect::<Vec<_>>().join(", ");
+                    if rest.is_empty() {
+                        write!(f, "; did you mean {:?}?", last)?;
+                    } else {
+                        write!(f, "; did you mean {} or {:?}?", rest, last)?;
+                    }
+                }
+                Ok(())
+            }
+            kind => write!(f, "{}: {}", self.command, kind),
         }
-        "display_headers" => {
-            return Some(CsvRequest::DisplayHeaders);
+    }
+}
+
+impl RequestError {
+    /// The syntax of the command, as `help` lists it, or nothing for an unknown command.
+    pub fn usage(&self) -> Vec<&'static str> {
+        COMMAND_SYNTAX
+            .iter()
+            .filter(|(name, _)| *name == self.command)
+            .map(|(_, syntax)| *syntax)
+            .collect()
+    }
+}
+
+impl Error for RequestError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match &self.kind {
+            RequestErrorKind::InvalidData(e) => Some(e),
+            RequestErrorKind::InvalidExpression(e) => Some(e),
+            RequestErrorKind::InvalidPattern(e) => Some(e),
+            _ => None,
         }
-        _ => {}
-    };
-    let (command, args) = s.split_once(' ')?;
-    match command {
+    }
+}
+
+/// Reads a command as typed at the editor's prompt, or says what is wrong with it. Cells given
+/// on the line, as by `insert_row`, are split the way `format` splits a line of a file.
+pub fn parse_csv_request(s: &str, format: &CsvFormat) -> Result<CsvRequest, RequestError> {
+    let (command, args) = s.split_once(' ').unwrap_or((s, ""));
+    parse_command(command, args, format).map_err(|kind| RequestError {
+        command: command.to_string(),
+        kind,
+    })
+}
+
+fn parse_command(
+    command: &str,
+    args: &str,
+    format: &CsvFormat,
+) -> Result<CsvRequest, RequestErrorKind> {
+    let request = match command {
+        "help" => no_arguments(args, CsvRequest::Help)?,
+        "dimensions" => no_arguments(args, CsvRequest::Dimensions)?,
+        "display_headers" => no_arguments(args, CsvRequest::DisplayHeaders)?,
+        "save" => no_arguments(args, CsvRequest::Save)?,
+        "quit" | "exit" => no_arguments(args, CsvRequest::Quit)?,
+        "undo" => no_arguments(args, CsvRequest::Undo)?,
+        "redo" => no_arguments(args, CsvRequest::Redo)?,
+        "history" => no_arguments(args, CsvRequest::History)?,
+        "clear_filter" => no_arguments(args, CsvRequest::ClearFilter)?,
+        "use_result" => no_arguments(args, CsvRequest::UseResult)?,
+        "use_original" => no_arguments(args, CsvRequest::UseOriginal)?,
+        "buffers" => no_arguments(args, CsvRequest::ListBuffers)?,
+        "display" => CsvRequest::Display(parse_display_style(args)?),
         "display_row_range" => {
-            let mut args = args.splitn(2, ' ');
-            let first_row = args.next()?.parse::<usize>().ok()?;
-            let last_row = args.next()?.parse::<usize>().ok()?;
-            Some(CsvRequest::DisplayRowRange(first_row, last_row))
+            let (first_row, args) = first_word(args, "<first_row>")?;
+            let (last_row, args) = first_word(args, "<last_row>")?;
+            CsvRequest::DisplayRowRange(
+                parse_number(first_row, "<first_row>")?,
+                parse_number(last_row, "<last_row>")?,
+                parse_display_style(args)?,
+            )
         }
+        "filter" => CsvRequest::Filter(parse_expression(args)?),
+        "set_filter" => CsvRequest::SetFilter(parse_expression(args)?),
         "modify_row" => {
-            let mut args = args.splitn(2, ' ');
-            let row = args.next()?.parse::<usize>().ok()?;
-            let new_data = args.next()?.split(',').map(|s| s.to_string()).collect();
-            Some(CsvRequest::ModifyRow { row, new_data })
-        }
-        "delete_row" => {
-            let row = args.parse::<usize>().ok()?;
-            Some(CsvRequest::DeleteRow(row))
+            let (row, args) = first_word(args, "<row>")?;
+            CsvRequest::ModifyRow {
+                row: parse_number(row, "<row>")?,
+                new_data: parse_data(args, format)?,
+            }
         }
+        "delete_row" => CsvRequest::DeleteRow(parse_number(only_word(args, "<row>")?, "<row>")?),
         "modify_column" => {
-            let mut args = args.splitn(2, ' ');
-            let column = args.next()?.parse::<usize>().ok()?;
-            let new_data = args.next()?.split(',').map(|s| s.to_string()).collect();
-            Some(CsvRequest::ModifyColumn { column, new_data })
+            let (column, args) = first_word(args, "<column>")?;
+            CsvRequest::ModifyColumn {
+                column: parse_number(column, "<column>")?,
+                new_data: parse_data(args, format)?,
+            }
         }
         "delete_column" => {
-            let column = args.parse::<usize>().ok()?;
-            Some(CsvRequest::DeleteColumn(column))
+            CsvRequest::DeleteColumn(parse_number(only_word(args, "<column>")?, "<column>")?)
         }
         "modify_column_by_name" => {
-            let mut args = args.splitn(2, ' ');
-            let column_name = args.next()?.to_string();
-            let new_data = args.next()?.split(',').map(|s| s.to_string()).collect();
-            Some(CsvRequest::ModifyColumnByName {
-                column_name,
-                new_data,
-            })
+            let (column_name, args) = first_word(args, "<column_name>")?;
+            CsvRequest::ModifyColumnByName {
+                column_name: column_name.to_string(),
+                new_data: parse_data(args, format)?,
+            }
         }
         "delete_column_by_name" => {
-            let column_name = args.to_string();
-            Some(CsvRequest::DeleteColumnByName(column_name))
+            if args.is_empty() {
+                return Err(RequestErrorKind::MissingArgument("<column_name>"));
+            }
+            CsvRequest::DeleteColumnByName(args.to_string())
+        }
+        "insert_row" => {
+            let (row, args) = first_word(args, "<row>")?;
+            CsvRequest::InsertRow {
+                row: parse_number(row, "<row>")?,
+                new_data: parse_data(args, format)?,
+            }
+        }
+        "append_row" => CsvRequest::AppendRow(parse_data(args, format)?),
+        "insert_column" => {
+            let (column, args) = first_word(args, "<column>")?;
+            let (header, args) = first_word(args, "<header>")?;
+            CsvRequest::InsertColumn {
+                column: parse_number(column, "<column>")?,
+                header: header.to_string(),
+                new_data: parse_data(args, format)?,
+            }
+        }
+        "append_column" => {
+            let (header, args) = first_word(args, "<header>")?;
+            CsvRequest::AppendColumn {
+                header: header.to_string(),
+                new_data: parse_data(args, format)?,
+            }
+        }
+        "group_by" => {
+            let (columns, aggregations) = args
+                .split_once(" agg ")
+                .or_else(|| Some((args.strip_suffix(" agg")?, "")))
+                .ok_or(RequestErrorKind::MissingArgument("agg"))?;
+            let columns = columns
+                .split(',')
+                .map(parse_column)
+                .collect::<Result<Vec<_>, _>>()?;
+            CsvRequest::GroupBy {
+                columns,
+                aggregations: parse_aggregations(aggregations)?,
+            }
+        }
+        "join" => {
+            let mut words = split_words(args)?.into_iter();
+            let path = PathBuf::from(next_word(&mut words, "<file>")?);
+            keyword(&mut words, "on")?;
+            let mut keys = Vec::new();
+            let mut kind = JoinKind::default();
+            let mut suffixes = (
+                DEFAULT_SUFFIXES.0.to_string(),
+                DEFAULT_SUFFIXES.1.to_string(),
+            );
+            while let Some(word) = words.next() {
+                if let Some(join_kind) = JoinKind::from_name(&word) {
+                    kind = join_kind;
+                } else if word == "--suffixes" {
+                    suffixes = (
+                        next_word(&mut words, "<left>")?,
+                        next_word(&mut words, "<right>")?,
+                    );
+                } else {
+                    keys.push(word);
+                }
+            }
+            if keys.is_empty() {
+                return Err(RequestErrorKind::MissingArgument("<column>=<other column>"));
+            }
+            let keys = keys
+                .join(" ")
+                .split(',')
+                .map(|pair| {
+                    let (left, right) =
+                        pair.split_once('=')
+                            .ok_or_else(|| RequestErrorKind::InvalidArgument {
+                                argument: "<column>=<other column>",
+                                found: pair.trim().to_string(),
+                            })?;
+                    Ok((parse_column(left)?, parse_column(right)?))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            CsvRequest::Join {
+                path,
+                keys,
+                kind,
+                suffixes,
+            }
+        }
+        "open" => {
+            let mut words = split_words(args)?.into_iter();
+            let path = PathBuf::from(next_word(&mut words, "<file>")?);
+            let name = match words.next() {
+                Some(word) if word == "as" => Some(next_word(&mut words, "<name>")?),
+                Some(word) => return Err(RequestErrorKind::UnexpectedArgument(word)),
+                None => None,
+            };
+            no_more_words(words)?;
+            CsvRequest::Open { path, name }
+        }
+        "switch" => {
+            let mut words = split_words(args)?.into_iter();
+            let name = next_word(&mut words, "<name>")?;
+            no_more_words(words)?;
+            CsvRequest::Switch(name)
+        }
+        "close" => {
+            let mut words = split_words(args)?.into_iter();
+            let name = next_word(&mut words, "<name>")?;
+            let discard = match words.next() {
+                Some(word) if word == "--discard" => true,
+                Some(word) => return Err(RequestErrorKind::UnexpectedArgument(word)),
+                None => false,
+            };
+            no_more_words(words)?;
+            CsvRequest::Close { name, discard }
+        }
+        "copy_rows" => {
+            let mut words = split_words(args)?.into_iter();
+            let first_row = parse_number(&next_word(&mut words, "<first_row>")?, "<first_row>")?;
+            let last_row = parse_number(&next_word(&mut words, "<last_row>")?, "<last_row>")?;
+            keyword(&mut words, "to")?;
+            let to = next_word(&mut words, "<name>")?;
+            let at = match words.next() {
+                Some(word) if word == "at" => {
+                    Some(parse_number(&next_word(&mut words, "<row>")?, "<row>")?)
+                }
+                Some(word) => return Err(RequestErrorKind::UnexpectedArgument(word)),
+                None => None,
+            };
+            no_more_words(words)?;
+            CsvRequest::CopyRows {
+                first_row,
+                last_row,
+                to,
+                at,
+            }
+        }
+        "add_column" => {
+            let (header, expression) = parse_assignment(args, "<header>")?;
+            CsvRequest::AddColumn {
+                header: header.to_string(),
+                expression,
+            }
+        }
+        "update_column" => {
+            let (column, expression) = parse_assignment(args, "<column>")?;
+            CsvRequest::UpdateColumn {
+                column: ColumnRef::parse(column),
+                expression,
+            }
+        }
+        "sort" => {
+            let columns = args
+                .split(',')
+                .map(parse_sort_column)
+                .collect::<Result<Vec<_>, _>>()?;
+            CsvRequest::Sort(columns)
+        }
+        "find" => {
+            let (scope, pattern, _, dry_run) = parse_search(args, &["<pattern>"])?;
+            if dry_run {
+                return Err(RequestErrorKind::UnexpectedArgument(
+                    "--dry-run".to_string(),
+                ));
+            }
+            CsvRequest::Find { pattern, scope }
+        }
+        "replace" => {
+            let (scope, pattern, mut rest, dry_run) =
+                parse_search(args, &["<pattern>", "<replacement>"])?;
+            CsvRequest::Replace {
+                pattern,
+                replacement: rest.remove(0),
+                scope,
+                dry_run,
+            }
+        }
+        "schema" if args.is_empty() => CsvRequest::ShowSchema,
+        "schema" => {
+            let mut words = split_words(args)?.into_iter();
+            let column = ColumnRef::parse(&next_word(&mut words, "<column>")?);
+            let type_name = next_word(&mut words, "<type>")?;
+            let (type_name, nullable) = match type_name.strip_suffix('?') {
+                Some(type_name) => (type_name, true),
+                None => (type_name.as_str(), false),
+            };
+            let values = words.collect::<Vec<_>>();
+            let column_type = match ColumnType::from_words(type_name, values.clone()) {
+                Some(column_type) => column_type,
+                None if type_name == "enum" => {
+                    return Err(RequestErrorKind::MissingArgument("<enum value>"))
+                }
+                None if ColumnType::from_words(type_name, Vec::new()).is_some() => {
+                    return Err(RequestErrorKind::UnexpectedArgument(values[0].clone()))
+                }
+                None => {
+                    return Err(RequestErrorKind::InvalidArgument {
+                        argument: "<type>",
+                        found: type_name.to_string(),
+                    })
+                }
+            };
+            CsvRequest::SetSchema {
+                column,
+                column_schema: ColumnSchema {
+                    column_type,
+                    nullable,
+                },
+            }
+        }
+        "stats" => {
+            let mut words = split_words(args)?;
+            let mut top = DEFAULT_TOP_VALUES;
+            if let Some(i) = words.iter().position(|word| word == "--top") {
+                let n = words
+                    .get(i + 1)
+                    .ok_or(RequestErrorKind::MissingArgument("<n>"))?;
+                top = parse_number(n, "<n>")?;
+                words.drain(i..i + 2);
+            }
+            let mut words = words.into_iter();
+            let column = words.next().map(|word| ColumnRef::parse(&word));
+            no_more_words(words)?;
+            CsvRequest::Stats { column, top }
+        }
+        "sum" => CsvRequest::Sum(parse_column(args)?),
+        "avg" => CsvRequest::Average(parse_column(args)?),
+        "count" if args.is_empty() => CsvRequest::Count(None),
+        "count" => CsvRequest::Count(Some(parse_column(args)?)),
+        "get" => {
+            let (row, column, _) = parse_cell_reference(args, false)?;
+            CsvRequest::Get { row, column }
+        }
+        "set" => {
+            let (row, column, value) = parse_cell_reference(args, true)?;
+            CsvRequest::Set {
+                row,
+                column,
+                value: value.unwrap_or_default().to_string(),
+            }
         }
         "write_to_file" => {
-            let file = File::create(args).ok()?;
-            Some(CsvRequest::WriteToFile(file))
+            let (file_name, policy) = match args.rsplit_once(' ') {
+                Some((file_name, policy_name)) => match QuotePolicy::from_name(policy_name) {
+                    Some(policy) => (file_name, policy),
+                    None => (args, QuotePolicy::default()),
+                },
+                None => (args, QuotePolicy::default()),
+            };
+            if file_name.is_empty() {
+                return Err(RequestErrorKind::MissingArgument("<file_name>"));
+            }
+            CsvRequest::WriteToFile(PathBuf::from(file_name), policy)
+        }
+        _ => {
+            return Err(RequestErrorKind::UnknownCommand {
+                suggestions: suggest_commands(command),
+            })
+        }
+    };
+    Ok(request)
+}
+
+/// `request`, as long as nothing follows the command.
+fn no_arguments(args: &str, request: CsvRequest) -> Result<CsvRequest, RequestErrorKind> {
+    match args.trim() {
+        "" => Ok(request),
+        args => Err(RequestErrorKind::UnexpectedArgument(args.to_string())),
+    }
+}
+
+/// Splits `args` after its first word, which is the argument called `argument`.
+fn first_word<'a>(
+    args: &'a str,
+    argument: &'static str,
+) -> Result<(&'a str, &'a str), RequestErrorKind> {
+    let (word, rest) = args.split_once(' ').unwrap_or((args, ""));
+    match word {
+        "" => Err(RequestErrorKind::MissingArgument(argument)),
+        word => Ok((word, rest)),
+    }
+}
+
+/// `args`, which should be the single word `argument`.
+fn only_word<'a>(args: &'a str, argument: &'static str) -> Result<&'a str, RequestErrorKind> {
+    let (word, rest) = first_word(args, argument)?;
+    match rest.trim() {
+        "" => Ok(word),
+        rest => Err(RequestErrorKind::UnexpectedArgument(rest.to_string())),
+    }
+}
+
+/// The next of the words of a request, which is the argument called `argument`.
+fn next_word(
+    words: &mut impl Iterator<Item = String>,
+    argument: &'static str,
+) -> Result<String, RequestErrorKind> {
+    words
+        .next()
+        .ok_or(RequestErrorKind::MissingArgument(argument))
+}
+
+/// Checks that the next of the words of a request is `keyword`.
+fn keyword(
+    words: &mut impl Iterator<Item = String>,
+    keyword: &'static str,
+) -> Result<(), RequestErrorKind> {
+    match words.next() {
+        Some(word) if word == keyword => Ok(()),
+        _ => Err(RequestErrorKind::MissingArgument(keyword)),
+    }
+}
+
+/// Checks that all the words of a request have been used.
+fn no_more_words(mut words: impl Iterator<Item = String>) -> Result<(), RequestErrorKind> {
+    match words.next() {
+        Some(word) => Err(RequestErrorKind::UnexpectedArgument(word)),
+        None => Ok(()),
+    }
+}
+
+fn parse_number(word: &str, argument: &'static str) -> Result<usize, RequestErrorKind> {
+    word.parse::<usize>()
+        .map_err(|_| RequestErrorKind::NotANumber {
+            argument,
+            found: word.to_string(),
+        })
+}
+
+/// The cells of `<new_data>`, split the way `format` splits a line of a file.
+fn parse_data(args: &str, format: &CsvFormat) -> Result<Vec<String>, RequestErrorKind> {
+    if args.is_empty() {
+        return Err(RequestErrorKind::MissingArgument("<new_data>"));
+    }
+    split_record(args, format).map_err(RequestErrorKind::InvalidData)
+}
+
+fn parse_expression(args: &str) -> Result<Expression, RequestErrorKind> {
+    if args.trim().is_empty() {
+        return Err(RequestErrorKind::MissingArgument("<expression>"));
+    }
+    Expression::parse(args).map_err(RequestErrorKind::InvalidExpression)
+}
+
+/// Parses the `--raw` and `--max-width <width>` options of the display requests.
+fn parse_display_style(args: &str) -> Result<DisplayStyle, RequestErrorKind> {
+    let mut style = DisplayStyle::default();
+    let mut args = args.split_whitespace();
+    while let Some(arg) = args.next() {
+        style = match (arg, style) {
+            ("--raw", _) => DisplayStyle::Raw,
+            ("--max-width", DisplayStyle::Table { .. }) => {
+                let width = args
+                    .next()
+                    .ok_or(RequestErrorKind::MissingArgument("<width>"))?;
+                DisplayStyle::Table {
+                    max_column_width: parse_number(width, "<width>")?,
+                }
+            }
+            _ => return Err(RequestErrorKind::UnexpectedArgument(arg.to_string())),
+        };
+    }
+    Ok(style)
+}
+
+/// A column given by number or header, which may have spaces in it.
+fn parse_column(args: &str) -> Result<ColumnRef, RequestErrorKind> {
+    match args.trim() {
+        "" => Err(RequestErrorKind::MissingArgument("<column>")),
+        column => Ok(ColumnRef::parse(column)),
+    }
+}
+
+/// Parses a list of aggregations such as `sum(price) count() concat(rep, "; ")`.
+fn parse_aggregations(s: &str) -> Result<Vec<Aggregation>, RequestErrorKind> {
+    let separators = |c: char| c.is_whitespace() || c == ',';
+    let mut aggregations = Vec::new();
+    let mut rest = s.trim_start_matches(separators);
+    while !rest.is_empty() {
+        let (name, after) =
+            rest.split_once('(')
+                .ok_or_else(|| RequestErrorKind::InvalidArgument {
+                    argument: "<function>(<column>)",
+                    found: rest.to_string(),
+                })?;
+        let mut function = AggregateFunction::from_name(name.trim()).ok_or_else(|| {
+            RequestErrorKind::InvalidArgument {
+                argument: "<function>",
+                found: name.trim().to_string(),
+            }
+        })?;
+        let mut quote = None;
+        let end = after
+            .find(|c: char| {
+                match quote {
+                    Some(q) if c == q => quote = None,
+                    Some(_) => {}
+                    None if c == '"' || c == '\'' => quote = Some(c),
+                    None => return c == ')',
+                }
+                false
+            })
+            .ok_or(RequestErrorKind::MissingArgument(")"))?;
+        let (mut column, mut separator) = (after[..end].trim(), None);
+        if let AggregateFunction::Concat(_) = function {
+            if let Some((before, after)) = column.split_once(',') {
+                column = before.trim();
+                separator = Some(after);
+            }
+        }
+        if let Some(separator) = separator {
+            let mut words = split_words(separator)?.into_iter();
+            function = AggregateFunction::Concat(next_word(&mut words, "<separator>")?);
+            no_more_words(words)?;
         }
+        let column = match column {
+            "" | "*" if function == AggregateFunction::Count => None,
+            column => Some(parse_column(column)?),
+        };
+        aggregations.push(Aggregation { function, column });
+        rest = after[end + 1..].trim_start_matches(separators);
+    }
+    if aggregations.is_empty() {
+        return Err(RequestErrorKind::MissingArgument("<function>(<column>)"));
+    }
+    Ok(aggregations)
+}
+
+/// Splits `<target> = <expression>` at the first `=`, where the target is called `argument`.
+fn parse_assignment<'a>(
+    args: &'a str,
+    argument: &'static str,
+) -> Result<(&'a str, Expression), RequestErrorKind> {
+    let (target, expression) = args
+        .split_once('=')
+        .ok_or(RequestErrorKind::MissingArgument("="))?;
+    let target = target.trim();
+    if target.is_empty() {
+        return Err(RequestErrorKind::MissingArgument(argument));
+    }
+    Ok((target, parse_expression(expression)?))
+}
+
+/// Parses `<column> [asc|desc] [lexical|numeric|natural|date]`; the column may be a header
+/// with spaces in it.
+fn parse_sort_column(s: &str) -> Result<SortColumn, RequestErrorKind> {
+    let mut words = s.split_whitespace().collect::<Vec<_>>();
+    let mode = words.last().and_then(|word| SortMode::from_name(word));
+    if mode.is_some() {
+        words.pop();
+    }
+    let descending = match words.last() {
+        Some(&"asc") => Some(false),
+        Some(&"desc") => Some(true),
         _ => None,
+    };
+    if descending.is_some() {
+        words.pop();
+    }
+    if words.is_empty() {
+        return Err(RequestErrorKind::MissingArgument("<column>"));
+    }
+    Ok(SortColumn {
+        column: ColumnRef::parse(&words.join(" ")),
+        descending: descending.unwrap_or(false),
+        mode: mode.unwrap_or_default(),
+    })
+}
+
+/// Parses the options of `find` and `replace` followed by the words named in `positionals`, the
+/// first of which is the pattern. Returns the scope, the pattern, the other words and whether
+/// `--dry-run` was given.
+fn parse_search(
+    args: &str,
+    positionals: &[&'static str],
+) -> Result<(Scope, Pattern, Vec<String>, bool), RequestErrorKind> {
+    let mut scope = Scope::default();
+    let (mut is_regex, mut ignore_case, mut dry_run) = (false, false, false);
+    let mut words = split_words(args)?.into_iter();
+    let mut rest = Vec::new();
+    while let Some(word) = words.next() {
+        match word.as_str() {
+            "--column" => {
+                scope.column = Some(ColumnRef::parse(&next_word(&mut words, "<column>")?))
+            }
+            "--rows" => {
+                let rows = next_word(&mut words, "<first>-<last>")?;
+                let (first, last) =
+                    rows.split_once('-')
+                        .ok_or_else(|| RequestErrorKind::InvalidArgument {
+                            argument: "<first>-<last>",
+                            found: rows.clone(),
+                        })?;
+                scope.rows = Some((
+                    parse_number(first, "<first>")?,
+                    parse_number(last, "<last>")?,
+                ));
+            }
+            "--regex" => is_regex = true,
+            "--ignore-case" => ignore_case = true,
+            "--dry-run" => dry_run = true,
+            "--" => rest.extend(words.by_ref()),
+            option if option.starts_with("--") => {
+                return Err(RequestErrorKind::UnexpectedArgument(word))
+            }
+            _ => rest.push(word),
+        }
+    }
+    if let Some(missing) = positionals.get(rest.len()) {
+        return Err(RequestErrorKind::MissingArgument(missing));
+    }
+    no_more_words(rest.drain(positionals.len()..))?;
+    if rest[0].is_empty() {
+        return Err(RequestErrorKind::MissingArgument("<pattern>"));
+    }
+    let pattern = Pattern::new(&rest.remove(0), is_regex, ignore_case)
+        .map_err(RequestErrorKind::InvalidPattern)?;
+    Ok((scope, pattern, rest, dry_run))
+}
+
+/// Splits `args` at whitespace, except inside double or single quotes. A backslash in front of
+/// a quote or another backslash stands for that character.
+fn split_words(args: &str) -> Result<Vec<String>, RequestErrorKind> {
+    let mut words = Vec::new();
+    let mut chars = args.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            return Ok(words);
+        }
+        let mut word = String::new();
+        let mut quote = None;
+        while let Some(c) = chars.next() {
+            match (c, quote) {
+                ('\\', _) if matches!(chars.peek(), Some('"' | '\'' | '\\')) => {
+                    word.extend(chars.next())
+                }
+                ('"' | '\'', None) => quote = Some(c),
+                (c, Some(q)) if c == q => quote = None,
+                (c, None) if c.is_whitespace() => break,
+                (c, _) => word.push(c),
+            }
+        }
+        if quote.is_some() {
+            return Err(RequestErrorKind::UnclosedQuote);
+        }
+        words.push(word);
+    }
+}
+
+/// Parses `<row> <column>` or an A1-style reference such as `C5`, optionally followed by the
+/// rest of the line as a value.
+fn parse_cell_reference(
+    args: &str,
+    with_value: bool,
+) -> Result<(usize, ColumnRef, Option<&str>), RequestErrorKind> {
+    let (reference, rest) = first_word(args, "<row>")?;
+    let rest = (!rest.is_empty()).then_some(rest);
+    if let Some(Index { row, column }) = Index::from_a1(reference) {
+        return match (rest, with_value) {
+            (None, true) => Err(RequestErrorKind::MissingArgument("<value>")),
+            (Some(rest), false) => Err(RequestErrorKind::UnexpectedArgument(rest.to_string())),
+            (value, _) => Ok((row, ColumnRef::Index(column), value)),
+        };
+    }
+    let row = parse_number(reference, "<row>")?;
+    let (column, value) = first_word(rest.unwrap_or(""), "<column>")?;
+    match (value, with_value) {
+        ("", true) => Err(RequestErrorKind::MissingArgument("<value>")),
+        (value, true) => Ok((row, ColumnRef::parse(column), Some(value))),
+        ("", false) => Ok((row, ColumnRef::parse(column), None)),
+        (value, false) => Err(RequestErrorKind::UnexpectedArgument(value.to_string())),
     }
 }
 
+/// The known commands closest to `command`: those it begins, and those a few typing mistakes
+/// away from it, nearest first.
+fn suggest_commands(command: &str) -> Vec<&'static str> {
+    let limit = (command.chars().count() / 3).max(1);
+    let mut names = COMMAND_SYNTAX
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>();
+    names.extend(["help", "exit"]);
+    names.dedup();
+    let mut suggestions = names
+        .into_iter()
+        .filter_map(|name| {
+            let distance = edit_distance(command, name);
+            let begins = command.len() >= 3 && name.starts_with(command);
+            (distance <= limit || begins).then_some((distance, name))
+        })
+        .collect::<Vec<_>>();
+    suggestions.sort();
+    suggestions
+        .into_iter()
+        .take(3)
+        .map(|(_, name)| name)
+        .collect()
+}
+
+/// How many characters must be inserted, deleted, replaced or swapped with their neighbour to
+/// turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b) = (a.chars().collect::<Vec<_>>(), b.chars().collect::<Vec<_>>());
+    // Rows of the table of distances between prefixes of `a` and `b`, two rows back to now.
+    let mut before = Vec::new();
+    let mut previous = (0..b.len() + 1).collect::<Vec<_>>();
+    for i in 1..a.len() + 1 {
+        let mut current = vec![i; b.len() + 1];
+        for j in 1..b.len() + 1 {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            current[j] = (previous[j] + 1)
+                .min(current[j - 1] + 1)
+                .min(previous[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before[j - 2] + 1);
+            }
+        }
+        before = std::mem::replace(&mut previous, current);
+    }
+    previous[b.len()]
+}
+
+/// Refuses a row whose cells do not match the sheet's columns one for one.
+fn check_row_length(
+    csv_holder: &impl CsvDataHandle,
+    new_data: &[String],
+) -> Result<(), CsvIoError> {
+    let (expected, found) = (csv_holder.column_count(), new_data.len());
+    if found != expected {
+        return Err(CsvIoError::MismatchedColumns { expected, found });
+    }
+    Ok(())
+}
+
+/// Handles `csv_request` on the active buffer, or on the buffers themselves.
+pub fn handle_buffer_request(
+    csv_request: CsvRequest,
+    buffers: &mut Buffers,
+    writer: &mut impl Write,
+) -> Result<(), CsvIoError> {
+    match csv_request {
+        CsvRequest::Open { path, name } => {
+            let format = &buffers.active().options.format;
+            let (data, format) = read_other_csv(File::open(&path)?, format)?;
+            let name = name.unwrap_or_else(|| Buffer::name_for(&path));
+            let options = RequestOptions {
+                format,
+                save_path: Some(path),
+            };
+            buffers.open(Buffer::new(name, data.into(), options))
+        }
+        CsvRequest::ListBuffers => {
+            let active = &buffers.active().name;
+            for buffer in buffers.iter() {
+                let marker = if &buffer.name == active { '*' } else { ' ' };
+                let file = match &buffer.options.save_path {
+                    Some(path) => path.display().to_string(),
+                    None => "no file".to_string(),
+                };
+                write!(
+                    writer,
+                    "{} {}: {}, {} rows, {} columns",
+                    marker,
+                    buffer.name,
+                    file,
+                    buffer.data.row_count(),
+                    buffer.data.column_count()
+                )?;
+                if buffer.has_unsaved_changes() {
+                    write!(writer, ", unsaved changes")?;
+                }
+                writeln!(writer)?;
+            }
+            Ok(())
+        }
+        CsvRequest::Switch(name) => buffers.switch(&name),
+        CsvRequest::Close { name, discard } => buffers.close(&name, discard),
+        CsvRequest::CopyRows {
+            first_row,
+            last_row,
+            to,
+            at,
+        } => {
+            let source = &buffers.active().data;
+            let mut new_data = Vec::new();
+            for row in first_row..=last_row {
+                let cells = source.row_cells(row)?;
+                new_data.push(
+                    cells
+                        .into_iter()
+                        .map(|cell| cell.map(str::to_string))
+                        .collect(),
+                );
+            }
+            let found = source.column_count();
+            let target = buffers.get_mut(&to)?;
+            let expected = target.data.column_count();
+            if found != expected {
+                return Err(CsvIoError::MismatchedColumns { expected, found });
+            }
+            let count = new_data.len();
+            let edit = Edit::InsertRows {
+                row: at.unwrap_or(target.data.row_count() + 1),
+                new_data,
+            };
+            let data = &mut SchemaChecked {
+                data: &mut target.data,
+                schema: &mut target.session.schema,
+            };
+            target.session.history.apply(data, edit)?;
+            writeln!(writer, "{} rows copied to {}", count, to)?;
+            Ok(())
+        }
+        csv_request => {
+            let buffer = buffers.active_mut();
+            handle_sheet_request(
+                csv_request,
+                &mut buffer.data,
+                &mut buffer.session,
+                &buffer.options,
+                writer,
+            )
+        }
+    }
+}
+
+/// Reads a CSV file other than the one loaded at the start, working out its dialect from its
+/// contents but otherwise laid out like `format`.
+fn read_other_csv(file: File, format: &CsvFormat) -> Result<(CsvHolder, CsvFormat), CsvIoError> {
+    let mut reader = BufReader::new(file);
+    let format = CsvFormat {
+        dialect: sniff_dialect(&mut reader)?,
+        ..*format
+    };
+    Ok((read_csv(reader, &format)?, format))
+}
+
+/// Handles `csv_request` on an open sheet, which unlike `handle_csv_request` can switch to
+/// another sheet.
+pub fn handle_sheet_request(
+    csv_request: CsvRequest,
+    sheet: &mut Sheet,
+    session: &mut Session,
+    options: &RequestOptions,
+    writer: &mut impl Write,
+) -> Result<(), CsvIoError> {
+    match csv_request {
+        CsvRequest::Join {
+            path,
+            keys,
+            kind,
+            suffixes,
+        } => {
+            let (other, _) = read_other_csv(File::open(path)?, &options.format)?;
+            let mut columns = Vec::new();
+            for (left, right) in keys {
+                columns.push((left.resolve(sheet)?, right.resolve(&other)?));
+            }
+            let suffixes = (suffixes.0.as_str(), suffixes.1.as_str());
+            let result = join::join(sheet, &other, &columns, kind, suffixes)?;
+            session.result = Some(result.into());
+            session.use_result(sheet)
+        }
+        CsvRequest::UseResult => session.use_result(sheet),
+        CsvRequest::UseOriginal => session.use_original(sheet),
+        csv_request => handle_csv_request(csv_request, sheet, session, options, writer),
+    }
+}
+
+/// Carries out `csv_request` on `csv_holder`, writing anything it shows to `writer`. Edits are
+/// checked against `session.schema` and recorded in `session.history`. Requests that switch to
+/// another sheet or buffer fail here; `handle_sheet_request` and `handle_buffer_request` carry
+/// them out.
 pub fn handle_csv_request(
     csv_request: CsvRequest,
     csv_holder: &mut impl CsvDataHandle,
+    session: &mut Session,
+    options: &RequestOptions,
     writer: &mut impl Write,
 ) -> Result<(), CsvIoError> {
+    let format = &options.format;
+    let history = &mut session.history;
+    let csv_holder = &mut SchemaChecked {
+        data: csv_holder,
+        schema: &mut session.schema,
+    };
     match csv_request {
         CsvRequest::Help => write_help_text(writer),
-        CsvRequest::Display => write_csv(writer, csv_holder),
-        CsvRequest::DisplayRowRange(start, end) => {
-            write_csv_row_range(writer, csv_holder, start, end)
+        CsvRequest::Display(DisplayStyle::Raw) if session.filter.is_none() => {
+            write_csv(writer, csv_holder, format, QuotePolicy::Minimal)
+        }
+        CsvRequest::Display(style) => {
+            let rows = 1..csv_holder.row_count() + 1;
+            display_rows(
+                writer,
+                csv_holder,
+                rows,
+                session.filter.as_ref(),
+                style,
+                format,
+            )
+        }
+        CsvRequest::DisplayRowRange(_, end, _) if end > csv_holder.row_count() => {
+            Err(CsvError::NoSuchRow(end).into())
+        }
+        CsvRequest::DisplayRowRange(start, end, DisplayStyle::Raw) if session.filter.is_none() => {
+            write_csv_row_range(writer, csv_holder, format, start, end)
+        }
+        CsvRequest::DisplayRowRange(start, end, style) => {
+            // `end` is at most the row count here, so it has room for one more.
+            let rows = start..end + 1;
+            display_rows(
+                writer,
+                csv_holder,
+                rows,
+                session.filter.as_ref(),
+                style,
+                format,
+            )
+        }
+        CsvRequest::Filter(filter) => {
+            let rows = 1..csv_holder.row_count() + 1;
+            let style = DisplayStyle::default();
+            display_rows(writer, csv_holder, rows, Some(&filter), style, format)
         }
-        CsvRequest::ModifyRow { row, new_data } => Ok(csv_holder.replace_row(row, new_data)?),
-        CsvRequest::DeleteRow(row) => Ok(csv_holder.delete_row(row)?),
+        CsvRequest::SetFilter(filter) => {
+            let matching = matching_rows(csv_holder, 1..csv_holder.row_count() + 1, &filter)?;
+            writeln!(
+                writer,
+                "{} of {} rows match {}",
+                matching.len(),
+                csv_holder.row_count(),
+                filter
+            )?;
+            session.filter = Some(filter);
+            Ok(())
+        }
+        CsvRequest::ClearFilter => {
+            session.filter = None;
+            Ok(())
+        }
+        CsvRequest::ModifyRow { row, new_data } => {
+            check_row_length(csv_holder, &new_data)?;
+            let new_data = new_data.into_iter().map(Some).collect();
+            Ok(history.apply(csv_holder, Edit::ReplaceRow { row, new_data })?)
+        }
+        CsvRequest::DeleteRow(row) => Ok(history.apply(csv_holder, Edit::DeleteRow(row))?),
         CsvRequest::ModifyColumn { column, new_data } => {
-            Ok(csv_holder.replace_column(column, new_data)?)
+            let new_data = new_data.into_iter().map(Some).collect();
+            Ok(history.apply(csv_holder, Edit::ReplaceColumn { column, new_data })?)
+        }
+        CsvRequest::DeleteColumn(column) => {
+            Ok(history.apply(csv_holder, Edit::DeleteColumn(column))?)
         }
-        CsvRequest::DeleteColumn(column) => Ok(csv_holder.delete_column(column)?),
         CsvRequest::ModifyColumnByName {
             column_name,
             new_data,
-        } => Ok(csv_holder.replace_column_by_field(&column_name, new_data)?),
+        } => {
+            let column = csv_holder.column_of_field(&column_name)?;
+            let new_data = new_data.into_iter().map(Some).collect();
+            Ok(history.apply(csv_holder, Edit::ReplaceColumn { column, new_data })?)
+        }
         CsvRequest::DeleteColumnByName(column_name) => {
-            Ok(csv_holder.delete_column_by_field(&column_name)?)
+            let column = csv_holder.column_of_field(&column_name)?;
+            Ok(history.apply(csv_holder, Edit::DeleteColumn(column))?)
+        }
+        CsvRequest::InsertRow { row, new_data } => {
+            check_row_length(csv_holder, &new_data)?;
+            let new_data = new_data.into_iter().map(Some).collect();
+            Ok(history.apply(csv_holder, Edit::InsertRow { row, new_data })?)
+        }
+        CsvRequest::AppendRow(new_data) => {
+            check_row_length(csv_holder, &new_data)?;
+            let row = csv_holder.row_count() + 1;
+            let new_data = new_data.into_iter().map(Some).collect();
+            Ok(history.apply(csv_holder, Edit::InsertRow { row, new_data })?)
+        }
+        CsvRequest::InsertColumn {
+            column,
+            header,
+            new_data,
+        } => {
+            let new_data = new_data.into_iter().map(Some).collect();
+            let edit = Edit::InsertColumn {
+                column,
+                header,
+                new_data,
+            };
+            Ok(history.apply(csv_holder, edit)?)
+        }
+        CsvRequest::AppendColumn { header, new_data } => {
+            let column = csv_holder.column_count() + 1;
+            let new_data = new_data.into_iter().map(Some).collect();
+            let edit = Edit::InsertColumn {
+                column,
+                header,
+                new_data,
+            };
+            Ok(history.apply(csv_holder, edit)?)
+        }
+        CsvRequest::GroupBy {
+            columns,
+            aggregations,
+        } => {
+            let result = group::group_by(csv_holder, &columns, &aggregations)?;
+            let rows = 1..result.row_count() + 1;
+            display_rows(writer, &result, rows, None, DisplayStyle::default(), format)?;
+            session.result = Some(result.into());
+            Ok(())
+        }
+        CsvRequest::Join { .. }
+        | CsvRequest::UseResult
+        | CsvRequest::UseOriginal
+        | CsvRequest::Open { .. }
+        | CsvRequest::ListBuffers
+        | CsvRequest::Switch(_)
+        | CsvRequest::Close { .. }
+        | CsvRequest::CopyRows { .. } => Err(CsvIoError::CannotSwitchSheets),
+        CsvRequest::AddColumn { header, expression } => {
+            let new_data = expression.evaluate_all(csv_holder)?;
+            let edit = Edit::InsertColumn {
+                column: csv_holder.column_count() + 1,
+                header,
+                new_data,
+            };
+            Ok(history.apply(csv_holder, edit)?)
+        }
+        CsvRequest::UpdateColumn { column, expression } => {
+            let column = column.resolve(csv_holder)?;
+            let new_data = expression.evaluate_all(csv_holder)?;
+            Ok(history.apply(csv_holder, Edit::ReplaceColumn { column, new_data })?)
+        }
+        CsvRequest::Sort(columns) => {
+            let mut keys = Vec::new();
+            for SortColumn {
+                column,
+                descending,
+                mode,
+            } in columns
+            {
+                keys.push(SortKey {
+                    column: column.resolve(csv_holder)?,
+                    descending,
+                    mode,
+                });
+            }
+            let order = csv_holder.sorted_order(&keys)?;
+            Ok(history.apply(csv_holder, Edit::SortRows { keys, order })?)
+        }
+        CsvRequest::Find { pattern, scope } => {
+            let found = search::find(csv_holder, &pattern, &scope)?;
+            for &index in &found {
+                let cell = csv_holder.data_at(index)?;
+                writeln!(writer, "({},{}) {}", index.row, index.column, cell)?;
+            }
+            writeln!(writer, "{} matching cells", found.len())?;
+            Ok(())
+        }
+        CsvRequest::Replace {
+            pattern,
+            replacement,
+            scope,
+            dry_run,
+        } => {
+            let changes = search::replacements(csv_holder, &pattern, &replacement, &scope)?;
+            if dry_run {
+                for (index, new_value) in &changes {
+                    let cell = csv_holder.data_at(*index)?;
+                    writeln!(
+                        writer,
+                        "({},{}) {} -> {}",
+                        index.row, index.column, cell, new_value
+                    )?;
+                }
+                writeln!(writer, "{} cells would change", changes.len())?;
+                return Ok(());
+            }
+            let count = changes.len();
+            if count > 0 {
+                let cells = changes
+                    .into_iter()
+                    .map(|(index, new_value)| (index, Some(new_value)))
+                    .collect();
+                history.apply(csv_holder, Edit::ReplaceCells(cells))?;
+            }
+            writeln!(writer, "{} cells changed", count)?;
+            Ok(())
+        }
+        CsvRequest::Get { row, column } => {
+            let column = column.resolve(csv_holder)?;
+            match csv_holder.cell(Index { row, column })? {
+                Some(value) => writeln!(writer, "{}", value)?,
+                None => writeln!(writer, "<null>")?,
+            }
+            Ok(())
+        }
+        CsvRequest::Set { row, column, value } => {
+            let column = column.resolve(csv_holder)?;
+            let edit = Edit::ReplaceDataAt {
+                index: Index { row, column },
+                new_data: Some(value),
+            };
+            Ok(history.apply(csv_holder, edit)?)
+        }
+        CsvRequest::ShowSchema => {
+            for (i, header) in csv_holder.headers().into_iter().enumerate() {
+                let column_schema = csv_holder.schema.column(i + 1);
+                writeln!(writer, "{}. {}: {}", i + 1, header, column_schema)?;
+            }
+            Ok(())
+        }
+        CsvRequest::SetSchema {
+            column,
+            column_schema,
+        } => {
+            let column = column.resolve(csv_holder)?;
+            Ok(csv_holder
+                .schema
+                .set_column(csv_holder.data, column, column_schema)?)
+        }
+        CsvRequest::Stats { column, top } => {
+            let columns = match column {
+                Some(column) => {
+                    let column = column.resolve(csv_holder)?;
+                    column..column + 1
+                }
+                None => 1..csv_holder.column_count() + 1,
+            };
+            let headers = csv_holder.headers();
+            for column in columns {
+                let stats = ColumnStats::compute(csv_holder, column, top)?;
+                writeln!(writer, "{}. {}", column, headers[column - 1])?;
+                for line in stats.to_string().lines() {
+                    writeln!(writer, "  {}", line)?;
+                }
+            }
+            Ok(())
+        }
+        CsvRequest::Sum(column) => {
+            let column = column.resolve(csv_holder)?;
+            writeln!(writer, "{}", stats::sum(csv_holder, column)?)?;
+            Ok(())
+        }
+        CsvRequest::Average(column) => {
+            let column = column.resolve(csv_holder)?;
+            match stats::average(csv_holder, column)? {
+                Some(average) => writeln!(writer, "{}", average)?,
+                None => writeln!(writer, "<null>")?,
+            }
+            Ok(())
+        }
+        CsvRequest::Count(column) => {
+            let count = match column {
+                Some(column) => stats::count(csv_holder, column.resolve(csv_holder)?)?,
+                None => csv_holder.row_count(),
+            };
+            writeln!(writer, "{}", count)?;
+            Ok(())
         }
         CsvRequest::Dimensions => {
             let rows = csv_holder.row_count();
@@ -120,23 +1498,875 @@ pub fn handle_csv_request(
             writeln!(writer, "Rows: {}, Columns: {}", rows, columns)?;
             Ok(())
         }
-        CsvRequest::WriteToFile(mut file) => write_csv(&mut file, csv_holder),
-        CsvRequest::DisplayHeaders => write_headers(writer, csv_holder),
+        // The save path belongs to the original sheet, which the result would overwrite.
+        CsvRequest::WriteToFile(path, _)
+            if session.original.is_some() && options.save_path.as_ref() == Some(&path) =>
+        {
+            Err(CsvIoError::ResultNeedsOwnFile)
+        }
+        CsvRequest::WriteToFile(path, policy) => write_csv_file(&path, csv_holder, format, policy),
+        CsvRequest::Save if session.original.is_some() => Err(CsvIoError::ResultNeedsOwnFile),
+        CsvRequest::Save => {
+            let save_path = options.save_path.as_ref().ok_or(CsvIoError::NoOutputFile)?;
+            write_csv_file(save_path, csv_holder, format, QuotePolicy::default())
+        }
+        CsvRequest::DisplayHeaders => write_headers(writer, csv_holder, format),
+        // Leaving is up to whoever is reading the requests.
+        CsvRequest::Quit => Ok(()),
+        CsvRequest::Undo => {
+            match history.undo(csv_holder)? {
+                Some(edit) => writeln!(writer, "Undid: {}", edit)?,
+                None => writeln!(writer, "Nothing to undo")?,
+            }
+            Ok(())
+        }
+        CsvRequest::Redo => {
+            match history.redo(csv_holder)? {
+                Some(edit) => writeln!(writer, "Redid: {}", edit)?,
+                None => writeln!(writer, "Nothing to redo")?,
+            }
+            Ok(())
+        }
+        CsvRequest::History => {
+            for (i, edit) in history.undoable().enumerate() {
+                writeln!(writer, "{}. {}", i + 1, edit)?;
+            }
+            for edit in history.redoable() {
+                writeln!(writer, "   {} (undone)", edit)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Shows those of `rows` that `filter` accepts, followed in a table by how many of them that was.
+fn display_rows(
+    writer: &mut impl Write,
+    csv_holder: &impl CsvDataHandle,
+    rows: std::ops::Range<usize>,
+    filter: Option<&Expression>,
+    style: DisplayStyle,
+    format: &CsvFormat,
+) -> Result<(), CsvIoError> {
+    let considered = rows.len();
+    let rows = match filter {
+        Some(filter) => matching_rows(csv_holder, rows, filter)?,
+        None => rows.collect(),
+    };
+    match style {
+        DisplayStyle::Raw => {
+            if format.has_header {
+                write_headers(writer, csv_holder, format)?;
+            }
+            write_csv_rows(writer, csv_holder, format, rows)
+        }
+        DisplayStyle::Table { max_column_width } => {
+            let shown = rows.len();
+            write_table(writer, csv_holder, rows, max_column_width)?;
+            if let Some(filter) = filter {
+                writeln!(writer, "{} of {} rows match {}", shown, considered, filter)?;
+            }
+            Ok(())
+        }
     }
 }
 
+fn matching_rows(
+    csv_holder: &impl CsvDataHandle,
+    rows: std::ops::Range<usize>,
+    filter: &Expression,
+) -> CsvResult<Vec<usize>> {
+    let mut matching = Vec::new();
+    for row in rows {
+        if filter.accepts(csv_holder, row)? {
+            matching.push(row);
+        }
+    }
+    Ok(matching)
+}
+
+/// The syntax of every command, as `help` lists it, under the command's name.
+const COMMAND_SYNTAX: [(&str, &str); 45] = [
+    ("display", "display [--raw] [--max-width <width>]"),
+    ("display_row_range", "display_row_range <first_row> <last_row> [--raw] [--max-width <width>]"),
+    ("filter", "filter <expression>"),
+    ("set_filter", "set_filter <expression>"),
+    ("clear_filter", "clear_filter"),
+    ("modify_row", "modify_row <row> <new_data>"),
+    ("delete_row", "delete_row <row>"),
+    ("modify_column", "modify_column <column> <new_data>"),
+    ("delete_column", "delete_column <column>"),
+    ("modify_column_by_name", "modify_column_by_name <column_name> <new_data>"),
+    ("delete_column_by_name", "delete_column_by_name <column_name>"),
+    ("insert_row", "insert_row <row> <new_data>"),
+    ("append_row", "append_row <new_data>"),
+    ("insert_column", "insert_column <column> <header> <new_data>"),
+    ("append_column", "append_column <header> <new_data>"),
+    ("add_column", "add_column <header> = <expression>"),
+    ("update_column", "update_column <column> = <expression>"),
+    ("sort", "sort <column> [asc|desc] [lexical|numeric|natural|date] [, <column> ...]"),
+    ("find", "find [--column <column>] [--rows <first>-<last>] [--regex] [--ignore-case] <pattern>"),
+    ("replace", "replace [--column <column>] [--rows <first>-<last>] [--regex] [--ignore-case] [--dry-run] <pattern> <replacement>"),
+    ("get", "get <row> <column> | get <cell>"),
+    ("set", "set <row> <column> <value> | set <cell> <value>"),
+    ("schema", "schema"),
+    ("schema", "schema <column> integer|float|bool|date|string|enum[?] [<enum value> ...]"),
+    ("group_by", "group_by <column>[, <column> ...] agg <function>(<column>) ..."),
+    ("join", "join <file> on <column>=<other column>[, ...] [inner|left|right|outer] [--suffixes <left> <right>]"),
+    ("use_result", "use_result"),
+    ("use_original", "use_original"),
+    ("open", "open <file> [as <name>]"),
+    ("buffers", "buffers"),
+    ("switch", "switch <name>"),
+    ("close", "close <name> [--discard]"),
+    ("copy_rows", "copy_rows <first_row> <last_row> to <name> [at <row>]"),
+    ("stats", "stats [<column>] [--top <n>]"),
+    ("sum", "sum <column>"),
+    ("avg", "avg <column>"),
+    ("count", "count [<column>]"),
+    ("dimensions", "dimensions"),
+    ("write_to_file", "write_to_file <file_name> [minimal|always|non_numeric|never]"),
+    ("display_headers", "display_headers"),
+    ("save", "save"),
+    ("undo", "undo"),
+    ("redo", "redo"),
+    ("history", "history"),
+    ("quit", "quit"),
+];
+
+/// Lists every command along with its syntax.
 pub fn write_help_text(writer: &mut impl Write) -> Result<(), CsvIoError> {
-    writeln!(writer, "display")?;
-    writeln!(writer, "display_row_range <first_row> <last_row>")?;
-    writeln!(writer, "modify_row <row> <new_data>")?;
-    writeln!(writer, "delete_row <row>")?;
-    writeln!(writer, "modify_column <column> <new_data>")?;
-    writeln!(writer, "delete_column <column>")?;
-    writeln!(writer, "modify_column_by_name <column_name> <new_data>")?;
-    writeln!(writer, "delete_column_by_name <column_name>")?;
-    writeln!(writer, "dimensions")?;
-    writeln!(writer, "write_to_file <file_name>")?;
-    writeln!(writer, "display_headers")?;
-    writeln!(writer, "CTRL+C to quit")?;
+    for (_, syntax) in COMMAND_SYNTAX {
+        writeln!(writer, "{}", syntax)?;
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dialect::Dialect;
+
+    #[test]
+    fn test_saves_data() {
+        let format = CsvFormat::default();
+        let saves = |line, save_path: Option<&str>| {
+            let request = parse_csv_request(line, &format).unwrap();
+            request.saves_data(save_path.map(Path::new))
+        };
+        assert!(saves("save", Some("data.csv")));
+        assert!(saves("save", None));
+        assert!(saves("write_to_file data.csv", Some("data.csv")));
+        assert!(!saves("write_to_file copy.csv", Some("data.csv")));
+        assert!(!saves("write_to_file copy.csv", None));
+        assert!(!saves("delete_row 1", Some("data.csv")));
+    }
+
+    #[test]
+    fn test_parse_display_style() {
+        assert_eq!(parse_display_style(""), Ok(DisplayStyle::default()));
+        assert_eq!(parse_display_style("--raw"), Ok(DisplayStyle::Raw));
+        assert_eq!(
+            parse_display_style("--max-width 12"),
+            Ok(DisplayStyle::Table {
+                max_column_width: 12
+            })
+        );
+        assert_eq!(
+            parse_display_style("--max-width"),
+            Err(RequestErrorKind::MissingArgument("<width>"))
+        );
+        assert_eq!(
+            parse_display_style("--wide"),
+            Err(RequestErrorKind::UnexpectedArgument("--wide".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_data_follows_dialect() {
+        let format = CsvFormat::default();
+        let Ok(CsvRequest::AppendRow(new_data)) =
+            parse_csv_request("append_row Brendan,\"Dublin, Ireland\"", &format)
+        else {
+            panic!("append_row did not parse");
+        };
+        assert_eq!(new_data, vec!["Brendan", "Dublin, Ireland"]);
+        assert!(parse_csv_request("append_row a,\"b", &format).is_err());
+
+        let format = CsvFormat {
+            dialect: Dialect::from_name("semicolon").unwrap(),
+            ..CsvFormat::default()
+        };
+        let Ok(CsvRequest::ModifyRow { row, new_data }) =
+            parse_csv_request("modify_row 2 1,5;2,5", &format)
+        else {
+            panic!("modify_row did not parse");
+        };
+        assert_eq!(row, 2);
+        assert_eq!(new_data, vec!["1,5", "2,5"]);
+    }
+
+    #[test]
+    fn test_parse_insert_and_append() {
+        let format = CsvFormat::default();
+        let Ok(CsvRequest::InsertRow { row, new_data }) =
+            parse_csv_request("insert_row 3 a,\"b,c\"", &format)
+        else {
+            panic!("insert_row did not parse");
+        };
+        assert_eq!(row, 3);
+        assert_eq!(new_data, vec!["a", "b,c"]);
+        let Ok(CsvRequest::AppendRow(new_data)) = parse_csv_request("append_row a,,c", &format)
+        else {
+            panic!("append_row did not parse");
+        };
+        assert_eq!(new_data, vec!["a", "", "c"]);
+        let Ok(CsvRequest::InsertColumn {
+            column,
+            header,
+            new_data,
+        }) = parse_csv_request("insert_column 1 total 1,2", &format)
+        else {
+            panic!("insert_column did not parse");
+        };
+        assert_eq!((column, header.as_str()), (1, "total"));
+        assert_eq!(new_data, vec!["1", "2"]);
+        let Ok(CsvRequest::AppendColumn { header, new_data }) =
+            parse_csv_request("append_column total 1,2", &format)
+        else {
+            panic!("append_column did not parse");
+        };
+        assert_eq!(header, "total");
+        assert_eq!(new_data, vec!["1", "2"]);
+
+        let error = |request| {
+            parse_csv_request(request, &format)
+                .err()
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(error("insert_row"), "insert_row: missing <row>");
+        assert_eq!(error("insert_row 2"), "insert_row: missing <new_data>");
+        assert_eq!(
+            error("insert_row -1 a,b"),
+            "insert_row: <row> must be a number, not \"-1\""
+        );
+        assert_eq!(
+            error("insert_row a,b"),
+            "insert_row: <row> must be a number, not \"a,b\""
+        );
+        assert_eq!(error("append_row"), "append_row: missing <new_data>");
+        assert_eq!(error("insert_column"), "insert_column: missing <column>");
+        assert_eq!(error("insert_column 2"), "insert_column: missing <header>");
+        assert_eq!(
+            error("insert_column 2 total"),
+            "insert_column: missing <new_data>"
+        );
+        assert_eq!(
+            error("insert_column total 1,2"),
+            "insert_column: <column> must be a number, not \"total\""
+        );
+        assert_eq!(error("append_column"), "append_column: missing <header>");
+        assert_eq!(
+            error("append_column total"),
+            "append_column: missing <new_data>"
+        );
+    }
+
+    #[test]
+    fn test_request_errors() {
+        let format = CsvFormat::default();
+        let error = |request| parse_csv_request(request, &format).err().unwrap();
+        assert_eq!(
+            error("dispaly").to_string(),
+            "unknown command \"dispaly\"; did you mean \"display\"?"
+        );
+        assert_eq!(
+            error("modify 1 a,b").to_string(),
+            "unknown command \"modify\"; did you mean \"modify_row\", \"modify_column\" or \"modify_column_by_name\"?"
+        );
+        assert_eq!(
+            error("frobnicate").to_string(),
+            "unknown command \"frobnicate\""
+        );
+        assert!(error("frobnicate").usage().is_empty());
+        assert_eq!(error("").to_string(), "no command given");
+
+        let set = error("set 2");
+        assert_eq!(set.to_string(), "set: missing <column>");
+        assert_eq!(
+            set.usage(),
+            ["set <row> <column> <value> | set <cell> <value>"]
+        );
+        assert_eq!(error("schema 2").usage().len(), 2);
+        assert_eq!(error("help me").to_string(), "help: unexpected \"me\"");
+        assert_eq!(
+            error("delete_row 2x").to_string(),
+            "delete_row: <row> must be a number, not \"2x\""
+        );
+        assert_eq!(
+            error("append_row a,\"b").to_string(),
+            "append_row: invalid <new_data>: quoted field is never closed at character 3"
+        );
+        assert_eq!(error("sort price, ").to_string(), "sort: missing <column>");
+        assert!(error("filter price >")
+            .to_string()
+            .starts_with("filter: invalid expression: "));
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("display", "display"), 0);
+        assert_eq!(edit_distance("dispaly", "display"), 1);
+        assert_eq!(edit_distance("sotr", "sort"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "avg"), 3);
+        assert_eq!(suggest_commands("exti"), ["exit"]);
+        assert_eq!(suggest_commands("st"), ["set"]);
+    }
+
+    #[test]
+    fn test_row_length() {
+        let mut csv_holder =
+            crate::csv_holder::CsvHolder::from_strs(&["a", "b", "c"], &[&["1", "2", "3"]], &[]);
+        let mut session = Session::new(&csv_holder);
+        let options = RequestOptions::default();
+        for request in ["modify_row 1 x,y", "insert_row 1 x,y", "append_row x,y,z,w"] {
+            let request = parse_csv_request(request, &options.format).unwrap();
+            let error = handle_csv_request(
+                request,
+                &mut csv_holder,
+                &mut session,
+                &options,
+                &mut std::io::sink(),
+            )
+            .unwrap_err();
+            assert!(matches!(
+                error,
+                CsvIoError::MismatchedColumns { expected: 3, .. }
+            ));
+        }
+        assert_eq!(csv_holder.row_count(), 1);
+    }
+
+    #[test]
+    fn test_filter_view() {
+        let mut csv_holder = crate::csv_holder::CsvHolder::from_strs(
+            &["job", "status"],
+            &[
+                &["build", "failed"],
+                &["test", "passed"],
+                &["deploy", "failed"],
+            ],
+            &[],
+        );
+        let mut session = Session::new(&csv_holder);
+        let options = RequestOptions::default();
+        let mut run = |request: &str| {
+            let request = parse_csv_request(request, &options.format).unwrap();
+            let mut written = Vec::new();
+            handle_csv_request(
+                request,
+                &mut csv_holder,
+                &mut session,
+                &options,
+                &mut written,
+            )
+            .unwrap();
+            String::from_utf8(written).unwrap()
+        };
+        assert_eq!(
+            run("set_filter status = 'failed'"),
+            "2 of 3 rows match status = 'failed'\n"
+        );
+        assert_eq!(
+            run("display --raw"),
+            "job,status\nbuild,failed\ndeploy,failed\n"
+        );
+        assert_eq!(
+            run("display_row_range 2 3 --raw"),
+            "job,status\ndeploy,failed\n"
+        );
+        assert!(
+            run("display").ends_with(" 3 | deploy | failed\n2 of 3 rows match status = 'failed'\n")
+        );
+        assert!(run("filter job contains 'e'").contains("2 of 3 rows match"));
+        run("clear_filter");
+        assert_eq!(
+            run("display_row_range 1 2 --raw"),
+            "build,failed\ntest,passed\n"
+        );
+    }
+
+    #[test]
+    fn test_row_range_ending_at_largest_index() {
+        let mut csv_holder = CsvHolder::from_strs(&["n"], &[&["1"], &["2"]], &[]);
+        let mut session = Session::new(&csv_holder);
+        let options = RequestOptions::default();
+        for style in ["", " --raw"] {
+            let request = parse_csv_request(
+                &format!("display_row_range 1 {}{}", usize::MAX, style),
+                &options.format,
+            )
+            .unwrap();
+            let result = handle_csv_request(
+                request,
+                &mut csv_holder,
+                &mut session,
+                &options,
+                &mut Vec::new(),
+            );
+            assert!(matches!(
+                result,
+                Err(CsvIoError::Data(CsvError::NoSuchRow(usize::MAX)))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_computed_columns() {
+        let mut csv_holder = crate::csv_holder::CsvHolder::from_strs(
+            &["price", "quantity"],
+            &[&["2.5", "4"], &["3", "x"]],
+            &[],
+        );
+        let mut session = Session::new(&csv_holder);
+        let options = RequestOptions::default();
+        let mut run = |request: &str| {
+            let request = parse_csv_request(request, &options.format).unwrap();
+            let mut written = Vec::new();
+            handle_csv_request(
+                request,
+                &mut csv_holder,
+                &mut session,
+                &options,
+                &mut written,
+            )
+            .map(|_| String::from_utf8(written).unwrap())
+            .map_err(|error| error.to_string())
+        };
+        assert_eq!(
+            run("add_column total = price * quantity").unwrap_err(),
+            "Row 2: \"x\" is not a number"
+        );
+        run("set 2 quantity 2").unwrap();
+        run("add_column total = price * quantity").unwrap();
+        run("update_column 1 = round(price)").unwrap();
+        assert_eq!(
+            run("display --raw").unwrap(),
+            "price,quantity,total\n3,4,10\n3,2,6\n"
+        );
+        run("undo").unwrap();
+        assert_eq!(
+            run("display --raw").unwrap(),
+            "price,quantity,total\n2.5,4,10\n3,2,6\n"
+        );
+        assert!(parse_csv_request("add_column = 1", &options.format).is_err());
+        assert!(parse_csv_request("update_column total", &options.format).is_err());
+    }
+
+    #[test]
+    fn test_parse_stats() {
+        let format = CsvFormat::default();
+        let Ok(CsvRequest::Stats { column, top }) = parse_csv_request("stats", &format) else {
+            panic!("stats did not parse");
+        };
+        assert_eq!((column, top), (None, DEFAULT_TOP_VALUES));
+        let Ok(CsvRequest::Stats { column, top }) =
+            parse_csv_request("stats --top 2 'last run'", &format)
+        else {
+            panic!("stats with a column did not parse");
+        };
+        assert_eq!(
+            (column, top),
+            (Some(ColumnRef::Name("last run".to_string())), 2)
+        );
+        assert!(parse_csv_request("stats a b", &format).is_err());
+        assert!(parse_csv_request("stats --top", &format).is_err());
+        let Ok(CsvRequest::Sum(column)) = parse_csv_request("sum unit price", &format) else {
+            panic!("sum did not parse");
+        };
+        assert_eq!(column, ColumnRef::Name("unit price".to_string()));
+        let Ok(CsvRequest::Count(column)) = parse_csv_request("count 3", &format) else {
+            panic!("count did not parse");
+        };
+        assert_eq!(column, Some(ColumnRef::Index(3)));
+        assert!(parse_csv_request("avg  ", &format).is_err());
+    }
+
+    #[test]
+    fn test_parse_aggregations() {
+        let aggregation = |function, column: Option<&str>| Aggregation {
+            function,
+            column: column.map(ColumnRef::parse),
+        };
+        assert_eq!(
+            parse_aggregations(" sum(unit price), count() count(*) concat(rep, \"; \") min( 2 )"),
+            Ok(vec![
+                aggregation(AggregateFunction::Sum, Some("unit price")),
+                aggregation(AggregateFunction::Count, None),
+                aggregation(AggregateFunction::Count, None),
+                aggregation(AggregateFunction::Concat("; ".to_string()), Some("rep")),
+                aggregation(AggregateFunction::Min, Some("2")),
+            ])
+        );
+        assert_eq!(
+            parse_aggregations("concat(rep, ')')").unwrap()[0].function,
+            { AggregateFunction::Concat(")".to_string()) }
+        );
+        assert!(parse_aggregations("").is_err());
+        assert!(parse_aggregations("median(amount)").is_err());
+        assert!(parse_aggregations("sum()").is_err());
+        assert!(parse_aggregations("sum(amount").is_err());
+    }
+
+    #[test]
+    fn test_group_by_sheets() {
+        let mut sheet: Sheet = crate::csv_holder::CsvHolder::from_strs(
+            &["region", "amount"],
+            &[&["north", "1"], &["south", "2"], &["north", "3"]],
+            &[],
+        )
+        .into();
+        let mut session = Session::new(&sheet);
+        let options = RequestOptions {
+            save_path: Some("never-written.csv".into()),
+            ..RequestOptions::default()
+        };
+        let mut run = |request: &str| {
+            let request = parse_csv_request(request, &options.format).unwrap();
+            let mut written = Vec::new();
+            handle_sheet_request(request, &mut sheet, &mut session, &options, &mut written)
+                .map(|_| String::from_utf8(written).unwrap())
+                .map_err(|error| error.to_string())
+        };
+        assert!(run("use_result").is_err());
+        assert!(run("use_original").is_err());
+        assert!(run("group_by region agg sum(amount)")
+            .unwrap()
+            .contains(" 1 | north  | 4\n"));
+        run("use_result").unwrap();
+        run("set 1 2 5").unwrap();
+        assert_eq!(
+            run("display --raw").unwrap(),
+            "region,sum(amount)\nnorth,5\nsouth,2\n"
+        );
+        let needs_own_file = Err(CsvIoError::ResultNeedsOwnFile.to_string());
+        assert_eq!(run("save"), needs_own_file);
+        assert_eq!(run("write_to_file never-written.csv"), needs_own_file);
+        run("use_original").unwrap();
+        assert_eq!(run("count").unwrap(), "3\n");
+        run("use_result").unwrap();
+        assert_eq!(run("get 1 2").unwrap(), "5\n");
+    }
+
+    #[test]
+    fn test_join() {
+        let dir = std::env::temp_dir().join(format!("join-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("regions.csv");
+        std::fs::write(&path, "code;region name;rank\nN;North;1\nS;South;2\n").unwrap();
+        let path = path.display().to_string();
+
+        let format = CsvFormat::default();
+        let request = format!(
+            "join '{}' on region = code, rank=rank left --suffixes _a _b",
+            path
+        );
+        let Ok(CsvRequest::Join {
+            path: parsed_path,
+            keys,
+            kind,
+            suffixes,
+        }) = parse_csv_request(&request, &format)
+        else {
+            panic!("join did not parse");
+        };
+        assert_eq!(parsed_path, PathBuf::from(&path));
+        assert_eq!(
+            keys,
+            [
+                (ColumnRef::parse("region"), ColumnRef::parse("code")),
+                (ColumnRef::parse("rank"), ColumnRef::parse("rank")),
+            ]
+        );
+        assert_eq!(kind, JoinKind::Left);
+        assert_eq!(suffixes, ("_a".to_string(), "_b".to_string()));
+        assert!(parse_csv_request(&format!("join '{}' on", path), &format).is_err());
+        assert!(parse_csv_request(&format!("join '{}' region=code", path), &format).is_err());
+
+        let mut sheet: Sheet = crate::csv_holder::CsvHolder::from_strs(
+            &["rep", "region"],
+            &[&["ann", "S"], &["bob", "W"]],
+            &[],
+        )
+        .into();
+        let mut session = Session::new(&sheet);
+        let options = RequestOptions::default();
+        let missing = parse_csv_request("join missing.csv on a=b", &format).unwrap();
+        assert!(matches!(
+            handle_sheet_request(missing, &mut sheet, &mut session, &options, &mut Vec::new()),
+            Err(CsvIoError::IoError(_))
+        ));
+        let request = parse_csv_request(&format!("join '{}' on region=code", path), &format);
+        handle_sheet_request(
+            request.unwrap(),
+            &mut sheet,
+            &mut session,
+            &options,
+            &mut Vec::new(),
+        )
+        .unwrap();
+        assert_eq!(sheet.headers(), ["rep", "region", "region name", "rank"]);
+        assert_eq!(sheet.row(1).unwrap(), ["ann", "S", "South", "2"]);
+        assert_eq!(sheet.row_count(), 1);
+        session.use_original(&mut sheet).unwrap();
+        assert_eq!(sheet.row_count(), 2);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_buffers() {
+        let dir = std::env::temp_dir().join(format!("buffers-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("extra.csv");
+        std::fs::write(&path, "x;y\n1;2\n").unwrap();
+        let path = path.display().to_string();
+
+        let format = CsvFormat::default();
+        assert!(matches!(
+            parse_csv_request("open 'my file.csv' as other", &format),
+            Ok(CsvRequest::Open { path, name: Some(name) })
+                if path == std::path::Path::new("my file.csv") && name == "other"
+        ));
+        assert!(parse_csv_request("open a.csv other", &format).is_err());
+        assert!(matches!(
+            parse_csv_request("close other --discard", &format),
+            Ok(CsvRequest::Close { name, discard: true }) if name == "other"
+        ));
+        assert!(matches!(
+            parse_csv_request("copy_rows 1 2 to other at 3", &format),
+            Ok(CsvRequest::CopyRows {
+                first_row: 1,
+                last_row: 2,
+                to,
+                at: Some(3),
+            }) if to == "other"
+        ));
+        assert!(parse_csv_request("copy_rows 1 2 other", &format).is_err());
+        assert!(parse_csv_request("switch a b", &format).is_err());
+
+        let csv_holder = crate::csv_holder::CsvHolder::from_strs(
+            &["a", "b"],
+            &[&["1", "2"], &["3", "4"], &["5", "six"]],
+            &[],
+        );
+        let mut buffers = Buffers::new(Buffer::new(
+            "main".to_string(),
+            csv_holder.into(),
+            RequestOptions::default(),
+        ));
+        let run = |request: &str, buffers: &mut Buffers| {
+            let request = parse_csv_request(request, &format).unwrap();
+            let mut out = Vec::new();
+            handle_buffer_request(request, buffers, &mut out)
+                .map(|()| String::from_utf8(out).unwrap())
+        };
+        run(&format!("open '{}'", path), &mut buffers).unwrap();
+        assert_eq!(buffers.active().name, "extra");
+        assert_eq!(buffers.active().data.row(1).unwrap(), ["1", "2"]);
+        run("switch main", &mut buffers).unwrap();
+        // The last row does not fit the schema of extra, so none of them are copied.
+        assert!(matches!(
+            run("copy_rows 2 3 to extra", &mut buffers),
+            Err(CsvIoError::Data(CsvError::SchemaViolation { .. }))
+        ));
+        assert_eq!(buffers.get_mut("extra").unwrap().data.row_count(), 1);
+        assert_eq!(
+            run("copy_rows 2 2 to extra at 1", &mut buffers).unwrap(),
+            "1 rows copied to extra\n"
+        );
+        assert_eq!(
+            run("buffers", &mut buffers).unwrap(),
+            format!(
+                "* main: no file, 3 rows, 2 columns\n  extra: {}, 2 rows, 2 columns, unsaved changes\n",
+                path
+            )
+        );
+        assert!(run("close extra", &mut buffers).is_err());
+        run("switch extra", &mut buffers).unwrap();
+        assert_eq!(buffers.active().data.row(1).unwrap(), ["3", "4"]);
+        run("undo", &mut buffers).unwrap();
+        assert_eq!(buffers.active().data.row_count(), 1);
+        run("close extra --discard", &mut buffers).unwrap();
+        assert_eq!(buffers.active().name, "main");
+        assert!(run("close main", &mut buffers).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_sort_column() {
+        assert_eq!(
+            parse_sort_column(" 2 "),
+            Ok(SortColumn {
+                column: ColumnRef::Index(2),
+                descending: false,
+                mode: SortMode::Lexical,
+            })
+        );
+        assert_eq!(
+            parse_sort_column("last run desc date"),
+            Ok(SortColumn {
+                column: ColumnRef::Name("last run".to_string()),
+                descending: true,
+                mode: SortMode::Date,
+            })
+        );
+        assert_eq!(
+            parse_sort_column("desc asc"),
+            Ok(SortColumn {
+                column: ColumnRef::Name("desc".to_string()),
+                descending: false,
+                mode: SortMode::Lexical,
+            })
+        );
+        let missing = Err(RequestErrorKind::MissingArgument("<column>"));
+        assert_eq!(parse_sort_column("numeric"), missing);
+        assert_eq!(parse_sort_column(""), missing);
+    }
+
+    #[test]
+    fn test_split_words() {
+        assert_eq!(
+            split_words(r#" find  "New York" 'it\'s' a\"b "" "#),
+            Ok(vec![
+                "find".to_string(),
+                "New York".to_string(),
+                "it's".to_string(),
+                "a\"b".to_string(),
+                "".to_string()
+            ])
+        );
+        assert_eq!(split_words("'open"), Err(RequestErrorKind::UnclosedQuote));
+    }
+
+    #[test]
+    fn test_parse_search() {
+        let (scope, pattern, rest, dry_run) = parse_search(
+            "--column city --rows 2-5 --ignore-case dublin 'Dublin, Ireland'",
+            &["<pattern>", "<replacement>"],
+        )
+        .unwrap();
+        assert_eq!(
+            scope,
+            Scope {
+                column: Some(ColumnRef::Name("city".to_string())),
+                rows: Some((2, 5)),
+            }
+        );
+        assert!(pattern.is_match("DUBLIN"));
+        assert_eq!(rest, vec!["Dublin, Ireland"]);
+        assert!(!dry_run);
+
+        let (_, pattern, _, dry_run) = parse_search(
+            "--dry-run --regex -- --x+ y",
+            &["<pattern>", "<replacement>"],
+        )
+        .unwrap();
+        assert!(pattern.is_match("--xx"));
+        assert!(dry_run);
+
+        use RequestErrorKind::*;
+        let error = |args| parse_search(args, &["<pattern>", "<replacement>"]).err();
+        assert_eq!(error("a"), Some(MissingArgument("<replacement>")));
+        assert_eq!(error("a b c"), Some(UnexpectedArgument("c".to_string())));
+        assert_eq!(error("'' b"), Some(MissingArgument("<pattern>")));
+        assert!(matches!(error("--regex ( b"), Some(InvalidPattern(_))));
+        let rows = InvalidArgument {
+            argument: "<first>-<last>",
+            found: "3".to_string(),
+        };
+        assert_eq!(error("--rows 3 a b"), Some(rows));
+        let everywhere = UnexpectedArgument("--everywhere".to_string());
+        assert_eq!(error("--everywhere a b"), Some(everywhere));
+    }
+
+    #[test]
+    fn test_parse_schema() {
+        let format = CsvFormat::default();
+        let Ok(CsvRequest::SetSchema {
+            column,
+            column_schema,
+        }) = parse_csv_request("schema status enum? open 'in progress'", &format)
+        else {
+            panic!("schema did not parse");
+        };
+        assert_eq!(column, ColumnRef::Name("status".to_string()));
+        assert_eq!(
+            column_schema,
+            ColumnSchema {
+                column_type: ColumnType::Enum(vec!["open".to_string(), "in progress".to_string()]),
+                nullable: true,
+            }
+        );
+        assert!(parse_csv_request("schema 2 integer", &format).is_ok());
+        let error = |request| parse_csv_request(request, &format).err().unwrap().kind;
+        assert_eq!(
+            error("schema 2 integer 5"),
+            RequestErrorKind::UnexpectedArgument("5".to_string())
+        );
+        assert_eq!(
+            error("schema 2 enum"),
+            RequestErrorKind::MissingArgument("<enum value>")
+        );
+        assert_eq!(
+            error("schema 2 money"),
+            RequestErrorKind::InvalidArgument {
+                argument: "<type>",
+                found: "money".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_cell_reference() {
+        assert_eq!(
+            parse_cell_reference("5 3", false),
+            Ok((5, ColumnRef::Index(3), None))
+        );
+        assert_eq!(
+            parse_cell_reference("5 city", false),
+            Ok((5, ColumnRef::Name("city".to_string()), None))
+        );
+        assert_eq!(
+            parse_cell_reference("C5", false),
+            Ok((5, ColumnRef::Index(3), None))
+        );
+        assert_eq!(
+            parse_cell_reference("C5 hello world", true),
+            Ok((5, ColumnRef::Index(3), Some("hello world")))
+        );
+        assert_eq!(
+            parse_cell_reference("5 city Dublin, Ireland", true),
+            Ok((
+                5,
+                ColumnRef::Name("city".to_string()),
+                Some("Dublin, Ireland")
+            ))
+        );
+        use RequestErrorKind::*;
+        let error = |args, with_value| parse_cell_reference(args, with_value).err();
+        assert_eq!(error("C5", true), Some(MissingArgument("<value>")));
+        assert_eq!(error("5 3", true), Some(MissingArgument("<value>")));
+        assert_eq!(error("5", false), Some(MissingArgument("<column>")));
+        assert_eq!(
+            error("5 3 x", false),
+            Some(UnexpectedArgument("x".to_string()))
+        );
+        let not_a_number = NotANumber {
+            argument: "<row>",
+            found: "x".to_string(),
+        };
+        assert_eq!(error("x 3", false), Some(not_a_number));
+    }
+}
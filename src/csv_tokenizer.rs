@@ -0,0 +1,551 @@
+use crate::{
+    csv_io::CsvIoError,
+    dialect::{Dialect, EscapeStyle},
+    parse_error::{ParseError, ParseErrorKind, Position},
+};
+use std::io::BufRead;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Quoted fields, doubled quotes and multi-line records as described in RFC 4180.
+    Rfc4180,
+    /// Every line is a record and every delimiter separates a field; quotes are kept verbatim.
+    Raw,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    pub text: String,
+    pub quoted: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    FieldStart,
+    Unquoted,
+    Quoted,
+    QuoteInQuoted,
+    EscapeInQuoted,
+}
+
+pub struct RecordReader<R: BufRead> {
+    reader: R,
+    mode: ParseMode,
+    dialect: Dialect,
+    /// The first line of the last record read, with its line ending.
+    line: String,
+    /// Where the last record read starts.
+    record_start: Position,
+    lines_read: usize,
+    bytes_read: usize,
+}
+
+impl<R: BufRead> RecordReader<R> {
+    pub fn new(reader: R, mode: ParseMode, dialect: Dialect) -> Self {
+        Self {
+            reader,
+            mode,
+            dialect,
+            line: String::new(),
+            record_start: Position::default(),
+            lines_read: 0,
+            bytes_read: 0,
+        }
+    }
+
+    pub fn next_record(&mut self) -> Result<Option<Vec<Field>>, CsvIoError> {
+        let mut line = std::mem::take(&mut self.line);
+        line.clear();
+        self.record_start = self.position();
+        let record = match self.read_line(&mut line)? {
+            false => Ok(None),
+            true => match self.mode {
+                ParseMode::Raw => Ok(Some(
+                    strip_line_ending(&line)
+                        .split(self.dialect.delimiter)
+                        .map(|s| Field {
+                            text: s.to_string(),
+                            quoted: false,
+                        })
+                        .collect(),
+                )),
+                ParseMode::Rfc4180 => self.parse_quoted_record(&line).map(Some),
+            },
+        };
+        self.line = line;
+        record
+    }
+
+    /// The error for the last record read having `found` fields rather than `expected`, pointing
+    /// at the first field too many or at the end of the line.
+    pub fn field_count_error(&self, expected: usize, found: usize) -> CsvIoError {
+        let line = strip_line_ending(&self.line);
+        let error = match scan_record(line, 0, self.mode, self.dialect) {
+            Ok(Some((spans, _))) => field_count_error(line, &spans, expected, self.dialect),
+            // The record goes on past its first line.
+            _ => {
+                let kind = ParseErrorKind::FieldCount { expected, found };
+                ParseError::at(kind, line, line.len())
+            }
+        };
+        CsvIoError::Parse(error.offset_by(self.record_start))
+    }
+
+    /// The error for there being nothing more to read.
+    pub fn empty_error(&self) -> CsvIoError {
+        CsvIoError::Parse(ParseError {
+            kind: ParseErrorKind::Empty,
+            position: self.position(),
+            line_text: String::new(),
+        })
+    }
+
+    /// Where the next line to be read starts.
+    fn position(&self) -> Position {
+        Position {
+            line: self.lines_read + 1,
+            column: 0,
+            byte_offset: self.bytes_read,
+        }
+    }
+
+    /// Reads a line onto the end of `line`, returning whether there was one.
+    fn read_line(&mut self, line: &mut String) -> Result<bool, CsvIoError> {
+        let read = self.reader.read_line(line)?;
+        self.bytes_read += read;
+        self.lines_read += usize::from(read > 0);
+        Ok(read > 0)
+    }
+
+    fn parse_quoted_record(&mut self, first_line: &str) -> Result<Vec<Field>, CsvIoError> {
+        let mut fields = Vec::new();
+        let mut text = String::new();
+        let mut quoted = false;
+        let mut state = State::FieldStart;
+        let Dialect {
+            delimiter,
+            quote,
+            escape,
+            ..
+        } = self.dialect;
+        let mut line_start = self.record_start;
+        let mut more_lines = String::new();
+        // Where the last quoted field opened, and the line it opened on if not the first.
+        let mut open_quote = (line_start, None);
+        loop {
+            let whole_line = match line_start == self.record_start {
+                true => first_line,
+                false => &more_lines,
+            };
+            let line = strip_line_ending(whole_line);
+            for (i, c) in line.char_indices() {
+                state = match (state, c) {
+                    (State::FieldStart, c) if c == quote => {
+                        quoted = true;
+                        let at = Position {
+                            column: i,
+                            byte_offset: line_start.byte_offset + i,
+                            ..line_start
+                        };
+                        let other_line =
+                            (line_start != self.record_start).then(|| line.to_string());
+                        open_quote = (at, other_line);
+                        State::Quoted
+                    }
+                    (State::FieldStart | State::Unquoted | State::QuoteInQuoted, c)
+                        if c == delimiter =>
+                    {
+                        fields.push(Field {
+                            text: std::mem::take(&mut text),
+                            quoted,
+                        });
+                        quoted = false;
+                        State::FieldStart
+                    }
+                    (State::FieldStart | State::Unquoted, c) => {
+                        text.push(c);
+                        State::Unquoted
+                    }
+                    (State::Quoted, '\\') if escape == EscapeStyle::Backslash => {
+                        State::EscapeInQuoted
+                    }
+                    (State::Quoted, c) if c == quote => State::QuoteInQuoted,
+                    (State::Quoted | State::EscapeInQuoted, c) => {
+                        text.push(c);
+                        State::Quoted
+                    }
+                    (State::QuoteInQuoted, c) if c == quote && escape == EscapeStyle::Doubled => {
+                        text.push(c);
+                        State::Quoted
+                    }
+                    (State::QuoteInQuoted, _) => {
+                        let kind = ParseErrorKind::TextAfterQuote;
+                        let error = ParseError::at(kind, line, i).offset_by(line_start);
+                        return Err(CsvIoError::Parse(error));
+                    }
+                };
+            }
+            if !matches!(state, State::Quoted | State::EscapeInQuoted) {
+                break;
+            }
+            // The field goes on past the end of the line, keeping the line ending it had.
+            text.push_str(&whole_line[line.len()..]);
+            more_lines.clear();
+            line_start = self.position();
+            if !self.read_line(&mut more_lines)? {
+                let (position, other_line) = open_quote;
+                return Err(CsvIoError::Parse(ParseError {
+                    kind: ParseErrorKind::UnclosedQuote,
+                    position,
+                    line_text: other_line
+                        .unwrap_or_else(|| strip_line_ending(first_line).to_string()),
+                }));
+            }
+        }
+        fields.push(Field { text, quoted });
+        Ok(fields)
+    }
+}
+
+/// Where the text of a field lies in a file, as found by `scan_record`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldSpan {
+    /// The byte range of the field, without the quotes around it.
+    pub start: usize,
+    pub end: usize,
+    pub quoted: bool,
+    /// Whether the text of the field is not what lies in the file, because of escaped quotes or
+    /// line breaks, and has to be read with `unescape`.
+    pub escaped: bool,
+}
+
+/// Finds the fields of the record starting at byte `start` of `text`, as `RecordReader` would
+/// read them but without copying them. Returns them with where the next record starts, or
+/// `None` at the end of the text.
+pub fn scan_record(
+    text: &str,
+    start: usize,
+    mode: ParseMode,
+    dialect: Dialect,
+) -> Result<Option<(Vec<FieldSpan>, usize)>, CsvIoError> {
+    if start >= text.len() {
+        return Ok(None);
+    }
+    let Dialect {
+        delimiter,
+        quote,
+        escape,
+        ..
+    } = dialect;
+    let span = |start: usize, end: usize, quoted: bool| {
+        let field = &text[start..end];
+        let escaped = quoted
+            && (field.contains(quote)
+                || (escape == EscapeStyle::Backslash && field.contains('\\')));
+        FieldSpan {
+            start,
+            end,
+            quoted,
+            escaped,
+        }
+    };
+    let mut fields = Vec::new();
+    let mut line_start = start;
+    let mut field_start = start;
+    let mut quote_start = start;
+    let mut quoted = false;
+    let mut state = State::FieldStart;
+    loop {
+        let next = match text[line_start..].find('\n') {
+            Some(i) => line_start + i + 1,
+            None => text.len(),
+        };
+        let line = strip_line_ending(&text[line_start..next]);
+        let line_end = line_start + line.len();
+        for (i, c) in line.char_indices() {
+            let at = line_start + i;
+            if mode == ParseMode::Raw {
+                if c == delimiter {
+                    fields.push(span(field_start, at, false));
+                    field_start = at + c.len_utf8();
+                }
+                continue;
+            }
+            state = match (state, c) {
+                (State::FieldStart, c) if c == quote => {
+                    quoted = true;
+                    quote_start = at;
+                    field_start = at + c.len_utf8();
+                    State::Quoted
+                }
+                (State::FieldStart | State::Unquoted | State::QuoteInQuoted, c)
+                    if c == delimiter =>
+                {
+                    let end = match state {
+                        State::QuoteInQuoted => at - quote.len_utf8(),
+                        _ => at,
+                    };
+                    fields.push(span(field_start, end, quoted));
+                    quoted = false;
+                    field_start = at + c.len_utf8();
+                    State::FieldStart
+                }
+                (State::FieldStart | State::Unquoted, _) => State::Unquoted,
+                (State::Quoted, '\\') if escape == EscapeStyle::Backslash => State::EscapeInQuoted,
+                (State::Quoted, c) if c == quote => State::QuoteInQuoted,
+                (State::Quoted | State::EscapeInQuoted, _) => State::Quoted,
+                (State::QuoteInQuoted, c) if c == quote && escape == EscapeStyle::Doubled => {
+                    State::Quoted
+                }
+                (State::QuoteInQuoted, _) => {
+                    let error = ParseError::at(ParseErrorKind::TextAfterQuote, text, at);
+                    return Err(CsvIoError::Parse(error));
+                }
+            };
+        }
+        if !matches!(state, State::Quoted | State::EscapeInQuoted) {
+            let end = match state {
+                State::QuoteInQuoted => line_end - quote.len_utf8(),
+                _ => line_end,
+            };
+            fields.push(span(field_start, end, quoted));
+            return Ok(Some((fields, next)));
+        }
+        if next >= text.len() {
+            let error = ParseError::at(ParseErrorKind::UnclosedQuote, text, quote_start);
+            return Err(CsvIoError::Parse(error));
+        }
+        line_start = next;
+    }
+}
+
+/// The text of a field that `scan_record` found to be escaped, given what lies in the file.
+pub fn unescape(field: &str, dialect: Dialect) -> String {
+    let quoted = format!("{0}{1}{0}", dialect.quote, field);
+    let mut records = RecordReader::new(quoted.as_bytes(), ParseMode::Rfc4180, dialect);
+    match records.next_record() {
+        Ok(Some(mut record)) if record.len() == 1 => record.remove(0).text,
+        _ => unreachable!("scan_record only finds fields that read back on their own"),
+    }
+}
+
+/// The error for a record of `text` with the fields `spans` when it should have `expected`,
+/// pointing at the first field too many or at the end of the record.
+pub fn field_count_error(
+    text: &str,
+    spans: &[FieldSpan],
+    expected: usize,
+    dialect: Dialect,
+) -> ParseError {
+    let quote_len = |span: &FieldSpan| match span.quoted {
+        true => dialect.quote.len_utf8(),
+        false => 0,
+    };
+    let at = match (spans.get(expected), spans.last()) {
+        (Some(span), _) => span.start - quote_len(span),
+        (None, Some(span)) => span.end + quote_len(span),
+        (None, None) => 0,
+    };
+    let kind = ParseErrorKind::FieldCount {
+        expected,
+        found: spans.len(),
+    };
+    ParseError::at(kind, text, at)
+}
+
+fn strip_line_ending(line: &str) -> &str {
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    line.strip_suffix('\r').unwrap_or(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records(input: &str, mode: ParseMode) -> Vec<Vec<String>> {
+        let mut reader = RecordReader::new(input.as_bytes(), mode, Dialect::default());
+        let mut records = Vec::new();
+        while let Some(record) = reader.next_record().unwrap() {
+            records.push(record.into_iter().map(|f| f.text).collect());
+        }
+        records
+    }
+
+    #[test]
+    fn test_quoted_comma() {
+        assert_eq!(
+            records(
+                "name,city\nBrendan,\"Dublin, Ireland\"\n",
+                ParseMode::Rfc4180
+            ),
+            vec![vec!["name", "city"], vec!["Brendan", "Dublin, Ireland"]]
+        );
+    }
+
+    #[test]
+    fn test_doubled_quote() {
+        assert_eq!(
+            records("\"say \"\"hi\"\"\",b\n", ParseMode::Rfc4180),
+            vec![vec!["say \"hi\"", "b"]]
+        );
+    }
+
+    #[test]
+    fn test_multi_line_record() {
+        assert_eq!(
+            records("a,\"line one\r\nline two\"\r\nc,d\r\n", ParseMode::Rfc4180),
+            vec![vec!["a", "line one\r\nline two"], vec!["c", "d"]]
+        );
+        assert_eq!(
+            records("a,\"line one\nline two\"\r\n", ParseMode::Rfc4180),
+            vec![vec!["a", "line one\nline two"]]
+        );
+    }
+
+    /// The error `RecordReader` stops at in `input`, checking that `scan_record` agrees.
+    fn parse_error(input: &str) -> ParseError {
+        let mut reader =
+            RecordReader::new(input.as_bytes(), ParseMode::Rfc4180, Dialect::default());
+        let error = loop {
+            match reader.next_record() {
+                Ok(Some(_)) => continue,
+                Ok(None) => panic!("{:?} should not parse", input),
+                Err(CsvIoError::Parse(error)) => break error,
+                Err(e) => panic!("{}", e),
+            }
+        };
+        let mut start = 0;
+        let scanned = loop {
+            match scan_record(input, start, ParseMode::Rfc4180, Dialect::default()) {
+                Ok(Some((_, next))) => start = next,
+                Ok(None) => panic!("{:?} should not scan", input),
+                Err(CsvIoError::Parse(error)) => break error,
+                Err(e) => panic!("{}", e),
+            }
+        };
+        assert_eq!(scanned, error);
+        error
+    }
+
+    #[test]
+    fn test_unterminated_quote() {
+        let error = parse_error("h\na,\"never closed\n");
+        assert_eq!(error.kind, ParseErrorKind::UnclosedQuote);
+        assert_eq!((error.position.line, error.position.column), (2, 2));
+        assert_eq!(error.position.byte_offset, 4);
+        assert_eq!(error.line_text, "a,\"never closed");
+        // The quote that is never closed opens on the second line of the record.
+        let error = parse_error("1,\"a\r\nb\",c,\"open\r\nmore\r\n");
+        assert_eq!((error.position.line, error.position.column), (2, 5));
+        assert_eq!(error.position.byte_offset, 11);
+        assert_eq!(error.line_text, "b\",c,\"open");
+    }
+
+    #[test]
+    fn test_text_after_closing_quote() {
+        let error = parse_error("a,b\n\"a\"b,c\n");
+        assert_eq!(error.kind, ParseErrorKind::TextAfterQuote);
+        assert_eq!((error.position.line, error.position.column), (2, 3));
+        assert_eq!(error.position.byte_offset, 7);
+        assert_eq!(error.line_text, "\"a\"b,c");
+    }
+
+    #[test]
+    fn test_quoted_flag() {
+        let mut reader = RecordReader::new(
+            "\"a\",b\n".as_bytes(),
+            ParseMode::Rfc4180,
+            Dialect::default(),
+        );
+        let record = reader.next_record().unwrap().unwrap();
+        assert!(record[0].quoted);
+        assert!(!record[1].quoted);
+    }
+
+    #[test]
+    fn test_tab_delimiter() {
+        let dialect = Dialect {
+            delimiter: '\t',
+            ..Dialect::default()
+        };
+        let mut reader = RecordReader::new("a\t\"b\tc\"\n".as_bytes(), ParseMode::Rfc4180, dialect);
+        let record = reader.next_record().unwrap().unwrap();
+        assert_eq!(record[0].text, "a");
+        assert_eq!(record[1].text, "b\tc");
+    }
+
+    #[test]
+    fn test_single_quote_and_backslash_escape() {
+        let dialect = Dialect {
+            quote: '\'',
+            escape: EscapeStyle::Backslash,
+            ..Dialect::default()
+        };
+        let mut reader = RecordReader::new(
+            "'it\\'s',' a\\\\b ',c\n".as_bytes(),
+            ParseMode::Rfc4180,
+            dialect,
+        );
+        let record = reader.next_record().unwrap().unwrap();
+        let texts = record.into_iter().map(|f| f.text).collect::<Vec<_>>();
+        assert_eq!(texts, vec!["it's", " a\\b ", "c"]);
+    }
+
+    /// The records of `input` as `scan_record` and `unescape` find them.
+    fn scanned(input: &str, mode: ParseMode, dialect: Dialect) -> Vec<Vec<(String, bool)>> {
+        let mut records = Vec::new();
+        let mut start = 0;
+        while let Some((spans, next)) = scan_record(input, start, mode, dialect).unwrap() {
+            let fields = spans.into_iter().map(|span| {
+                let text = &input[span.start..span.end];
+                let text = match span.escaped {
+                    true => unescape(text, dialect),
+                    false => text.to_string(),
+                };
+                (text, span.quoted)
+            });
+            records.push(fields.collect());
+            start = next;
+        }
+        records
+    }
+
+    #[test]
+    fn test_scan_record_agrees_with_reader() {
+        let backslash = Dialect {
+            delimiter: ';',
+            quote: '\'',
+            escape: EscapeStyle::Backslash,
+            ..Dialect::default()
+        };
+        let inputs = [
+            (
+                "a,\"b,c\",\"say \"\"hi\"\"\"\r\n,\"\",x\n",
+                Dialect::default(),
+            ),
+            (
+                "a,\"line one\r\nline two\"\r\n\n\"é\",ü\r",
+                Dialect::default(),
+            ),
+            ("'it\\'s';' a\\\\b ';c\n'x\\\ny';z", backslash),
+        ];
+        for (input, dialect) in inputs {
+            for mode in [ParseMode::Rfc4180, ParseMode::Raw] {
+                let mut reader = RecordReader::new(input.as_bytes(), mode, dialect);
+                let mut expected = Vec::<Vec<_>>::new();
+                while let Some(record) = reader.next_record().unwrap() {
+                    expected.push(record.into_iter().map(|f| (f.text, f.quoted)).collect());
+                }
+                assert_eq!(scanned(input, mode, dialect), expected, "{:?}", input);
+            }
+        }
+    }
+
+    #[test]
+    fn test_raw_mode_preserves_quotes() {
+        assert_eq!(
+            records("\"Dublin, Ireland\",x\n", ParseMode::Raw),
+            vec![vec!["\"Dublin", " Ireland\"", "x"]]
+        );
+    }
+}
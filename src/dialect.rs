@@ -0,0 +1,190 @@
+use std::io::BufRead;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EscapeStyle {
+    /// A quote inside a quoted field is written twice, as in RFC 4180.
+    Doubled,
+    /// A quote or backslash inside a quoted field is preceded by a backslash.
+    Backslash,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineTerminator {
+    Lf,
+    CrLf,
+}
+
+impl LineTerminator {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineTerminator::Lf => "\n",
+            LineTerminator::CrLf => "\r\n",
+        }
+    }
+}
+
+/// The characters that lay out a CSV file. Both line terminators are always accepted when
+/// reading; `line_terminator` is what gets written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dialect {
+    pub delimiter: char,
+    pub quote: char,
+    pub escape: EscapeStyle,
+    pub line_terminator: LineTerminator,
+}
+
+impl Default for Dialect {
+    fn default() -> Self {
+        Self {
+            delimiter: ',',
+            quote: '"',
+            escape: EscapeStyle::Doubled,
+            line_terminator: LineTerminator::Lf,
+        }
+    }
+}
+
+const CANDIDATE_DELIMITERS: [char; 4] = [',', ';', '\t', '|'];
+const SNIFFED_LINES: usize = 20;
+
+impl Dialect {
+    pub fn from_name(name: &str) -> Option<Self> {
+        let delimiter = match name {
+            "csv" => ',',
+            "tsv" => '\t',
+            "semicolon" => ';',
+            "pipe" => '|',
+            _ => return None,
+        };
+        Some(Self {
+            delimiter,
+            ..Self::default()
+        })
+    }
+
+    /// Guesses the dialect of a CSV file from its first few lines.
+    pub fn sniff(sample: &str) -> Self {
+        let lines = sample
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .take(SNIFFED_LINES)
+            .collect::<Vec<_>>();
+        let quote = if sample.contains('"') {
+            '"'
+        } else if lines.iter().any(|line| has_single_quoted_field(line)) {
+            '\''
+        } else {
+            '"'
+        };
+        let escape = if sample.contains(&format!("\\{}", quote)) {
+            EscapeStyle::Backslash
+        } else {
+            EscapeStyle::Doubled
+        };
+        let line_terminator = match sample.find('\n') {
+            Some(end) if sample[..end].ends_with('\r') => LineTerminator::CrLf,
+            _ => LineTerminator::Lf,
+        };
+        let delimiter = CANDIDATE_DELIMITERS
+            .iter()
+            .copied()
+            .map(|delimiter| {
+                let counts = lines
+                    .iter()
+                    .map(|line| count_unquoted(line, delimiter, quote))
+                    .collect::<Vec<_>>();
+                let consistent = counts.windows(2).all(|pair| pair[0] == pair[1]);
+                let total = counts.iter().sum::<usize>();
+                (delimiter, consistent && total > 0, total)
+            })
+            .max_by_key(|&(_, consistent, total)| (consistent, total))
+            .filter(|&(_, _, total)| total > 0)
+            .map_or(',', |(delimiter, _, _)| delimiter);
+        Self {
+            delimiter,
+            quote,
+            escape,
+            line_terminator,
+        }
+    }
+}
+
+/// Sniffs the dialect from whatever `reader` has buffered, without consuming any of it.
+pub fn sniff_dialect(reader: &mut impl BufRead) -> std::io::Result<Dialect> {
+    let buffered = reader.fill_buf()?;
+    Ok(Dialect::sniff(&String::from_utf8_lossy(buffered)))
+}
+
+fn count_unquoted(line: &str, delimiter: char, quote: char) -> usize {
+    let mut in_quotes = false;
+    let mut count = 0;
+    for c in line.chars() {
+        if c == quote {
+            in_quotes = !in_quotes;
+        } else if c == delimiter && !in_quotes {
+            count += 1;
+        }
+    }
+    count
+}
+
+/// Whether some field of `line` is wrapped in single quotes: it starts with one, and a later one
+/// is followed by the same delimiter or the end of the line. A value like `'tis` that merely
+/// starts with an apostrophe does not count.
+fn has_single_quoted_field(line: &str) -> bool {
+    CANDIDATE_DELIMITERS.iter().any(|&delimiter| {
+        let field_starts = line
+            .match_indices(delimiter)
+            .map(|(start, _)| start + delimiter.len_utf8());
+        std::iter::once(0).chain(field_starts).any(|start| {
+            let Some(field) = line[start..].strip_prefix('\'') else {
+                return false;
+            };
+            field.match_indices('\'').any(|(end, _)| {
+                let after = &field[end + 1..];
+                after.is_empty() || after.starts_with(delimiter)
+            })
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sniff_delimiter() {
+        assert_eq!(Dialect::sniff("a,b,c\n1,2,3\n").delimiter, ',');
+        assert_eq!(Dialect::sniff("a;b;c\n1,5;2,5;3\n").delimiter, ';');
+        assert_eq!(Dialect::sniff("a\tb\n1\t\"x,y\"\n").delimiter, '\t');
+        assert_eq!(Dialect::sniff("a|b\n1|2\n").delimiter, '|');
+        assert_eq!(Dialect::sniff("single column\nvalue\n").delimiter, ',');
+    }
+
+    #[test]
+    fn test_sniff_quote_escape_and_terminator() {
+        let dialect = Dialect::sniff("name,quote\r\nx,\"say \\\"hi\\\"\"\r\n");
+        assert_eq!(dialect.quote, '"');
+        assert_eq!(dialect.escape, EscapeStyle::Backslash);
+        assert_eq!(dialect.line_terminator, LineTerminator::CrLf);
+
+        let dialect = Dialect::sniff("name;city\nx;'Dublin; Ireland'\n");
+        assert_eq!(dialect.quote, '\'');
+        assert_eq!(dialect.delimiter, ';');
+        assert_eq!(dialect.escape, EscapeStyle::Doubled);
+        assert_eq!(dialect.line_terminator, LineTerminator::Lf);
+
+        let dialect = Dialect::sniff("name,note\nbob,'tis fine\nann,it's 'mine\n");
+        assert_eq!(dialect.quote, '"');
+        assert_eq!(dialect.delimiter, ',');
+        assert_eq!(Dialect::sniff("name\n'ann'\n").quote, '\'');
+        assert_eq!(Dialect::sniff("a|b\n'x'|''\n").quote, '\'');
+    }
+
+    #[test]
+    fn test_sniff_does_not_consume() {
+        let mut reader = "a;b\n1;2\n".as_bytes();
+        assert_eq!(sniff_dialect(&mut reader).unwrap().delimiter, ';');
+        assert_eq!(reader, b"a;b\n1;2\n");
+    }
+}
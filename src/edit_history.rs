@@ -0,0 +1,598 @@
+use crate::{
+    csv_data_handle::*,
+    schema::{ColumnSchema, SchemaChecked},
+    sort::SortKey,
+};
+use std::{collections::VecDeque, fmt::Display};
+
+/// Cell values as they are stored, with `None` for a null cell.
+pub type Cells = Vec<Option<String>>;
+
+/// A change to a `CsvDataHandle`, kept as data so that it can be undone and redone.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Edit {
+    ReplaceDataAt {
+        index: Index,
+        new_data: Option<String>,
+    },
+    ReplaceRow {
+        row: usize,
+        new_data: Cells,
+    },
+    ReplaceColumn {
+        column: usize,
+        new_data: Cells,
+    },
+    InsertRow {
+        row: usize,
+        new_data: Cells,
+    },
+    InsertColumn {
+        column: usize,
+        header: String,
+        new_data: Cells,
+    },
+    DeleteRow(usize),
+    DeleteColumn(usize),
+    /// Rows one after another, the first becoming row `row`.
+    InsertRows {
+        row: usize,
+        new_data: Vec<Cells>,
+    },
+    /// `count` rows, starting from row `row`.
+    DeleteRows {
+        row: usize,
+        count: usize,
+    },
+    /// Any number of cells anywhere, each given a new value.
+    ReplaceCells(Vec<(Index, Option<String>)>),
+    /// Sorting, kept with the order it put the rows in so that redoing it is exact.
+    SortRows {
+        keys: Vec<SortKey>,
+        order: Vec<usize>,
+    },
+    /// See `CsvDataHandle::reorder_rows`.
+    ReorderRows(Vec<usize>),
+}
+
+impl Display for Edit {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Edit::ReplaceDataAt { index, .. } => {
+                write!(f, "replace cell ({},{})", index.row, index.column)
+            }
+            Edit::ReplaceRow { row, .. } => write!(f, "replace row {}", row),
+            Edit::ReplaceColumn { column, .. } => write!(f, "replace column {}", column),
+            Edit::InsertRow { row, .. } => write!(f, "insert row {}", row),
+            Edit::InsertColumn { column, header, .. } => {
+                write!(f, "insert column {} ({})", column, header)
+            }
+            Edit::DeleteRow(row) => write!(f, "delete row {}", row),
+            Edit::DeleteColumn(column) => write!(f, "delete column {}", column),
+            Edit::InsertRows { row, new_data } => {
+                write!(f, "insert rows {}-{}", row, row + new_data.len() - 1)
+            }
+            Edit::DeleteRows { row, count } => {
+                write!(f, "delete rows {}-{}", row, row + count - 1)
+            }
+            Edit::ReplaceCells(cells) => write!(f, "replace {} cells", cells.len()),
+            Edit::SortRows { keys, .. } => {
+                let keys = keys.iter().map(SortKey::to_string).collect::<Vec<_>>();
+                write!(f, "sort by {}", keys.join(", "))
+            }
+            Edit::ReorderRows(_) => write!(f, "reorder rows"),
+        }
+    }
+}
+
+impl Edit {
+    /// The edit that puts `csv_holder` back the way it is now once `self` has been applied.
+    fn inverse(&self, csv_holder: &impl CsvDataHandle) -> CsvResult<Edit> {
+        Ok(match self {
+            Edit::ReplaceDataAt { index, .. } => Edit::ReplaceDataAt {
+                index: *index,
+                new_data: csv_holder.cell(*index)?.map(str::to_string),
+            },
+            Edit::ReplaceRow { row, .. } => Edit::ReplaceRow {
+                row: *row,
+                new_data: owned(csv_holder.row_cells(*row)?),
+            },
+            Edit::ReplaceColumn { column, .. } => Edit::ReplaceColumn {
+                column: *column,
+                new_data: owned(csv_holder.column_cells(*column)?),
+            },
+            Edit::InsertRow { row, .. } => Edit::DeleteRow(*row),
+            Edit::InsertColumn { column, .. } => Edit::DeleteColumn(*column),
+            Edit::DeleteRow(row) => Edit::InsertRow {
+                row: *row,
+                new_data: owned(csv_holder.row_cells(*row)?),
+            },
+            Edit::DeleteColumn(column) => {
+                let header = csv_holder
+                    .headers()
+                    .get(column.wrapping_sub(1))
+                    .ok_or(CsvError::NoSuchColumn(*column))?
+                    .to_string();
+                Edit::InsertColumn {
+                    column: *column,
+                    header,
+                    new_data: owned(csv_holder.column_cells(*column)?),
+                }
+            }
+            Edit::InsertRows { row, new_data } => Edit::DeleteRows {
+                row: *row,
+                count: new_data.len(),
+            },
+            Edit::DeleteRows { row, count } => {
+                let mut new_data = Vec::new();
+                for row in *row..row + count {
+                    new_data.push(owned(csv_holder.row_cells(row)?));
+                }
+                Edit::InsertRows {
+                    row: *row,
+                    new_data,
+                }
+            }
+            Edit::ReplaceCells(cells) => {
+                let mut old_cells = Vec::new();
+                for (index, _) in cells {
+                    old_cells.push((*index, csv_holder.cell(*index)?.map(str::to_string)));
+                }
+                // Undone in reverse, so that a cell listed twice ends up as it started.
+                old_cells.reverse();
+                Edit::ReplaceCells(old_cells)
+            }
+            Edit::SortRows { order, .. } | Edit::ReorderRows(order) => {
+                if order.len() != csv_holder.row_count() {
+                    return Err(CsvError::FailedToReorderRows);
+                }
+                let mut restoring = vec![0; order.len()];
+                for (i, &row) in order.iter().enumerate() {
+                    *restoring
+                        .get_mut(row.wrapping_sub(1))
+                        .ok_or(CsvError::FailedToReorderRows)? = i + 1;
+                }
+                Edit::ReorderRows(restoring)
+            }
+        })
+    }
+
+    pub fn apply(&self, csv_holder: &mut impl CsvDataHandle) -> CsvResult<()> {
+        match self {
+            Edit::ReplaceDataAt { index, new_data } => replace_cell(csv_holder, *index, new_data),
+            Edit::ReplaceRow { row, new_data } => {
+                csv_holder.replace_row(*row, values(new_data))?;
+                set_nulls(csv_holder, new_data, |column| Index { row: *row, column })
+            }
+            Edit::ReplaceColumn { column, new_data } => {
+                csv_holder.replace_column(*column, values(new_data))?;
+                set_nulls(csv_holder, new_data, |row| Index {
+                    row,
+                    column: *column,
+                })
+            }
+            Edit::InsertRow { row, new_data } => {
+                csv_holder.insert_row(*row, values(new_data))?;
+                set_nulls(csv_holder, new_data, |column| Index { row: *row, column })
+            }
+            Edit::InsertColumn {
+                column,
+                header,
+                new_data,
+            } => {
+                csv_holder.insert_column(*column, header.clone(), values(new_data))?;
+                set_nulls(csv_holder, new_data, |row| Index {
+                    row,
+                    column: *column,
+                })
+            }
+            Edit::DeleteRow(row) => csv_holder.delete_row(*row),
+            Edit::DeleteColumn(column) => csv_holder.delete_column(*column),
+            Edit::InsertRows { row, new_data } => {
+                let mut inserted = 0;
+                let result = insert_rows(csv_holder, *row, new_data, &mut inserted);
+                if result.is_err() {
+                    // Take out the rows already inserted, so that a refused edit changes
+                    // nothing.
+                    for _ in 0..inserted {
+                        let _ = csv_holder.delete_row(*row);
+                    }
+                }
+                result
+            }
+            Edit::DeleteRows { row, count } => {
+                for _ in 0..*count {
+                    csv_holder.delete_row(*row)?;
+                }
+                Ok(())
+            }
+            Edit::ReplaceCells(cells) => {
+                let mut replaced = Vec::new();
+                let result = replace_cells(csv_holder, cells, &mut replaced);
+                if result.is_err() {
+                    // Put back the cells already replaced, so that a refused edit changes
+                    // nothing. They held these values a moment ago, so they take them again.
+                    for (index, old_data) in replaced.iter().rev() {
+                        let _ = replace_cell(csv_holder, *index, old_data);
+                    }
+                }
+                result
+            }
+            Edit::SortRows { order, .. } | Edit::ReorderRows(order) => {
+                csv_holder.reorder_rows(order)
+            }
+        }
+    }
+
+    /// Roughly how many bytes of cell data the edit holds on to.
+    fn size(&self) -> usize {
+        let cells_size = |cells: &Cells| -> usize {
+            cells
+                .iter()
+                .map(|cell| cell.as_ref().map_or(0, String::len) + std::mem::size_of::<String>())
+                .sum()
+        };
+        std::mem::size_of::<Edit>()
+            + match self {
+                Edit::ReplaceDataAt { new_data, .. } => new_data.as_ref().map_or(0, String::len),
+                Edit::ReplaceRow { new_data, .. }
+                | Edit::ReplaceColumn { new_data, .. }
+                | Edit::InsertRow { new_data, .. } => cells_size(new_data),
+                Edit::InsertColumn {
+                    header, new_data, ..
+                } => header.len() + cells_size(new_data),
+                Edit::InsertRows { new_data, .. } => new_data.iter().map(cells_size).sum(),
+                Edit::DeleteRow(_) | Edit::DeleteColumn(_) | Edit::DeleteRows { .. } => 0,
+                Edit::ReplaceCells(cells) => cells
+                    .iter()
+                    .map(|(_, cell)| {
+                        std::mem::size_of::<(Index, Option<String>)>()
+                            + cell.as_ref().map_or(0, String::len)
+                    })
+                    .sum(),
+                Edit::SortRows { keys, order } => {
+                    std::mem::size_of_val(&keys[..]) + std::mem::size_of_val(&order[..])
+                }
+                Edit::ReorderRows(order) => std::mem::size_of_val(&order[..]),
+            }
+    }
+}
+
+fn owned(cells: Vec<Option<&str>>) -> Cells {
+    cells
+        .into_iter()
+        .map(|cell| cell.map(str::to_string))
+        .collect()
+}
+
+fn values(cells: &Cells) -> Vec<String> {
+    cells
+        .iter()
+        .map(|cell| cell.clone().unwrap_or_default())
+        .collect()
+}
+
+fn replace_cell(
+    csv_holder: &mut impl CsvDataHandle,
+    index: Index,
+    new_data: &Option<String>,
+) -> CsvResult<()> {
+    match new_data {
+        Some(new_data) => csv_holder.replace_data_at(index, new_data.clone()),
+        None => csv_holder.set_null(index),
+    }
+}
+
+/// Replaces `cells` in order, adding each cell to `replaced` with the value it had once it has
+/// been replaced.
+fn replace_cells(
+    csv_holder: &mut impl CsvDataHandle,
+    cells: &[(Index, Option<String>)],
+    replaced: &mut Vec<(Index, Option<String>)>,
+) -> CsvResult<()> {
+    for (index, new_data) in cells {
+        let old_data = csv_holder.cell(*index)?.map(str::to_string);
+        replace_cell(csv_holder, *index, new_data)?;
+        replaced.push((*index, old_data));
+    }
+    Ok(())
+}
+
+/// Inserts `new_data` from row `row` on, counting each row in `inserted` once it is in.
+fn insert_rows(
+    csv_holder: &mut impl CsvDataHandle,
+    row: usize,
+    new_data: &[Cells],
+    inserted: &mut usize,
+) -> CsvResult<()> {
+    for (i, cells) in new_data.iter().enumerate() {
+        let row = row + i;
+        csv_holder.insert_row(row, values(cells))?;
+        *inserted += 1;
+        set_nulls(csv_holder, cells, |column| Index { row, column })?;
+    }
+    Ok(())
+}
+
+fn set_nulls(
+    csv_holder: &mut impl CsvDataHandle,
+    cells: &Cells,
+    index_of: impl Fn(usize) -> Index,
+) -> CsvResult<()> {
+    for (i, cell) in cells.iter().enumerate() {
+        if cell.is_none() {
+            csv_holder.set_null(index_of(i + 1))?;
+        }
+    }
+    Ok(())
+}
+
+struct HistoryEntry {
+    edit: Edit,
+    inverse: Edit,
+    /// The column `edit` deletes and the schema it had, which undoing `edit` puts back rather
+    /// than inferring one from the cells again.
+    deleted_schema: Option<(usize, ColumnSchema)>,
+}
+
+impl HistoryEntry {
+    fn size(&self) -> usize {
+        self.edit.size() + self.inverse.size()
+    }
+}
+
+fn deleted_schema(
+    edit: &Edit,
+    csv_holder: &SchemaChecked<'_, impl CsvDataHandle>,
+) -> Option<(usize, ColumnSchema)> {
+    match edit {
+        Edit::DeleteColumn(column) => Some((*column, csv_holder.schema.column(*column))),
+        _ => None,
+    }
+}
+
+/// Undo and redo stacks of applied edits. The oldest edits are forgotten once there are more
+/// than `max_entries` of them or they hold more than `max_bytes` of cell data between them.
+pub struct EditHistory {
+    undo_stack: VecDeque<HistoryEntry>,
+    redo_stack: Vec<HistoryEntry>,
+    bytes: usize,
+    max_entries: usize,
+    max_bytes: usize,
+}
+
+impl Default for EditHistory {
+    fn default() -> Self {
+        Self::new(100, 64 * 1024 * 1024)
+    }
+}
+
+impl EditHistory {
+    pub fn new(max_entries: usize, max_bytes: usize) -> Self {
+        Self {
+            undo_stack: VecDeque::new(),
+            redo_stack: Vec::new(),
+            bytes: 0,
+            max_entries,
+            max_bytes,
+        }
+    }
+
+    /// Applies `edit` and records it so that it can be undone.
+    pub fn apply(
+        &mut self,
+        csv_holder: &mut SchemaChecked<'_, impl CsvDataHandle>,
+        edit: Edit,
+    ) -> CsvResult<()> {
+        let inverse = edit.inverse(csv_holder)?;
+        let deleted_schema = deleted_schema(&edit, csv_holder);
+        edit.apply(csv_holder)?;
+        self.redo_stack.clear();
+        self.push_undo(HistoryEntry {
+            edit,
+            inverse,
+            deleted_schema,
+        });
+        Ok(())
+    }
+
+    /// Undoes the most recent edit and returns it, or `None` if there is nothing to undo.
+    pub fn undo(
+        &mut self,
+        csv_holder: &mut SchemaChecked<'_, impl CsvDataHandle>,
+    ) -> CsvResult<Option<&Edit>> {
+        let Some(entry) = self.undo_stack.pop_back() else {
+            return Ok(None);
+        };
+        self.bytes -= entry.size();
+        if let Err(e) = entry.inverse.apply(csv_holder) {
+            self.push_undo(entry);
+            return Err(e);
+        }
+        if let Some((column, column_schema)) = &entry.deleted_schema {
+            csv_holder
+                .schema
+                .restore_column(*column, column_schema.clone());
+        }
+        self.redo_stack.push(entry);
+        Ok(self.redo_stack.last().map(|entry| &entry.edit))
+    }
+
+    /// Reapplies the most recently undone edit and returns it, or `None` if there is nothing to
+    /// redo.
+    pub fn redo(
+        &mut self,
+        csv_holder: &mut SchemaChecked<'_, impl CsvDataHandle>,
+    ) -> CsvResult<Option<&Edit>> {
+        let Some(mut entry) = self.redo_stack.pop() else {
+            return Ok(None);
+        };
+        entry.deleted_schema = deleted_schema(&entry.edit, csv_holder);
+        if let Err(e) = entry.edit.apply(csv_holder) {
+            self.redo_stack.push(entry);
+            return Err(e);
+        }
+        self.push_undo(entry);
+        Ok(self.undo_stack.back().map(|entry| &entry.edit))
+    }
+
+    /// Edits that can be undone, oldest first.
+    pub fn undoable(&self) -> impl Iterator<Item = &Edit> {
+        self.undo_stack.iter().map(|entry| &entry.edit)
+    }
+
+    /// Edits that can be redone, next to be redone first.
+    pub fn redoable(&self) -> impl Iterator<Item = &Edit> {
+        self.redo_stack.iter().rev().map(|entry| &entry.edit)
+    }
+
+    fn push_undo(&mut self, entry: HistoryEntry) {
+        self.bytes += entry.size();
+        self.undo_stack.push_back(entry);
+        while self.undo_stack.len() > self.max_entries
+            || (self.bytes > self.max_bytes && self.undo_stack.len() > 1)
+        {
+            if let Some(oldest) = self.undo_stack.pop_front() {
+                self.bytes -= oldest.size();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{csv_holder::CsvHolder, schema::Schema};
+
+    fn csv_holder() -> CsvHolder {
+        CsvHolder::from_strs(
+            &["a", "b"],
+            &[&["1", ""], &["3", "4"]],
+            &[Index { row: 1, column: 2 }],
+        )
+    }
+
+    #[test]
+    fn test_undo_and_redo_every_edit() {
+        let edits = vec![
+            Edit::ReplaceDataAt {
+                index: Index { row: 1, column: 2 },
+                new_data: Some("x".to_string()),
+            },
+            Edit::ReplaceRow {
+                row: 2,
+                new_data: vec![None, Some("y".to_string())],
+            },
+            Edit::ReplaceColumn {
+                column: 2,
+                new_data: vec![Some("p".to_string()), Some("q".to_string())],
+            },
+            Edit::InsertRow {
+                row: 3,
+                new_data: vec![Some("5".to_string()), None],
+            },
+            Edit::InsertColumn {
+                column: 1,
+                header: "z".to_string(),
+                new_data: vec![None, Some("".to_string())],
+            },
+            Edit::DeleteRow(1),
+            Edit::DeleteColumn(2),
+            Edit::InsertRows {
+                row: 2,
+                new_data: vec![
+                    vec![Some("5".to_string()), None],
+                    vec![Some("6".to_string()), Some("7".to_string())],
+                ],
+            },
+            Edit::DeleteRows { row: 1, count: 2 },
+            Edit::ReplaceCells(vec![
+                (Index { row: 1, column: 2 }, Some("x".to_string())),
+                (Index { row: 2, column: 1 }, None),
+                (Index { row: 1, column: 2 }, Some("y".to_string())),
+            ]),
+            Edit::SortRows {
+                keys: vec![SortKey {
+                    column: 1,
+                    descending: true,
+                    mode: crate::sort::SortMode::Numeric,
+                }],
+                order: vec![2, 1],
+            },
+        ];
+        for edit in edits {
+            let original = csv_holder();
+            let mut edited = original.clone();
+            let mut schema = Schema::default();
+            let mut checked = SchemaChecked {
+                data: &mut edited,
+                schema: &mut schema,
+            };
+            let mut history = EditHistory::default();
+            history.apply(&mut checked, edit.clone()).unwrap();
+            let after_edit = checked.data.clone();
+            assert_ne!(after_edit, original, "{}", edit);
+            assert_eq!(history.undo(&mut checked).unwrap(), Some(&edit));
+            assert_eq!(*checked.data, original, "{}", edit);
+            assert_eq!(history.redo(&mut checked).unwrap(), Some(&edit));
+            assert_eq!(*checked.data, after_edit, "{}", edit);
+        }
+    }
+
+    #[test]
+    fn test_failed_edit_is_not_recorded() {
+        let mut csv_holder = csv_holder();
+        let mut schema = Schema::default();
+        let mut csv_holder = SchemaChecked {
+            data: &mut csv_holder,
+            schema: &mut schema,
+        };
+        let mut history = EditHistory::default();
+        assert!(history.apply(&mut csv_holder, Edit::DeleteRow(7)).is_err());
+        assert_eq!(history.undoable().count(), 0);
+        assert_eq!(history.undo(&mut csv_holder).unwrap(), None);
+    }
+
+    #[test]
+    fn test_new_edit_clears_redo() {
+        let mut csv_holder = csv_holder();
+        let mut schema = Schema::default();
+        let mut csv_holder = SchemaChecked {
+            data: &mut csv_holder,
+            schema: &mut schema,
+        };
+        let mut history = EditHistory::default();
+        history.apply(&mut csv_holder, Edit::DeleteRow(1)).unwrap();
+        history.undo(&mut csv_holder).unwrap();
+        assert_eq!(history.redoable().count(), 1);
+        history
+            .apply(&mut csv_holder, Edit::DeleteColumn(1))
+            .unwrap();
+        assert_eq!(history.redoable().count(), 0);
+        assert_eq!(history.redo(&mut csv_holder).unwrap(), None);
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let mut csv_holder = csv_holder();
+        let mut schema = Schema::default();
+        let mut csv_holder = SchemaChecked {
+            data: &mut csv_holder,
+            schema: &mut schema,
+        };
+        let mut history = EditHistory::new(2, usize::MAX);
+        for value in ["x", "y", "z"] {
+            let edit = Edit::ReplaceDataAt {
+                index: Index { row: 1, column: 1 },
+                new_data: Some(value.to_string()),
+            };
+            history.apply(&mut csv_holder, edit).unwrap();
+        }
+        assert_eq!(history.undoable().count(), 2);
+
+        let mut history = EditHistory::new(100, 1);
+        history.apply(&mut csv_holder, Edit::DeleteRow(1)).unwrap();
+        history.apply(&mut csv_holder, Edit::DeleteRow(1)).unwrap();
+        assert_eq!(history.undoable().count(), 1);
+    }
+}
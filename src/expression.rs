@@ -0,0 +1,846 @@
+use crate::csv_data_handle::{ColumnRef, CsvDataHandle, CsvError, CsvResult, Index};
+use regex::Regex;
+use std::{borrow::Cow, cmp::Ordering, error::Error, fmt::Display};
+
+/// A formula over the cells of a row, such as `status = "failed" and $3 > 10` or
+/// `upper(trim(name))`, together with the text it was parsed from.
+#[derive(Debug, Clone)]
+pub struct Expression {
+    source: String,
+    root: Node,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Literal(Value),
+    Column(ColumnRef),
+    Negate(Box<Node>),
+    Arithmetic(Box<Node>, Operator, Box<Node>),
+    Call(Function, Vec<Node>),
+    Not(Box<Node>),
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    Compare(Box<Node>, Comparison, Box<Node>),
+    Contains(Box<Node>, Box<Node>),
+    Matches(Box<Node>, Regex),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Function {
+    Upper,
+    Lower,
+    Trim,
+    Len,
+    Concat,
+    Substr,
+    Replace,
+    Round,
+    Abs,
+    If,
+    Coalesce,
+}
+
+impl Function {
+    /// The function called `name`, with the least and most arguments it takes.
+    fn from_name(name: &str) -> Option<(Self, usize, usize)> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "upper" => (Function::Upper, 1, 1),
+            "lower" => (Function::Lower, 1, 1),
+            "trim" => (Function::Trim, 1, 1),
+            "len" => (Function::Len, 1, 1),
+            "concat" => (Function::Concat, 1, usize::MAX),
+            "substr" => (Function::Substr, 2, 3),
+            "replace" => (Function::Replace, 3, 3),
+            "round" => (Function::Round, 1, 2),
+            "abs" => (Function::Abs, 1, 1),
+            "if" => (Function::If, 3, 3),
+            "coalesce" => (Function::Coalesce, 1, usize::MAX),
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+/// What an expression works out to for one row. Cells are always text, or null when they hold
+/// no value; numbers and booleans come from literals, arithmetic and comparisons.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    Text(String),
+}
+
+impl Value {
+    fn as_text(&self) -> Cow<'_, str> {
+        match self {
+            Value::Null => Cow::Borrowed(""),
+            Value::Bool(b) => Cow::Owned(b.to_string()),
+            Value::Number(n) => Cow::Owned(n.to_string()),
+            Value::Text(text) => Cow::Borrowed(text),
+        }
+    }
+
+    fn as_number(&self) -> Option<f64> {
+        match self {
+            Value::Number(n) => Some(*n),
+            Value::Text(text) => text.trim().parse::<f64>().ok(),
+            Value::Null | Value::Bool(_) => None,
+        }
+    }
+
+    /// The value as it would be written to a cell, with `None` for null.
+    pub fn into_cell(self) -> Option<String> {
+        match self {
+            Value::Null => None,
+            Value::Text(text) => Some(text),
+            value => Some(value.as_text().into_owned()),
+        }
+    }
+
+    /// Whether a row with this value passes a filter: empty text, zero and null do not.
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Null => false,
+            Value::Bool(b) => *b,
+            Value::Number(n) => *n != 0.0,
+            Value::Text(text) => !text.is_empty(),
+        }
+    }
+}
+
+/// Compares numerically when both sides are numbers, and as text otherwise.
+fn compare(left: &Value, right: &Value) -> Ordering {
+    match (left.as_number(), right.as_number()) {
+        (Some(left), Some(right)) => left.total_cmp(&right),
+        _ => left.as_text().cmp(&right.as_text()),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpressionError {
+    pub message: String,
+    /// The 0-based character offset in the expression where the problem was found.
+    pub position: usize,
+}
+
+impl Display for ExpressionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} at character {}", self.message, self.position + 1)
+    }
+}
+
+impl Error for ExpressionError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Text(String),
+    /// A bare word: a keyword or a column name.
+    Word(String),
+    /// A column name in backticks, which is never taken for a keyword.
+    QuotedName(String),
+    /// A column position such as `$3`.
+    ColumnIndex(usize),
+    OpenParen,
+    CloseParen,
+    Comma,
+    Operator(Operator),
+    Comparison(Comparison),
+}
+
+impl Token {
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self, Token::Word(word) if word.eq_ignore_ascii_case(keyword))
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, ExpressionError> {
+    let chars = source.chars().collect::<Vec<_>>();
+    let error = |message: &str, position: usize| ExpressionError {
+        message: message.to_string(),
+        position,
+    };
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let start = i;
+        let c = chars[i];
+        let token = match c {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => {
+                i += 1;
+                Token::OpenParen
+            }
+            ')' => {
+                i += 1;
+                Token::CloseParen
+            }
+            ',' => {
+                i += 1;
+                Token::Comma
+            }
+            '+' | '-' | '*' | '/' | '%' => {
+                i += 1;
+                Token::Operator(match c {
+                    '+' => Operator::Add,
+                    '-' => Operator::Subtract,
+                    '*' => Operator::Multiply,
+                    '/' => Operator::Divide,
+                    _ => Operator::Remainder,
+                })
+            }
+            '=' | '!' | '<' | '>' => {
+                let next = chars.get(i + 1).copied();
+                let (comparison, length) = match (c, next) {
+                    ('=', Some('=')) => (Comparison::Equal, 2),
+                    ('=', _) => (Comparison::Equal, 1),
+                    ('!', Some('=')) | ('<', Some('>')) => (Comparison::NotEqual, 2),
+                    ('<', Some('=')) => (Comparison::LessOrEqual, 2),
+                    ('<', _) => (Comparison::Less, 1),
+                    ('>', Some('=')) => (Comparison::GreaterOrEqual, 2),
+                    ('>', _) => (Comparison::Greater, 1),
+                    _ => return Err(error("expected != but found !", start)),
+                };
+                i += length;
+                Token::Comparison(comparison)
+            }
+            '"' | '\'' | '`' => {
+                let quote = c;
+                let mut text = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => return Err(error("unterminated quote", start)),
+                        Some('\\') if matches!(chars.get(i + 1), Some(&n) if n == quote || n == '\\') =>
+                        {
+                            text.push(chars[i + 1]);
+                            i += 2;
+                        }
+                        Some(&n) if n == quote => {
+                            i += 1;
+                            break;
+                        }
+                        Some(&n) => {
+                            text.push(n);
+                            i += 1;
+                        }
+                    }
+                }
+                if quote == '`' {
+                    Token::QuotedName(text)
+                } else {
+                    Token::Text(text)
+                }
+            }
+            '$' => {
+                i += 1;
+                while chars.get(i).is_some_and(|c| c.is_ascii_digit()) {
+                    i += 1;
+                }
+                let digits = chars[start + 1..i].iter().collect::<String>();
+                match digits.parse::<usize>() {
+                    Ok(column) if column > 0 => Token::ColumnIndex(column),
+                    _ => return Err(error("expected a column number after $", start)),
+                }
+            }
+            c if c.is_ascii_digit() || c == '.' => {
+                i += 1;
+                while chars
+                    .get(i)
+                    .is_some_and(|c| c.is_ascii_digit() || *c == '.')
+                {
+                    i += 1;
+                }
+                let number = chars[start..i].iter().collect::<String>();
+                match number.parse::<f64>() {
+                    Ok(number) => Token::Number(number),
+                    Err(_) => return Err(error("invalid number", start)),
+                }
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                while chars
+                    .get(i)
+                    .is_some_and(|c| c.is_alphanumeric() || *c == '_')
+                {
+                    i += 1;
+                }
+                Token::Word(chars[start..i].iter().collect())
+            }
+            c => return Err(error(&format!("unexpected {}", c), start)),
+        };
+        tokens.push((token, start));
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent over the tokens, loosest binding first: `or`, `and`, `not`, comparisons,
+/// `+` and `-`, then `*`, `/` and `%`.
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    next: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(token, _)| token)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens
+            .get(self.next)
+            .map_or(self.end, |&(_, position)| position)
+    }
+
+    fn error(&self, message: &str) -> ExpressionError {
+        let message = match self.peek() {
+            Some(_) => message.to_string(),
+            None => format!("{} but the expression ended", message),
+        };
+        ExpressionError {
+            message,
+            position: self.position(),
+        }
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.peek().is_some_and(|token| token.is_keyword(keyword));
+        if found {
+            self.next += 1;
+        }
+        found
+    }
+
+    fn or(&mut self) -> Result<Node, ExpressionError> {
+        let mut node = self.and()?;
+        while self.eat_keyword("or") {
+            node = Node::Or(Box::new(node), Box::new(self.and()?));
+        }
+        Ok(node)
+    }
+
+    fn and(&mut self) -> Result<Node, ExpressionError> {
+        let mut node = self.not()?;
+        while self.eat_keyword("and") {
+            node = Node::And(Box::new(node), Box::new(self.not()?));
+        }
+        Ok(node)
+    }
+
+    fn not(&mut self) -> Result<Node, ExpressionError> {
+        if self.eat_keyword("not") {
+            return Ok(Node::Not(Box::new(self.not()?)));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Node, ExpressionError> {
+        let left = self.sum()?;
+        if let Some(&Token::Comparison(comparison)) = self.peek() {
+            self.next += 1;
+            let right = self.sum()?;
+            return Ok(Node::Compare(Box::new(left), comparison, Box::new(right)));
+        }
+        if self.eat_keyword("contains") {
+            let right = self.sum()?;
+            return Ok(Node::Contains(Box::new(left), Box::new(right)));
+        }
+        if self.eat_keyword("matches") {
+            let position = self.position();
+            let Some(Token::Text(pattern)) = self.peek().cloned() else {
+                return Err(self.error("expected a quoted pattern after matches"));
+            };
+            self.next += 1;
+            let regex = Regex::new(&pattern).map_err(|e| ExpressionError {
+                message: format!("invalid pattern: {}", e),
+                position,
+            })?;
+            return Ok(Node::Matches(Box::new(left), regex));
+        }
+        Ok(left)
+    }
+
+    fn sum(&mut self) -> Result<Node, ExpressionError> {
+        let mut node = self.product()?;
+        while let Some(&Token::Operator(operator @ (Operator::Add | Operator::Subtract))) =
+            self.peek()
+        {
+            self.next += 1;
+            node = Node::Arithmetic(Box::new(node), operator, Box::new(self.product()?));
+        }
+        Ok(node)
+    }
+
+    fn product(&mut self) -> Result<Node, ExpressionError> {
+        let mut node = self.negation()?;
+        while let Some(&Token::Operator(
+            operator @ (Operator::Multiply | Operator::Divide | Operator::Remainder),
+        )) = self.peek()
+        {
+            self.next += 1;
+            node = Node::Arithmetic(Box::new(node), operator, Box::new(self.negation()?));
+        }
+        Ok(node)
+    }
+
+    fn negation(&mut self) -> Result<Node, ExpressionError> {
+        if self.peek() == Some(&Token::Operator(Operator::Subtract)) {
+            self.next += 1;
+            return Ok(Node::Negate(Box::new(self.negation()?)));
+        }
+        self.operand()
+    }
+
+    /// Parses the arguments of a call to the function named `name`, which is the next token.
+    fn call(&mut self, name: &str) -> Result<Node, ExpressionError> {
+        let position = self.position();
+        let Some((function, min_arguments, max_arguments)) = Function::from_name(name) else {
+            return Err(self.error(&format!("unknown function {}", name)));
+        };
+        self.next += 2;
+        let mut arguments = Vec::new();
+        if self.peek() != Some(&Token::CloseParen) {
+            loop {
+                arguments.push(self.or()?);
+                match self.peek() {
+                    Some(Token::Comma) => self.next += 1,
+                    Some(Token::CloseParen) => break,
+                    _ => return Err(self.error("expected , or )")),
+                }
+            }
+        }
+        if arguments.len() < min_arguments || arguments.len() > max_arguments {
+            let expected = match (min_arguments, max_arguments) {
+                (min, usize::MAX) => format!("at least {}", min),
+                (min, max) if min == max => min.to_string(),
+                (min, max) => format!("{} to {}", min, max),
+            };
+            return Err(ExpressionError {
+                message: format!("{} takes {} arguments", name, expected),
+                position,
+            });
+        }
+        self.next += 1;
+        Ok(Node::Call(function, arguments))
+    }
+
+    fn operand(&mut self) -> Result<Node, ExpressionError> {
+        let Some(token) = self.peek().cloned() else {
+            return Err(self.error("expected a value"));
+        };
+        let followed_by_paren =
+            matches!(self.tokens.get(self.next + 1), Some((Token::OpenParen, _)));
+        let node = match token {
+            Token::Number(n) => Node::Literal(Value::Number(n)),
+            Token::Text(text) => Node::Literal(Value::Text(text)),
+            Token::ColumnIndex(column) => Node::Column(ColumnRef::Index(column)),
+            Token::QuotedName(name) => Node::Column(ColumnRef::Name(name)),
+            Token::Word(word) if word.eq_ignore_ascii_case("true") => {
+                Node::Literal(Value::Bool(true))
+            }
+            Token::Word(word) if word.eq_ignore_ascii_case("false") => {
+                Node::Literal(Value::Bool(false))
+            }
+            Token::Word(word) if word.eq_ignore_ascii_case("null") => Node::Literal(Value::Null),
+            Token::Word(word) if is_reserved(&word) => {
+                return Err(self.error(&format!("expected a value but found {}", word)));
+            }
+            Token::Word(word) if followed_by_paren => return self.call(&word),
+            Token::Word(word) => Node::Column(ColumnRef::Name(word)),
+            Token::OpenParen => {
+                self.next += 1;
+                let node = self.or()?;
+                if self.peek() != Some(&Token::CloseParen) {
+                    return Err(self.error("expected )"));
+                }
+                node
+            }
+            Token::CloseParen | Token::Comma | Token::Operator(_) | Token::Comparison(_) => {
+                return Err(self.error("expected a value"));
+            }
+        };
+        self.next += 1;
+        Ok(node)
+    }
+}
+
+fn is_reserved(word: &str) -> bool {
+    ["and", "or", "not", "contains", "matches"]
+        .iter()
+        .any(|keyword| word.eq_ignore_ascii_case(keyword))
+}
+
+impl Expression {
+    pub fn parse(source: &str) -> Result<Self, ExpressionError> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            next: 0,
+            end: source.chars().count(),
+        };
+        let root = parser.or()?;
+        if parser.peek().is_some() {
+            return Err(parser.error("expected an operator or the end of the expression"));
+        }
+        Ok(Self {
+            source: source.to_string(),
+            root,
+        })
+    }
+
+    pub fn evaluate(
+        &self,
+        data_handle: &(impl CsvDataHandle + ?Sized),
+        row: usize,
+    ) -> CsvResult<Value> {
+        evaluate(&self.root, data_handle, row)
+    }
+
+    /// Works out the expression for every row, stopping at the first row it fails for.
+    pub fn evaluate_all(
+        &self,
+        data_handle: &(impl CsvDataHandle + ?Sized),
+    ) -> CsvResult<Vec<Option<String>>> {
+        (1..data_handle.row_count() + 1)
+            .map(|row| Ok(self.evaluate(data_handle, row)?.into_cell()))
+            .collect()
+    }
+
+    /// Whether `row` passes this expression used as a filter.
+    pub fn accepts(
+        &self,
+        data_handle: &(impl CsvDataHandle + ?Sized),
+        row: usize,
+    ) -> CsvResult<bool> {
+        Ok(self.evaluate(data_handle, row)?.is_truthy())
+    }
+}
+
+impl Display for Expression {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+fn evaluate(
+    node: &Node,
+    data_handle: &(impl CsvDataHandle + ?Sized),
+    row: usize,
+) -> CsvResult<Value> {
+    let value = match node {
+        Node::Literal(value) => value.clone(),
+        Node::Column(column) => {
+            let column = column.resolve(data_handle)?;
+            match data_handle.cell(Index { row, column })? {
+                Some(text) => Value::Text(text.to_string()),
+                None => Value::Null,
+            }
+        }
+        Node::Negate(node) => match number(evaluate(node, data_handle, row)?, row)? {
+            Some(n) => Value::Number(-n),
+            None => Value::Null,
+        },
+        Node::Arithmetic(left, operator, right) => {
+            let left = number(evaluate(left, data_handle, row)?, row)?;
+            let right = number(evaluate(right, data_handle, row)?, row)?;
+            let (Some(left), Some(right)) = (left, right) else {
+                return Ok(Value::Null);
+            };
+            if right == 0.0 && matches!(operator, Operator::Divide | Operator::Remainder) {
+                return Err(evaluation_failed(row, "division by zero".to_string()));
+            }
+            Value::Number(match operator {
+                Operator::Add => left + right,
+                Operator::Subtract => left - right,
+                Operator::Multiply => left * right,
+                Operator::Divide => left / right,
+                Operator::Remainder => left % right,
+            })
+        }
+        Node::Call(function, arguments) => call(*function, arguments, data_handle, row)?,
+        Node::Not(node) => Value::Bool(!evaluate(node, data_handle, row)?.is_truthy()),
+        Node::And(left, right) => Value::Bool(
+            evaluate(left, data_handle, row)?.is_truthy()
+                && evaluate(right, data_handle, row)?.is_truthy(),
+        ),
+        Node::Or(left, right) => Value::Bool(
+            evaluate(left, data_handle, row)?.is_truthy()
+                || evaluate(right, data_handle, row)?.is_truthy(),
+        ),
+        Node::Compare(left, comparison, right) => {
+            let ordering = compare(
+                &evaluate(left, data_handle, row)?,
+                &evaluate(right, data_handle, row)?,
+            );
+            Value::Bool(match comparison {
+                Comparison::Equal => ordering.is_eq(),
+                Comparison::NotEqual => ordering.is_ne(),
+                Comparison::Less => ordering.is_lt(),
+                Comparison::LessOrEqual => ordering.is_le(),
+                Comparison::Greater => ordering.is_gt(),
+                Comparison::GreaterOrEqual => ordering.is_ge(),
+            })
+        }
+        Node::Contains(left, right) => {
+            let haystack = evaluate(left, data_handle, row)?;
+            let needle = evaluate(right, data_handle, row)?;
+            Value::Bool(haystack.as_text().contains(needle.as_text().as_ref()))
+        }
+        Node::Matches(left, regex) => {
+            Value::Bool(regex.is_match(&evaluate(left, data_handle, row)?.as_text()))
+        }
+    };
+    Ok(value)
+}
+
+fn call(
+    function: Function,
+    arguments: &[Node],
+    data_handle: &(impl CsvDataHandle + ?Sized),
+    row: usize,
+) -> CsvResult<Value> {
+    if function == Function::If {
+        let branch = if evaluate(&arguments[0], data_handle, row)?.is_truthy() {
+            &arguments[1]
+        } else {
+            &arguments[2]
+        };
+        return evaluate(branch, data_handle, row);
+    }
+    let mut values = Vec::new();
+    for argument in arguments {
+        values.push(evaluate(argument, data_handle, row)?);
+    }
+    let text = |value: &Value| value.as_text().into_owned();
+    let value = match function {
+        Function::Concat => Value::Text(values.iter().map(text).collect()),
+        Function::Coalesce => values
+            .into_iter()
+            .find(|value| *value != Value::Null)
+            .unwrap_or(Value::Null),
+        // The rest give null for a null first argument.
+        _ if values[0] == Value::Null => Value::Null,
+        Function::Upper => Value::Text(values[0].as_text().to_uppercase()),
+        Function::Lower => Value::Text(values[0].as_text().to_lowercase()),
+        Function::Trim => Value::Text(values[0].as_text().trim().to_string()),
+        Function::Len => Value::Number(values[0].as_text().chars().count() as f64),
+        Function::Substr => {
+            let start = whole_number(values[1].clone(), row)?.max(1) as usize;
+            let length = match values.get(2) {
+                Some(length) => whole_number(length.clone(), row)?.max(0) as usize,
+                None => usize::MAX,
+            };
+            let text = values[0].as_text();
+            Value::Text(text.chars().skip(start - 1).take(length).collect())
+        }
+        Function::Replace => Value::Text(
+            values[0]
+                .as_text()
+                .replace(values[1].as_text().as_ref(), &values[2].as_text()),
+        ),
+        Function::Round => {
+            let n = number(values[0].clone(), row)?.unwrap_or_default();
+            let digits = match values.get(1) {
+                Some(digits) => whole_number(digits.clone(), row)?,
+                None => 0,
+            };
+            let scale = 10f64.powi(digits as i32);
+            Value::Number((n * scale).round() / scale)
+        }
+        Function::Abs => Value::Number(number(values[0].clone(), row)?.unwrap_or_default().abs()),
+        Function::If => unreachable!("if is evaluated lazily above"),
+    };
+    Ok(value)
+}
+
+/// Reads `value` as a number for arithmetic, with `None` for null.
+fn number(value: Value, row: usize) -> CsvResult<Option<f64>> {
+    match value {
+        Value::Null => Ok(None),
+        value => match value.as_number() {
+            Some(n) => Ok(Some(n)),
+            None => Err(evaluation_failed(
+                row,
+                format!("{:?} is not a number", value.as_text()),
+            )),
+        },
+    }
+}
+
+fn whole_number(value: Value, row: usize) -> CsvResult<i64> {
+    match number(value, row)? {
+        Some(n) if n.fract() == 0.0 => Ok(n as i64),
+        Some(n) => Err(evaluation_failed(
+            row,
+            format!("{} is not a whole number", n),
+        )),
+        None => Err(evaluation_failed(
+            row,
+            "expected a number but found null".to_string(),
+        )),
+    }
+}
+
+fn evaluation_failed(row: usize, message: String) -> CsvError {
+    CsvError::EvaluationFailed { row, message }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::csv_holder::CsvHolder;
+
+    fn csv_holder() -> CsvHolder {
+        CsvHolder::from_strs(
+            &["name", "status", "retries", "last run"],
+            &[
+                &["build", "failed", "10", "2024-03-01"],
+                &["test", "passed", "9", ""],
+                &["deploy", "FAILED", "2.5", "2024-02-11"],
+            ],
+            &[Index { row: 2, column: 4 }],
+        )
+    }
+
+    fn matching_rows(source: &str) -> Vec<usize> {
+        let csv_holder = csv_holder();
+        let expression = Expression::parse(source).unwrap();
+        (1..=csv_holder.row_count())
+            .filter(|&row| expression.accepts(&csv_holder, row).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_comparisons() {
+        assert_eq!(matching_rows("status = \"failed\""), vec![1]);
+        assert_eq!(matching_rows("status != 'failed'"), vec![2, 3]);
+        assert_eq!(matching_rows("retries > 9"), vec![1]);
+        assert_eq!(matching_rows("retries <= 9.0"), vec![2, 3]);
+        assert_eq!(matching_rows("$3 >= -1"), vec![1, 2, 3]);
+        assert_eq!(matching_rows("name < \"e\""), vec![1, 3]);
+        assert_eq!(matching_rows("`last run` < '2024-03'"), vec![2, 3]);
+    }
+
+    #[test]
+    fn test_logic_and_patterns() {
+        assert_eq!(
+            matching_rows("status = 'passed' or retries < 5 and name contains 'loy'"),
+            vec![2, 3]
+        );
+        assert_eq!(matching_rows("NOT (status = 'passed')"), vec![1, 3]);
+        assert_eq!(matching_rows("status matches '(?i)^fail'"), vec![1, 3]);
+        assert_eq!(matching_rows("`last run`"), vec![1, 3]);
+        assert_eq!(matching_rows("not $4 or true = false"), vec![2]);
+    }
+
+    #[test]
+    fn test_unknown_column() {
+        let csv_holder = csv_holder();
+        let expression = Expression::parse("owner = 'me'").unwrap();
+        assert!(expression.accepts(&csv_holder, 1).is_err());
+        let expression = Expression::parse("$5 = 'me'").unwrap();
+        assert!(expression.accepts(&csv_holder, 1).is_err());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let position = |source: &str| Expression::parse(source).unwrap_err().position;
+        assert_eq!(position("status = "), 9);
+        assert_eq!(position("status = 'failed"), 9);
+        assert_eq!(position("(status = 'x'"), 13);
+        assert_eq!(position("status 'x'"), 7);
+        assert_eq!(position("status matches '('"), 15);
+        assert_eq!(position("status matches name"), 15);
+        assert_eq!(position("status = and"), 9);
+        assert_eq!(position("$0 = 1"), 0);
+        assert_eq!(position("a ! b"), 2);
+        assert_eq!(
+            Expression::parse("status =").unwrap_err().to_string(),
+            "expected a value but the expression ended at character 9"
+        );
+        assert_eq!(position("shout(name)"), 0);
+        assert_eq!(position("retries * round(1, 2, 3)"), 10);
+        assert_eq!(position("upper(name name)"), 11);
+    }
+
+    fn values(source: &str) -> Vec<Option<String>> {
+        let expression = Expression::parse(source).unwrap();
+        expression.evaluate_all(&csv_holder()).unwrap()
+    }
+
+    #[test]
+    fn test_arithmetic_and_functions() {
+        let cells = |cells: &[Option<&str>]| {
+            cells
+                .iter()
+                .map(|cell| cell.map(String::from))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            values("retries * 2 + 1"),
+            cells(&[Some("21"), Some("19"), Some("6")])
+        );
+        assert_eq!(
+            values("-retries % 4 - (1 - 3)"),
+            cells(&[Some("0"), Some("1"), Some("-0.5")])
+        );
+        assert_eq!(
+            values("upper(trim(concat(' ', name, '!')))"),
+            cells(&[Some("BUILD!"), Some("TEST!"), Some("DEPLOY!")])
+        );
+        assert_eq!(
+            values("if(lower(status) = 'failed', substr(name, 2, 3), len(name))"),
+            cells(&[Some("uil"), Some("4"), Some("epl")])
+        );
+        assert_eq!(
+            values("round(retries / 3, 2)"),
+            cells(&[Some("3.33"), Some("3"), Some("0.83")])
+        );
+        assert_eq!(
+            values("coalesce(replace(`last run`, '-', '/'), 'never')"),
+            cells(&[Some("2024/03/01"), Some("never"), Some("2024/02/11")])
+        );
+        assert_eq!(values("upper($4)")[1], None);
+        assert_eq!(values("null"), cells(&[None, None, None]));
+    }
+
+    #[test]
+    fn test_evaluation_errors() {
+        let csv_holder = csv_holder();
+        let error = |source: &str| {
+            let expression = Expression::parse(source).unwrap();
+            expression
+                .evaluate_all(&csv_holder)
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(error("name + 1"), "Row 1: \"build\" is not a number");
+        assert_eq!(error("retries / (retries - 9)"), "Row 2: division by zero");
+        assert_eq!(
+            error("substr(name, retries)"),
+            "Row 3: 2.5 is not a whole number"
+        );
+        assert_eq!(values("if(true, 1, 1 / 0)"), vec![Some("1".to_string()); 3]);
+    }
+}
@@ -0,0 +1,288 @@
+use crate::{
+    csv_data_handle::{ColumnRef, CsvDataHandle, CsvError, CsvResult, Index},
+    csv_holder::CsvHolder,
+};
+use std::{collections::HashMap, fmt::Display};
+
+/// What `group_by` works out for each group from one column. Cells that hold no value, or an
+/// empty one, are left out of every aggregate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AggregateFunction {
+    Count,
+    Sum,
+    Mean,
+    /// Compared as numbers when every value in the group is one, and as text otherwise.
+    Min,
+    Max,
+    First,
+    Last,
+    /// The values joined by the separator.
+    Concat(String),
+}
+
+impl AggregateFunction {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "count" => Some(AggregateFunction::Count),
+            "sum" => Some(AggregateFunction::Sum),
+            "mean" | "avg" => Some(AggregateFunction::Mean),
+            "min" => Some(AggregateFunction::Min),
+            "max" => Some(AggregateFunction::Max),
+            "first" => Some(AggregateFunction::First),
+            "last" => Some(AggregateFunction::Last),
+            "concat" => Some(AggregateFunction::Concat(DEFAULT_SEPARATOR.to_string())),
+            _ => None,
+        }
+    }
+}
+
+impl Display for AggregateFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AggregateFunction::Count => write!(f, "count"),
+            AggregateFunction::Sum => write!(f, "sum"),
+            AggregateFunction::Mean => write!(f, "mean"),
+            AggregateFunction::Min => write!(f, "min"),
+            AggregateFunction::Max => write!(f, "max"),
+            AggregateFunction::First => write!(f, "first"),
+            AggregateFunction::Last => write!(f, "last"),
+            AggregateFunction::Concat(_) => write!(f, "concat"),
+        }
+    }
+}
+
+pub const DEFAULT_SEPARATOR: &str = ", ";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Aggregation {
+    pub function: AggregateFunction,
+    /// `None` only for `count`, which then counts the rows in the group.
+    pub column: Option<ColumnRef>,
+}
+
+/// A sheet with one row for each distinct combination of values in `columns`, in the order the
+/// combinations first appear, followed by a column for each of `aggregations`. The aggregate
+/// columns are headed like `sum(price)`.
+pub fn group_by(
+    data_handle: &(impl CsvDataHandle + ?Sized),
+    columns: &[ColumnRef],
+    aggregations: &[Aggregation],
+) -> CsvResult<CsvHolder> {
+    let source_headers = data_handle.headers();
+    let mut headers = Vec::new();
+    let mut key_cells = Vec::new();
+    for column in columns {
+        let column = column.resolve(data_handle)?;
+        headers.push(source_headers[column - 1].to_string());
+        key_cells.push(data_handle.column_cells(column)?);
+    }
+    let mut aggregate_columns = Vec::new();
+    for aggregation in aggregations {
+        let column = match &aggregation.column {
+            Some(column) => {
+                let column = column.resolve(data_handle)?;
+                let header = source_headers[column - 1];
+                headers.push(format!("{}({})", aggregation.function, header));
+                Some((column, data_handle.column_cells(column)?))
+            }
+            None => {
+                headers.push(aggregation.function.to_string());
+                None
+            }
+        };
+        aggregate_columns.push((&aggregation.function, column));
+    }
+
+    let mut groups = Vec::<(Vec<Option<&str>>, Vec<usize>)>::new();
+    let mut group_of_key = HashMap::new();
+    for row in 1..data_handle.row_count() + 1 {
+        let key = key_cells
+            .iter()
+            .map(|cells| cells[row - 1])
+            .collect::<Vec<_>>();
+        let group = *group_of_key.entry(key.clone()).or_insert_with(|| {
+            groups.push((key, Vec::new()));
+            groups.len() - 1
+        });
+        groups[group].1.push(row);
+    }
+
+    let mut data = Vec::new();
+    let mut nulls = Vec::new();
+    for (key, rows) in groups {
+        let mut cells = key
+            .into_iter()
+            .map(|cell| cell.map(str::to_string))
+            .collect::<Vec<_>>();
+        for (function, column) in &aggregate_columns {
+            let cell = match column {
+                Some((column, column_cells)) => {
+                    let values = rows
+                        .iter()
+                        .filter_map(|&row| match column_cells[row - 1] {
+                            Some("") | None => None,
+                            Some(value) => Some((row, value)),
+                        })
+                        .collect::<Vec<_>>();
+                    aggregate(function, *column, &values)?
+                }
+                None => Some(rows.len().to_string()),
+            };
+            cells.push(cell);
+        }
+        nulls.push(cells.iter().map(Option::is_none).collect());
+        data.push(cells.into_iter().map(Option::unwrap_or_default).collect());
+    }
+    Ok(CsvHolder::with_nulls(headers, data, nulls).expect("every row has a cell for each header"))
+}
+
+/// Works out `function` over the values of one group, given with the rows they come from.
+fn aggregate(
+    function: &AggregateFunction,
+    column: usize,
+    values: &[(usize, &str)],
+) -> CsvResult<Option<String>> {
+    let numbers = || {
+        values
+            .iter()
+            .map(|&(row, value)| match value.trim().parse::<f64>() {
+                Ok(n) if n.is_finite() => Ok(n),
+                _ => Err(CsvError::NotANumber(Index { row, column })),
+            })
+            .collect::<CsvResult<Vec<_>>>()
+    };
+    let cell = match function {
+        AggregateFunction::Count => Some(values.len().to_string()),
+        AggregateFunction::Sum => Some(numbers()?.into_iter().sum::<f64>().to_string()),
+        AggregateFunction::Mean => {
+            let numbers = numbers()?;
+            (!numbers.is_empty())
+                .then(|| (numbers.iter().sum::<f64>() / numbers.len() as f64).to_string())
+        }
+        AggregateFunction::Min | AggregateFunction::Max => {
+            let mut sorted = values.iter().map(|&(_, value)| value).collect::<Vec<_>>();
+            match numbers() {
+                Ok(numbers) => {
+                    let mut by_number = numbers.into_iter().zip(sorted).collect::<Vec<_>>();
+                    by_number.sort_by(|a, b| a.0.total_cmp(&b.0));
+                    sorted = by_number.into_iter().map(|(_, value)| value).collect();
+                }
+                Err(_) => sorted.sort(),
+            }
+            let value = match function {
+                AggregateFunction::Min => sorted.first(),
+                _ => sorted.last(),
+            };
+            value.map(|value| value.to_string())
+        }
+        AggregateFunction::First => values.first().map(|(_, value)| value.to_string()),
+        AggregateFunction::Last => values.last().map(|(_, value)| value.to_string()),
+        AggregateFunction::Concat(separator) => Some(
+            values
+                .iter()
+                .map(|(_, value)| *value)
+                .collect::<Vec<_>>()
+                .join(separator),
+        ),
+    };
+    Ok(cell)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sales() -> CsvHolder {
+        CsvHolder::from_strs(
+            &["region", "rep", "amount"],
+            &[
+                &["north", "ann", "10"],
+                &["south", "bob", "2.5"],
+                &["north", "cat", "9"],
+                &["north", "ann", ""],
+                &["", "dan", "4"],
+            ],
+            &[Index { row: 5, column: 1 }],
+        )
+    }
+
+    fn aggregation(function: AggregateFunction, column: &str) -> Aggregation {
+        Aggregation {
+            function,
+            column: (!column.is_empty()).then(|| ColumnRef::parse(column)),
+        }
+    }
+
+    #[test]
+    fn test_group_by() {
+        let aggregations = [
+            aggregation(AggregateFunction::Count, ""),
+            aggregation(AggregateFunction::Count, "amount"),
+            aggregation(AggregateFunction::Sum, "amount"),
+            aggregation(AggregateFunction::Mean, "3"),
+            aggregation(AggregateFunction::Min, "amount"),
+            aggregation(AggregateFunction::Max, "rep"),
+            aggregation(AggregateFunction::First, "rep"),
+            aggregation(AggregateFunction::Last, "rep"),
+            aggregation(AggregateFunction::Concat("/".to_string()), "rep"),
+        ];
+        let result = group_by(&sales(), &[ColumnRef::parse("region")], &aggregations).unwrap();
+        assert_eq!(
+            result.headers(),
+            [
+                "region",
+                "count",
+                "count(amount)",
+                "sum(amount)",
+                "mean(amount)",
+                "min(amount)",
+                "max(rep)",
+                "first(rep)",
+                "last(rep)",
+                "concat(rep)"
+            ]
+        );
+        assert_eq!(
+            result.row(1).unwrap(),
+            [
+                "north",
+                "3",
+                "2",
+                "19",
+                "9.5",
+                "9",
+                "cat",
+                "ann",
+                "ann",
+                "ann/cat/ann"
+            ]
+        );
+        assert_eq!(
+            result.row(2).unwrap(),
+            ["south", "1", "1", "2.5", "2.5", "2.5", "bob", "bob", "bob", "bob"]
+        );
+        assert_eq!(result.row_cells(3).unwrap()[..2], [None, Some("1")]);
+    }
+
+    #[test]
+    fn test_several_columns_and_errors() {
+        let columns = [ColumnRef::parse("region"), ColumnRef::parse("rep")];
+        let aggregations = [aggregation(AggregateFunction::Mean, "amount")];
+        let result = group_by(&sales(), &columns, &aggregations).unwrap();
+        assert_eq!(result.row_count(), 4);
+        assert_eq!(
+            result.row_cells(1).unwrap(),
+            [Some("north"), Some("ann"), Some("10")]
+        );
+
+        let aggregations = [aggregation(AggregateFunction::Sum, "rep")];
+        assert_eq!(
+            group_by(&sales(), &columns, &aggregations)
+                .unwrap_err()
+                .to_string(),
+            "Cell (1,2) is not a number"
+        );
+        let aggregations = [aggregation(AggregateFunction::Sum, "price")];
+        assert!(group_by(&sales(), &columns, &aggregations).is_err());
+    }
+}
@@ -0,0 +1,272 @@
+use crate::{
+    csv_data_handle::{CsvDataHandle, CsvResult},
+    csv_holder::CsvHolder,
+};
+use std::collections::HashMap;
+
+/// Which rows a join keeps besides those that match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum JoinKind {
+    /// Only rows that match.
+    #[default]
+    Inner,
+    /// Every row of the left sheet.
+    Left,
+    /// Every row of the right sheet.
+    Right,
+    /// Every row of both.
+    Outer,
+}
+
+impl JoinKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "inner" => Some(JoinKind::Inner),
+            "left" => Some(JoinKind::Left),
+            "right" => Some(JoinKind::Right),
+            "outer" => Some(JoinKind::Outer),
+            _ => None,
+        }
+    }
+}
+
+pub const DEFAULT_SUFFIXES: (&str, &str) = ("_left", "_right");
+
+/// Joins the rows of `left` and `right` whose cells are equal in every pair of `keys`, given as
+/// (left column, right column). The result has the columns of `left` followed by those of
+/// `right` apart from its key columns. A header found on both sides gets the first of `suffixes`
+/// on the left and the second on the right. Cells that hold no value, or an empty one, never
+/// match.
+pub fn join(
+    left: &(impl CsvDataHandle + ?Sized),
+    right: &(impl CsvDataHandle + ?Sized),
+    keys: &[(usize, usize)],
+    kind: JoinKind,
+    suffixes: (&str, &str),
+) -> CsvResult<CsvHolder> {
+    let left_keys = keys
+        .iter()
+        .map(|&(column, _)| left.column_cells(column))
+        .collect::<CsvResult<Vec<_>>>()?;
+    let right_keys = keys
+        .iter()
+        .map(|&(_, column)| right.column_cells(column))
+        .collect::<CsvResult<Vec<_>>>()?;
+
+    let mut right_rows_of_key = HashMap::<Vec<&str>, Vec<usize>>::new();
+    for row in 1..right.row_count() + 1 {
+        if let Some(key) = key_of(&right_keys, row) {
+            right_rows_of_key.entry(key).or_default().push(row);
+        }
+    }
+
+    let right_columns = (1..right.column_count() + 1)
+        .filter(|column| !keys.iter().any(|&(_, key)| key == *column))
+        .collect::<Vec<_>>();
+    let headers = headers(left, right, &right_columns, suffixes);
+
+    let keep_left = matches!(kind, JoinKind::Left | JoinKind::Outer);
+    let keep_right = matches!(kind, JoinKind::Right | JoinKind::Outer);
+    let mut right_matched = vec![false; right.row_count()];
+    let mut rows = Vec::<Vec<Option<&str>>>::new();
+    for left_row in 1..left.row_count() + 1 {
+        let left_cells = left.row_cells(left_row)?;
+        let matches = key_of(&left_keys, left_row)
+            .and_then(|key| right_rows_of_key.get(&key))
+            .map_or(&[][..], Vec::as_slice);
+        for &right_row in matches {
+            right_matched[right_row - 1] = true;
+            let right_cells = right.row_cells(right_row)?;
+            let mut cells = left_cells.clone();
+            cells.extend(right_columns.iter().map(|column| right_cells[column - 1]));
+            rows.push(cells);
+        }
+        if matches.is_empty() && keep_left {
+            let mut cells = left_cells;
+            cells.resize(headers.len(), None);
+            rows.push(cells);
+        }
+    }
+    if keep_right {
+        for right_row in 1..right.row_count() + 1 {
+            if right_matched[right_row - 1] {
+                continue;
+            }
+            let right_cells = right.row_cells(right_row)?;
+            let mut cells = vec![None; left.column_count()];
+            for &(left_column, right_column) in keys {
+                cells[left_column - 1] = right_cells[right_column - 1];
+            }
+            cells.extend(right_columns.iter().map(|column| right_cells[column - 1]));
+            rows.push(cells);
+        }
+    }
+
+    let nulls = rows
+        .iter()
+        .map(|cells| cells.iter().map(Option::is_none).collect())
+        .collect();
+    let data = rows
+        .into_iter()
+        .map(|cells| {
+            cells
+                .into_iter()
+                .map(|cell| cell.unwrap_or_default().to_string())
+                .collect()
+        })
+        .collect();
+    Ok(CsvHolder::with_nulls(headers, data, nulls).expect("every row has a cell for each header"))
+}
+
+/// The key cells of `row`, unless one of them is empty.
+fn key_of<'a>(key_columns: &[Vec<Option<&'a str>>], row: usize) -> Option<Vec<&'a str>> {
+    key_columns
+        .iter()
+        .map(|cells| cells[row - 1].filter(|value| !value.is_empty()))
+        .collect()
+}
+
+fn headers(
+    left: &(impl CsvDataHandle + ?Sized),
+    right: &(impl CsvDataHandle + ?Sized),
+    right_columns: &[usize],
+    (left_suffix, right_suffix): (&str, &str),
+) -> Vec<String> {
+    let left_headers = left.headers();
+    let right_headers = right_columns
+        .iter()
+        .map(|column| right.headers()[column - 1])
+        .collect::<Vec<_>>();
+    let left_part = left_headers.iter().map(|header| {
+        if right_headers.contains(header) {
+            format!("{}{}", header, left_suffix)
+        } else {
+            header.to_string()
+        }
+    });
+    let right_part = right_headers.iter().map(|header| {
+        if left_headers.contains(header) {
+            format!("{}{}", header, right_suffix)
+        } else {
+            header.to_string()
+        }
+    });
+    left_part.chain(right_part).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn orders() -> CsvHolder {
+        CsvHolder::from_strs(
+            &["id", "customer", "total"],
+            &[
+                &["1", "c1", "10"],
+                &["2", "c2", "5"],
+                &["3", "", "7"],
+                &["4", "c1", "1"],
+            ],
+            &[],
+        )
+    }
+
+    fn customers() -> CsvHolder {
+        CsvHolder::from_strs(
+            &["customer", "name", "total"],
+            &[
+                &["c1", "Ann", "11"],
+                &["c3", "Cat", "0"],
+                &["c1", "Annie", "11"],
+            ],
+            &[],
+        )
+    }
+
+    fn joined(kind: JoinKind) -> Vec<Vec<Option<String>>> {
+        let result = join(&orders(), &customers(), &[(2, 1)], kind, DEFAULT_SUFFIXES).unwrap();
+        (1..result.row_count() + 1)
+            .map(|row| {
+                let cells = result.row_cells(row).unwrap();
+                cells
+                    .into_iter()
+                    .map(|cell| cell.map(String::from))
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn row(cells: &[Option<&str>]) -> Vec<Option<String>> {
+        cells.iter().map(|cell| cell.map(String::from)).collect()
+    }
+
+    #[test]
+    fn test_kinds() {
+        let ann = row(&[Some("1"), Some("c1"), Some("10"), Some("Ann"), Some("11")]);
+        let annie = row(&[Some("1"), Some("c1"), Some("10"), Some("Annie"), Some("11")]);
+        let ann_again = row(&[Some("4"), Some("c1"), Some("1"), Some("Ann"), Some("11")]);
+        let annie_again = row(&[Some("4"), Some("c1"), Some("1"), Some("Annie"), Some("11")]);
+        let unmatched_left = [
+            row(&[Some("2"), Some("c2"), Some("5"), None, None]),
+            row(&[Some("3"), Some(""), Some("7"), None, None]),
+        ];
+        let unmatched_right = row(&[None, Some("c3"), None, Some("Cat"), Some("0")]);
+
+        let inner = vec![
+            ann.clone(),
+            annie.clone(),
+            ann_again.clone(),
+            annie_again.clone(),
+        ];
+        assert_eq!(joined(JoinKind::Inner), inner);
+        let left = vec![
+            ann.clone(),
+            annie.clone(),
+            unmatched_left[0].clone(),
+            unmatched_left[1].clone(),
+            ann_again.clone(),
+            annie_again.clone(),
+        ];
+        assert_eq!(joined(JoinKind::Left), left);
+        let mut right = inner;
+        right.push(unmatched_right.clone());
+        assert_eq!(joined(JoinKind::Right), right);
+        let mut outer = left;
+        outer.push(unmatched_right);
+        assert_eq!(joined(JoinKind::Outer), outer);
+    }
+
+    #[test]
+    fn test_headers_and_keys() {
+        let result = join(
+            &orders(),
+            &customers(),
+            &[(2, 1)],
+            JoinKind::Inner,
+            ("", "_c"),
+        )
+        .unwrap();
+        assert_eq!(
+            result.headers(),
+            ["id", "customer", "total", "name", "total_c"]
+        );
+        let result = join(
+            &orders(),
+            &customers(),
+            &[(2, 1), (3, 3)],
+            JoinKind::Inner,
+            DEFAULT_SUFFIXES,
+        )
+        .unwrap();
+        assert_eq!(result.headers(), ["id", "customer", "total", "name"]);
+        assert_eq!(result.row_count(), 0);
+        assert!(join(
+            &orders(),
+            &customers(),
+            &[(4, 1)],
+            JoinKind::Inner,
+            DEFAULT_SUFFIXES
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,93 @@
+//! Reading, editing and writing CSV files, as done by the `bootleg_editor_3000` editor.
+//!
+//! - Reading: [`read_csv`] loads a file into a [`CsvHolder`]. [`ColumnarCsv`] and [`MappedCsv`]
+//!   keep a file column by column or leave it on disk, and [`sniff_dialect`] guesses how a file
+//!   is laid out when that is not known.
+//! - Writing: [`write_csv`] writes any sheet out again, and [`write_csv_file`] replaces a file
+//!   with one.
+//! - Data: every kind of sheet is worked on through [`CsvDataHandle`]. Rows and columns are
+//!   counted from 1, and a cell is given by its [`Index`].
+//! - Requests: [`parse_csv_request`] reads a command such as `set 2 price 10` into a
+//!   [`CsvRequest`], and [`handle_csv_request`] carries it out on a sheet. A [`Session`] keeps
+//!   the undo history, filter and schema from one request to the next.
+//!   [`handle_sheet_request`] and [`handle_buffer_request`] also carry out the requests that
+//!   switch to another sheet or buffer.
+//!
+//! ```
+//! use bootleg_editor_3000::{
+//!     handle_csv_request, parse_csv_request, read_csv, write_csv, CsvDataHandle, CsvFormat,
+//!     Index, QuotePolicy, RequestOptions, Session,
+//! };
+//!
+//! let format = CsvFormat::default();
+//! let mut sheet = read_csv("name,qty\npear,3\nplum,5\n".as_bytes(), &format)?;
+//! assert_eq!(sheet.data_at(Index { row: 2, column: 1 })?, "plum");
+//!
+//! let mut session = Session::new(&sheet);
+//! let request = parse_csv_request("set 1 qty 4", &format).expect("a valid request");
+//! let options = RequestOptions::default();
+//! handle_csv_request(request, &mut sheet, &mut session, &options, &mut std::io::sink())?;
+//!
+//! let mut out = Vec::new();
+//! write_csv(&mut out, &sheet, &format, QuotePolicy::default())?;
+//! assert_eq!(String::from_utf8(out)?, "name,qty\npear,4\nplum,5\n");
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+/// Running commands one after another without a prompt.
+pub mod batch;
+/// Several sheets open at once, one of which requests act on.
+pub mod buffers;
+/// Sheets kept column by column.
+pub mod columnar_csv;
+/// The interface every kind of sheet offers, and its errors.
+pub mod csv_data_handle;
+/// Sheets kept row by row in memory.
+pub mod csv_holder;
+/// Reading and writing CSV files.
+pub mod csv_io;
+/// Parsing and carrying out the editor's commands.
+pub mod csv_request;
+mod csv_tokenizer;
+/// How a file delimits, quotes and ends its fields and lines.
+pub mod dialect;
+/// Undo and redo.
+pub mod edit_history;
+/// The expressions used by `filter`, `add_column` and `update_column`.
+pub mod expression;
+/// Grouping rows and aggregating over the groups.
+pub mod group;
+/// Joining two sheets on key columns.
+pub mod join;
+/// Sheets read from a memory-mapped file as they are needed.
+pub mod mapped_csv;
+/// Problems with the text of a file, and where they lie.
+pub mod parse_error;
+/// The types columns are expected to hold.
+pub mod schema;
+/// Finding and replacing text in cells.
+pub mod search;
+/// A sheet in whichever storage it was loaded into.
+pub mod sheet;
+/// Ordering rows.
+pub mod sort;
+/// Summaries of a column's values.
+pub mod stats;
+mod table;
+
+pub use buffers::{Buffer, Buffers};
+pub use columnar_csv::ColumnarCsv;
+pub use csv_data_handle::{ColumnRef, CsvDataHandle, CsvError, CsvResult, Index};
+pub use csv_holder::CsvHolder;
+pub use csv_io::{
+    read_csv, write_csv, write_csv_encoded, write_csv_file, CsvFormat, CsvIoError, Encoding,
+    ParseMode, QuotePolicy,
+};
+pub use csv_request::{
+    handle_buffer_request, handle_csv_request, handle_sheet_request, parse_csv_request, CsvRequest,
+    RequestError, RequestErrorKind, RequestOptions, Session,
+};
+pub use dialect::{sniff_dialect, Dialect};
+pub use mapped_csv::MappedCsv;
+pub use parse_error::{ParseError, ParseErrorKind, Position};
+pub use sheet::{Sheet, Storage};
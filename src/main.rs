@@ -1,29 +1,183 @@
-mod csv_data_handle;
-mod csv_holder;
-mod csv_io;
-mod csv_request;
+mod cli;
 
-use std::io::Write;
+use bootleg_editor_3000::{
+    batch, buffers, columnar_csv, csv_io, csv_request, dialect, mapped_csv, sheet,
+};
+use std::{
+    io::{BufRead, Write},
+    process::ExitCode,
+};
 
-fn main() -> std::io::Result<()> {
+const EXIT_USAGE: u8 = 2;
+const EXIT_CANNOT_OPEN: u8 = 3;
+const EXIT_INVALID_CSV: u8 = 4;
+const EXIT_COMMAND_FAILED: u8 = 5;
+const EXIT_UNSAVED_CHANGES: u8 = 6;
+
+fn main() -> ExitCode {
+    let mut options = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(cli::CliCommand::Run(options)) => options,
+        Ok(cli::CliCommand::Help) => {
+            println!("{}", cli::USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!("{}", cli::USAGE);
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+    let loaded = match options.input.clone() {
+        cli::Input::Stdin => load_csv(std::io::stdin().lock(), &mut options),
+        cli::Input::File(path) => {
+            let Ok(file) = std::fs::File::open(&path) else {
+                eprintln!("Error opening CSV file: {}", path.display());
+                return ExitCode::from(EXIT_CANNOT_OPEN);
+            };
+            match options.storage {
+                sheet::Storage::File => map_csv(&file, &mut options).map(sheet::Sheet::from),
+                _ => load_csv(std::io::BufReader::new(file), &mut options),
+            }
+        }
+    };
+    let sheet = match loaded {
+        Ok(sheet) => sheet,
+        Err(e) => {
+            eprintln!("Error reading CSV: {}", e);
+            if let Some(snippet) = e.snippet() {
+                eprint!("{}", snippet);
+            }
+            return ExitCode::from(EXIT_INVALID_CSV);
+        }
+    };
+    if options.is_batch() {
+        return run_batch(sheet, &options);
+    }
+    let save_path = match options.input {
+        _ if options.output.is_some() => options.output,
+        cli::Input::File(path) => Some(path),
+        cli::Input::Stdin => None,
+    };
+    let request_options = csv_request::RequestOptions {
+        format: options.format,
+        save_path,
+    };
+    let mut stdin = std::io::stdin().lock();
     let mut stdout = std::io::stdout().lock();
-    let Ok(file) = std::fs::File::open("testdata.csv") else {
-        return writeln!(stdout, "Error opening CSV file");
+    match run_app_loop(sheet, &request_options, &mut stdin, &mut stdout) {
+        Ok(exit_code) => exit_code,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Reads the input into the chosen storage, first settling on its dialect if none was given on
+/// the command line.
+fn load_csv(
+    mut reader: impl BufRead,
+    options: &mut cli::CliOptions,
+) -> Result<sheet::Sheet, csv_io::CsvIoError> {
+    if options.sniff_dialect {
+        options.format.dialect = dialect::sniff_dialect(&mut reader)?;
+    }
+    match options.storage {
+        sheet::Storage::Columns => {
+            columnar_csv::ColumnarCsv::read(reader, &options.format).map(sheet::Sheet::from)
+        }
+        _ => csv_io::read_csv(reader, &options.format).map(sheet::Sheet::from),
+    }
+}
+
+/// Maps the input file rather than reading it, first settling on its dialect like `load_csv`.
+fn map_csv(
+    file: &std::fs::File,
+    options: &mut cli::CliOptions,
+) -> Result<mapped_csv::MappedCsv, csv_io::CsvIoError> {
+    if options.sniff_dialect {
+        let mut reader = std::io::BufReader::new(file);
+        options.format.dialect = dialect::sniff_dialect(&mut reader)?;
+    }
+    mapped_csv::MappedCsv::open(file, &options.format)
+}
+
+fn run_batch(sheet: sheet::Sheet, options: &cli::CliOptions) -> ExitCode {
+    let mut commands = match &options.script {
+        Some(script) => match batch::read_script(script) {
+            Ok(commands) => commands,
+            Err(e) => {
+                eprintln!("Error reading script {}: {}", script.display(), e);
+                return ExitCode::from(EXIT_CANNOT_OPEN);
+            }
+        },
+        None => Vec::new(),
+    };
+    commands.extend(batch::command_line_commands(&options.commands));
+    let request_options = csv_request::RequestOptions {
+        format: options.format,
+        save_path: options.output.clone(),
+    };
+    let name = match &options.output {
+        Some(path) => buffers::Buffer::name_for(path),
+        None => "stdin".to_string(),
     };
-    let file = std::io::BufReader::new(file);
-    match csv_io::read_csv(file) {
-        Ok(csv_holder) => run_app_loop(csv_holder, &mut stdout)?,
+    let mut buffers = buffers::Buffers::new(buffers::Buffer::new(name, sheet, request_options));
+    let mut stdout = std::io::stdout().lock();
+    let mut stderr = std::io::stderr().lock();
+    let succeeded = match batch::run_commands(
+        &commands,
+        &mut buffers,
+        options.keep_going,
+        &mut stdout,
+        &mut stderr,
+    ) {
+        Ok(succeeded) => succeeded,
         Err(e) => {
-            return writeln!(stdout, "Error reading CSV: {}", e);
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
         }
+    };
+    if !succeeded && !options.keep_going {
+        return ExitCode::from(EXIT_COMMAND_FAILED);
+    }
+    // The output is the loaded sheet's, even if another buffer was opened and left active.
+    let Some(loaded) = buffers.loaded() else {
+        eprintln!("Error writing CSV: the loaded sheet was closed");
+        return ExitCode::FAILURE;
+    };
+    let sheet = &loaded.data;
+    let written = match &options.output {
+        Some(output) => csv_io::write_csv_file(
+            output,
+            sheet,
+            &options.format,
+            csv_io::QuotePolicy::default(),
+        ),
+        None => csv_io::write_csv_encoded(
+            &mut stdout,
+            sheet,
+            &options.format,
+            csv_io::QuotePolicy::default(),
+        ),
+    };
+    if let Err(e) = written {
+        eprintln!("Error writing CSV: {}", e);
+        return ExitCode::FAILURE;
+    }
+    if succeeded {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(EXIT_COMMAND_FAILED)
     }
-    Ok(())
 }
 
 fn run_app_loop(
-    mut csv_holder: csv_holder::CsvHolder,
+    sheet: sheet::Sheet,
+    options: &csv_request::RequestOptions,
+    input: &mut impl BufRead,
     out: &mut impl Write,
-) -> std::io::Result<()> {
+) -> std::io::Result<ExitCode> {
     writeln!(
         out,
         "Welcome To BootlegEditor3000. Your CSV Data Has Been Loaded."
@@ -33,24 +187,148 @@ fn run_app_loop(
         "Below is a list of commands. To see this at any time, type 'help'."
     )?;
     if csv_request::write_help_text(out).is_err() {
-        return writeln!(out, "Error writing to stdout");
+        writeln!(out, "Error writing to stdout")?;
+        return Ok(ExitCode::FAILURE);
     }
+    let name = match &options.save_path {
+        Some(path) => buffers::Buffer::name_for(path),
+        None => "stdin".to_string(),
+    };
+    let mut buffers = buffers::Buffers::new(buffers::Buffer::new(name, sheet, options.clone()));
     loop {
         write!(out, ">>>> ")?;
         out.flush()?;
-        let Some(csv_request) = read_csv_request_from_stdin() else {
-            writeln!(out, "CSV Request Entered Incorrectly")?;
-            continue;
+        let Some(line) = read_line(input)? else {
+            writeln!(out)?;
+            // With no input left there is no staying, so leave, failing if any changes are left
+            // unsaved.
+            offer_to_save_all(&mut buffers, input, out)?;
+            if buffers.iter().any(buffers::Buffer::has_unsaved_changes) {
+                writeln!(out, "Input ended before the unsaved changes were saved")?;
+                return Ok(ExitCode::from(EXIT_UNSAVED_CHANGES));
+            }
+            return Ok(ExitCode::SUCCESS);
+        };
+        let format = &buffers.active().options.format;
+        let csv_request = match csv_request::parse_csv_request(&line, format) {
+            Ok(csv_request) => csv_request,
+            Err(e) => {
+                writeln!(out, "CSV Request Entered Incorrectly: {}", e)?;
+                for syntax in e.usage() {
+                    writeln!(out, "usage: {}", syntax)?;
+                }
+                continue;
+            }
+        };
+        if let csv_request::CsvRequest::Quit = csv_request {
+            match offer_to_save_all(&mut buffers, input, out)? {
+                LeaveDecision::Leave => return Ok(ExitCode::SUCCESS),
+                LeaveDecision::Stay => continue,
+            }
+        }
+        let saves_data = csv_request.saves_data(buffers.active().options.save_path.as_deref());
+        match csv_request::handle_buffer_request(csv_request, &mut buffers, out) {
+            Ok(()) if saves_data => buffers.active_mut().data.mark_saved(),
+            Ok(()) => {}
+            Err(e) => {
+                writeln!(out, "CSV Request Failed: {}", e)?;
+                if let Some(snippet) = e.snippet() {
+                    write!(out, "{}", snippet)?;
+                }
+            }
+        }
+    }
+}
+
+enum LeaveDecision {
+    Leave,
+    Stay,
+}
+
+/// Offers to save each buffer with unsaved changes in turn, staying if any answer says to.
+fn offer_to_save_all(
+    buffers: &mut buffers::Buffers,
+    input: &mut impl BufRead,
+    out: &mut impl Write,
+) -> std::io::Result<LeaveDecision> {
+    let several = buffers.iter().count() > 1;
+    for buffer in buffers.iter_mut() {
+        // Only the loaded sheet has somewhere to be saved to.
+        let original = buffer.session.original_sheet(&mut buffer.data);
+        let name = several.then_some(buffer.name.as_str());
+        if let LeaveDecision::Stay = offer_to_save(original, &buffer.options, name, input, out)? {
+            return Ok(LeaveDecision::Stay);
+        }
+    }
+    Ok(LeaveDecision::Leave)
+}
+
+/// Asks whether to save unsaved changes before leaving, naming the buffer if given its name.
+/// Running out of input counts as answering no.
+fn offer_to_save(
+    sheet: &mut sheet::Sheet,
+    options: &csv_request::RequestOptions,
+    name: Option<&str>,
+    input: &mut impl BufRead,
+    out: &mut impl Write,
+) -> std::io::Result<LeaveDecision> {
+    if !sheet.has_unsaved_changes() {
+        return Ok(LeaveDecision::Leave);
+    }
+    loop {
+        match name {
+            Some(name) => write!(out, "You have unsaved changes in {}. ", name)?,
+            None => write!(out, "You have unsaved changes. ")?,
+        }
+        write!(out, "Save before leaving? [y/n/cancel] ")?;
+        out.flush()?;
+        let Some(answer) = read_line(input)? else {
+            writeln!(out)?;
+            writeln!(out, "Unsaved changes discarded")?;
+            return Ok(LeaveDecision::Leave);
         };
-        if let Err(e) = csv_request::handle_csv_request(csv_request, &mut csv_holder, out) {
-            writeln!(out, "CSV Request Failed: {}", e)?;
+        match answer.as_str() {
+            "y" | "yes" => break,
+            "n" | "no" => return Ok(LeaveDecision::Leave),
+            "c" | "cancel" => return Ok(LeaveDecision::Stay),
+            _ => continue,
+        }
+    }
+    let save_path = match &options.save_path {
+        Some(save_path) => save_path.clone(),
+        None => {
+            write!(out, "File name: ")?;
+            out.flush()?;
+            match read_line(input)? {
+                Some(file_name) if !file_name.is_empty() => file_name.into(),
+                _ => return Ok(LeaveDecision::Stay),
+            }
+        }
+    };
+    let saved = csv_io::write_csv_file(
+        &save_path,
+        &*sheet,
+        &options.format,
+        csv_io::QuotePolicy::default(),
+    );
+    match saved {
+        Ok(()) => {
+            sheet.mark_saved();
+            writeln!(out, "Saved to {}", save_path.display())?;
+            Ok(LeaveDecision::Leave)
+        }
+        Err(e) => {
+            writeln!(out, "Could not save to {}: {}", save_path.display(), e)?;
+            Ok(LeaveDecision::Stay)
         }
     }
 }
 
-fn read_csv_request_from_stdin() -> Option<csv_request::CsvRequest> {
-    let mut csv_request = String::new();
-    std::io::stdin().read_line(&mut csv_request).ok()?;
-    let csv_request = csv_request.trim();
-    csv_request::parse_csv_request(csv_request)
+/// Reads a trimmed line, or `None` once the input has run out.
+fn read_line(input: &mut impl BufRead) -> std::io::Result<Option<String>> {
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim().to_string()))
 }
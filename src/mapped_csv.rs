@@ -0,0 +1,551 @@
+use crate::{
+    csv_data_handle::*,
+    csv_io::{CsvFormat, CsvIoError},
+    csv_tokenizer::{field_count_error, scan_record, unescape, FieldSpan, ParseMode},
+    dialect::Dialect,
+    parse_error::{ParseError, ParseErrorKind},
+};
+use memmap2::Mmap;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    sync::OnceLock,
+};
+
+/// A sheet that reads its rows from a memory-mapped file as they are asked for, so that the
+/// file can be larger than memory. Changes are kept apart from the file, which is never written
+/// to; writing the sheet out merges them with the rows that have not changed.
+///
+/// Rows and columns are known by ids that stay the same as others are added, removed or moved.
+/// The ids of the file's own rows and columns are their 0-based positions in it.
+pub struct MappedCsv {
+    map: Mmap,
+    parse_mode: ParseMode,
+    dialect: Dialect,
+    /// Where each row of the file starts, followed by the end of the file.
+    records: Vec<usize>,
+    /// The ids of the file's rows that have escaped fields, each with the text of those fields
+    /// once the row has been read, by their position in the row.
+    unescaped: HashMap<usize, OnceLock<Vec<Option<String>>>>,
+    headers: Vec<String>,
+    file_column_count: usize,
+    /// The row ids in order, or `None` while the rows are still those of the file in order.
+    rows: Option<Vec<usize>>,
+    next_row_id: usize,
+    /// The column ids in order.
+    columns: Vec<usize>,
+    next_column_id: usize,
+    /// Cells given a new value, or none, since the file was opened, by row and column id.
+    edits: BTreeMap<(usize, usize), Option<String>>,
+    /// Whether the data has changed since it was loaded or last saved.
+    modified: bool,
+}
+
+impl MappedCsv {
+    /// Maps `file` and finds where each of its rows starts, reading it as `read_csv` would. The
+    /// file must be UTF-8 and must not change while it is open.
+    pub fn open(file: &File, format: &CsvFormat) -> Result<Self, CsvIoError> {
+        // SAFETY: the map is only ever read, and the file is expected to stay as it is while
+        // it is open; writing the sheet out replaces the file rather than writing into it.
+        let map = unsafe { Mmap::map(file)? };
+        let text = std::str::from_utf8(&map)
+            .map_err(|e| CsvIoError::Parse(ParseError::invalid_utf8(&map, e.valid_up_to())))?;
+        let (parse_mode, dialect) = (format.parse_mode, format.dialect);
+        let empty_error = || {
+            let error = ParseError::at(ParseErrorKind::Empty, text, text.len());
+            CsvIoError::Parse(error)
+        };
+
+        let mut start = 0;
+        let mut headers = None;
+        if format.has_header {
+            let (spans, next) =
+                scan_record(text, start, parse_mode, dialect)?.ok_or_else(empty_error)?;
+            headers = Some(spans);
+            start = next;
+        }
+        let mut records = Vec::new();
+        let mut unescaped = HashMap::new();
+        let mut column_count = headers.as_ref().map(Vec::len);
+        while let Some((spans, next)) = scan_record(text, start, parse_mode, dialect)? {
+            if !is_blank_line(&spans) || column_count == Some(1) {
+                let expected = *column_count.get_or_insert(spans.len());
+                if expected != spans.len() {
+                    let error = field_count_error(text, &spans, expected, dialect);
+                    return Err(CsvIoError::Parse(error));
+                }
+                // Unescaped only when the row is read, so that opening the file copies none of it.
+                if spans.iter().any(|span| span.escaped) {
+                    unescaped.insert(records.len(), OnceLock::new());
+                }
+                records.push(start);
+            }
+            start = next;
+        }
+        records.push(text.len());
+
+        let headers = match headers {
+            Some(spans) => spans
+                .iter()
+                .map(|span| match span.escaped {
+                    true => unescape(&text[span.start..span.end], dialect),
+                    false => text[span.start..span.end].to_string(),
+                })
+                .collect::<Vec<_>>(),
+            None => {
+                let column_count = column_count.ok_or_else(empty_error)?;
+                (1..column_count + 1)
+                    .map(|column| format!("column_{}", column))
+                    .collect()
+            }
+        };
+        let file_column_count = headers.len();
+        Ok(Self {
+            map,
+            parse_mode,
+            dialect,
+            next_row_id: records.len() - 1,
+            records,
+            unescaped,
+            headers,
+            file_column_count,
+            rows: None,
+            columns: (0..file_column_count).collect(),
+            next_column_id: file_column_count,
+            edits: BTreeMap::new(),
+            modified: false,
+        })
+    }
+
+    pub fn has_unsaved_changes(&self) -> bool {
+        self.modified
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.modified = false;
+    }
+
+    fn file_row_count(&self) -> usize {
+        self.records.len() - 1
+    }
+
+    fn row_id(&self, row: usize) -> Option<usize> {
+        if row == 0 || row > self.row_count() {
+            return None;
+        }
+        Some(match &self.rows {
+            Some(rows) => rows[row - 1],
+            None => row - 1,
+        })
+    }
+
+    fn column_id(&self, column: usize) -> Option<usize> {
+        column
+            .checked_sub(1)
+            .and_then(|column| self.columns.get(column))
+            .copied()
+    }
+
+    fn row_ids_mut(&mut self) -> &mut Vec<usize> {
+        let file_row_count = self.file_row_count();
+        self.rows
+            .get_or_insert_with(|| (0..file_row_count).collect())
+    }
+
+    /// The cells of the file's row with id `row_id`.
+    fn file_cells(&self, row_id: usize) -> CsvResult<Vec<Option<&str>>> {
+        let (start, end) = (self.records[row_id], self.records[row_id + 1]);
+        let text = std::str::from_utf8(&self.map[start..end]).map_err(|_| CsvError::FileChanged)?;
+        let (spans, _) = scan_record(text, 0, self.parse_mode, self.dialect)
+            .ok()
+            .flatten()
+            .ok_or(CsvError::FileChanged)?;
+        let unescaped = self.unescaped.get(&row_id).map(|unescaped| {
+            unescaped.get_or_init(|| {
+                spans
+                    .iter()
+                    .map(|span| {
+                        let field = &text[span.start..span.end];
+                        span.escaped.then(|| unescape(field, self.dialect))
+                    })
+                    .collect()
+            })
+        });
+        spans
+            .iter()
+            .enumerate()
+            .map(|(i, span)| {
+                if !span.quoted && span.start == span.end {
+                    return Ok(None);
+                }
+                if span.escaped {
+                    let field = unescaped.and_then(|unescaped| unescaped[i].as_deref());
+                    return field.map(Some).ok_or(CsvError::FileChanged);
+                }
+                Ok(Some(&text[span.start..span.end]))
+            })
+            .collect()
+    }
+
+    /// The cells of `row`, reading the file only if some of them have not changed.
+    fn cells(&self, row: usize) -> CsvResult<Vec<Option<&str>>> {
+        let row_id = self.row_id(row).ok_or(CsvError::NoSuchRow(row))?;
+        let edited = |column_id: usize| self.edits.get(&(row_id, column_id));
+        let from_file = row_id < self.file_row_count()
+            && self.columns.iter().any(|&column_id| {
+                column_id < self.file_column_count && edited(column_id).is_none()
+            });
+        let file_cells = match from_file {
+            true => self.file_cells(row_id)?,
+            false => Vec::new(),
+        };
+        Ok(self
+            .columns
+            .iter()
+            .map(|&column_id| match edited(column_id) {
+                Some(cell) => cell.as_deref(),
+                None => file_cells.get(column_id).copied().flatten(),
+            })
+            .collect())
+    }
+
+    fn set_cell(&mut self, row: usize, column: usize, cell: Option<String>) {
+        if let (Some(row_id), Some(column_id)) = (self.row_id(row), self.column_id(column)) {
+            self.edits.insert((row_id, column_id), cell);
+        }
+    }
+
+    fn index_exists(&self, index: Index) -> bool {
+        self.row_id(index.row).is_some() && self.column_id(index.column).is_some()
+    }
+}
+
+fn is_blank_line(spans: &[FieldSpan]) -> bool {
+    matches!(spans, [span] if !span.quoted && span.start == span.end)
+}
+
+impl CsvDataHandle for MappedCsv {
+    fn data_at(&self, index: Index) -> CsvResult<&str> {
+        Ok(self.cell(index)?.unwrap_or_default())
+    }
+
+    fn is_null(&self, index: Index) -> CsvResult<bool> {
+        Ok(self.cell(index)?.is_none())
+    }
+
+    fn cell(&self, index: Index) -> CsvResult<Option<&str>> {
+        if !self.index_exists(index) {
+            return Err(CsvError::NoSuchIndex(index));
+        }
+        Ok(self.cells(index.row)?[index.column - 1])
+    }
+
+    fn row(&self, row: usize) -> CsvResult<Vec<&str>> {
+        let cells = self.cells(row)?;
+        Ok(cells.into_iter().map(Option::unwrap_or_default).collect())
+    }
+
+    fn row_cells(&self, row: usize) -> CsvResult<Vec<Option<&str>>> {
+        self.cells(row)
+    }
+
+    fn column(&self, column: usize) -> CsvResult<Vec<&str>> {
+        let cells = self.column_cells(column)?;
+        Ok(cells.into_iter().map(Option::unwrap_or_default).collect())
+    }
+
+    fn column_cells(&self, column: usize) -> CsvResult<Vec<Option<&str>>> {
+        if self.column_id(column).is_none() {
+            return Err(CsvError::NoSuchColumn(column));
+        }
+        (1..self.row_count() + 1)
+            .map(|row| Ok(self.cells(row)?[column - 1]))
+            .collect()
+    }
+
+    fn headers(&self) -> Vec<&str> {
+        self.headers.iter().map(|s| s.as_str()).collect()
+    }
+
+    fn property_count(&self) -> usize {
+        self.headers.len()
+    }
+
+    fn row_count(&self) -> usize {
+        match &self.rows {
+            Some(rows) => rows.len(),
+            None => self.file_row_count(),
+        }
+    }
+
+    fn column_count(&self) -> usize {
+        self.headers.len()
+    }
+
+    fn column_of_field(&self, field: &str) -> CsvResult<usize> {
+        self.headers
+            .iter()
+            .position(|s| s == field)
+            .map(|s| s + 1)
+            .ok_or(CsvError::NoSuchField(field.to_string()))
+    }
+
+    fn replace_data_at(&mut self, index: Index, new_data: String) -> CsvResult<()> {
+        if !self.index_exists(index) {
+            return Err(CsvError::NoSuchIndex(index));
+        }
+        self.set_cell(index.row, index.column, Some(new_data));
+        self.modified = true;
+        Ok(())
+    }
+
+    fn set_null(&mut self, index: Index) -> CsvResult<()> {
+        if !self.index_exists(index) {
+            return Err(CsvError::NoSuchIndex(index));
+        }
+        self.set_cell(index.row, index.column, None);
+        self.modified = true;
+        Ok(())
+    }
+
+    fn replace_column(&mut self, column: usize, new_data: Vec<String>) -> CsvResult<()> {
+        if self.column_id(column).is_none() {
+            return Err(CsvError::NoSuchColumn(column));
+        }
+        if new_data.len() != self.row_count() {
+            return Err(CsvError::FailedToReplaceColumn(column));
+        }
+        for (i, new_value) in new_data.into_iter().enumerate() {
+            self.set_cell(i + 1, column, Some(new_value));
+        }
+        self.modified = true;
+        Ok(())
+    }
+
+    fn replace_row(&mut self, row: usize, new_data: Vec<String>) -> CsvResult<()> {
+        if self.row_id(row).is_none() {
+            return Err(CsvError::NoSuchRow(row));
+        }
+        if new_data.len() != self.property_count() {
+            return Err(CsvError::FailedToReplaceRow(row));
+        }
+        for (i, new_value) in new_data.into_iter().enumerate() {
+            self.set_cell(row, i + 1, Some(new_value));
+        }
+        self.modified = true;
+        Ok(())
+    }
+
+    fn insert_row(&mut self, row: usize, new_data: Vec<String>) -> CsvResult<()> {
+        if row == 0 || row > self.row_count() + 1 {
+            return Err(CsvError::NoSuchRow(row));
+        }
+        if new_data.len() != self.property_count() {
+            return Err(CsvError::FailedToInsertRow(row));
+        }
+        let row_id = self.next_row_id;
+        self.next_row_id += 1;
+        self.row_ids_mut().insert(row - 1, row_id);
+        for (i, new_value) in new_data.into_iter().enumerate() {
+            self.set_cell(row, i + 1, Some(new_value));
+        }
+        self.modified = true;
+        Ok(())
+    }
+
+    fn insert_column(
+        &mut self,
+        column: usize,
+        header: String,
+        new_data: Vec<String>,
+    ) -> CsvResult<()> {
+        if column == 0 || column > self.column_count() + 1 {
+            return Err(CsvError::NoSuchColumn(column));
+        }
+        if new_data.len() != self.row_count() {
+            return Err(CsvError::FailedToInsertColumn(column));
+        }
+        let column_id = self.next_column_id;
+        self.next_column_id += 1;
+        self.headers.insert(column - 1, header);
+        self.columns.insert(column - 1, column_id);
+        for (i, new_value) in new_data.into_iter().enumerate() {
+            self.set_cell(i + 1, column, Some(new_value));
+        }
+        self.modified = true;
+        Ok(())
+    }
+
+    fn reorder_rows(&mut self, order: &[usize]) -> CsvResult<()> {
+        let mut seen = vec![false; self.row_count()];
+        for &row in order {
+            if self.row_id(row).is_none() || std::mem::replace(&mut seen[row - 1], true) {
+                return Err(CsvError::FailedToReorderRows);
+            }
+        }
+        if order.len() != self.row_count() {
+            return Err(CsvError::FailedToReorderRows);
+        }
+        let rows = order.iter().filter_map(|&row| self.row_id(row)).collect();
+        self.rows = Some(rows);
+        self.modified = true;
+        Ok(())
+    }
+
+    fn delete_row(&mut self, row: usize) -> CsvResult<()> {
+        if self.row_id(row).is_none() {
+            return Err(CsvError::NoSuchRow(row));
+        }
+        let row_id = self.row_ids_mut().remove(row - 1);
+        let edited = self
+            .edits
+            .range((row_id, 0)..(row_id + 1, 0))
+            .map(|(&key, _)| key)
+            .collect::<Vec<_>>();
+        for key in edited {
+            self.edits.remove(&key);
+        }
+        self.modified = true;
+        Ok(())
+    }
+
+    fn delete_column(&mut self, column: usize) -> CsvResult<()> {
+        if self.column_id(column).is_none() {
+            return Err(CsvError::NoSuchColumn(column));
+        }
+        self.headers.remove(column - 1);
+        let column_id = self.columns.remove(column - 1);
+        self.edits.retain(|&(_, edited), _| edited != column_id);
+        self.modified = true;
+        Ok(())
+    }
+
+    fn is_file_backed(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{csv_data_handle::tests::cells, csv_io::read_csv, schema::Schema};
+    use std::io::Write;
+
+    fn mapped(name: &str, text: &str, format: &CsvFormat) -> MappedCsv {
+        let path = std::env::temp_dir().join(format!("{}-{}.csv", name, std::process::id()));
+        std::fs::File::create(&path)
+            .unwrap()
+            .write_all(text.as_bytes())
+            .unwrap();
+        let mapped_csv = MappedCsv::open(&File::open(&path).unwrap(), format).unwrap();
+        std::fs::remove_file(path).unwrap();
+        mapped_csv
+    }
+
+    const TEXT: &str = "name,\"home, town\"\r\nann,\"say \"\"hi\"\"\"\r\n\r\nbob,\r\n,\"\"\r\n";
+
+    #[test]
+    fn test_reads_like_read_csv() {
+        let format = CsvFormat::default();
+        let mapped_csv = mapped("mapped-read", TEXT, &format);
+        let csv_holder = read_csv(TEXT.as_bytes(), &format).unwrap();
+        assert_eq!(cells(&mapped_csv), cells(&csv_holder));
+        assert_eq!(mapped_csv.row_count(), 3);
+        assert_eq!(
+            mapped_csv.data_at(Index { row: 1, column: 2 }).unwrap(),
+            "say \"hi\""
+        );
+        assert!(mapped_csv.is_null(Index { row: 2, column: 2 }).unwrap());
+        assert!(!mapped_csv.is_null(Index { row: 3, column: 2 }).unwrap());
+        assert!(mapped_csv.row(4).is_err());
+
+        let format = CsvFormat {
+            has_header: false,
+            ..CsvFormat::default()
+        };
+        let mapped_csv = mapped("mapped-no-header", TEXT, &format);
+        assert_eq!(mapped_csv.headers(), ["column_1", "column_2"]);
+        assert_eq!(mapped_csv.row_count(), 4);
+
+        let text = "a\n\n\"\"\n";
+        let mapped_csv = mapped("mapped-one-column", text, &CsvFormat::default());
+        let csv_holder = read_csv(text.as_bytes(), &CsvFormat::default()).unwrap();
+        assert_eq!(cells(&mapped_csv), cells(&csv_holder));
+        assert!(mapped_csv.is_null(Index { row: 1, column: 1 }).unwrap());
+    }
+
+    #[test]
+    fn test_edits_match_csv_holder() {
+        let format = CsvFormat::default();
+        let mut mapped_csv = mapped("mapped-edits", TEXT, &format);
+        let mut csv_holder = read_csv(TEXT.as_bytes(), &format).unwrap();
+        let edits: [fn(&mut dyn CsvDataHandle) -> CsvResult<()>; 10] = [
+            |data| data.replace_data_at(Index { row: 1, column: 1 }, "anne".to_string()),
+            |data| data.set_null(Index { row: 3, column: 2 }),
+            |data| data.insert_row(2, vec!["cat".to_string(), "x".to_string()]),
+            |data| {
+                data.append_column(
+                    "age".to_string(),
+                    ["1", "2", "3", "4"].map(String::from).to_vec(),
+                )
+            },
+            |data| data.delete_column(1),
+            |data| data.reorder_rows(&[4, 3, 2, 1]),
+            |data| data.delete_row(2),
+            |data| data.replace_row(1, vec!["a".to_string(), "b".to_string()]),
+            |data| data.replace_column(2, ["5", "6", "7"].map(String::from).to_vec()),
+            |data| {
+                data.insert_column(
+                    1,
+                    "id".to_string(),
+                    ["1", "2", "3"].map(String::from).to_vec(),
+                )
+            },
+        ];
+        for edit in edits {
+            edit(&mut mapped_csv).unwrap();
+            edit(&mut csv_holder).unwrap();
+            assert_eq!(cells(&mapped_csv), cells(&csv_holder));
+        }
+        assert!(mapped_csv.has_unsaved_changes());
+        assert!(mapped_csv.delete_row(4).is_err());
+        assert!(mapped_csv.reorder_rows(&[1, 1, 2]).is_err());
+        assert!(mapped_csv.insert_row(1, Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        let path = std::env::temp_dir().join(format!("mapped-invalid-{}.csv", std::process::id()));
+        let field_count = ParseErrorKind::FieldCount {
+            expected: 2,
+            found: 1,
+        };
+        let cases = [
+            (&b"a,b\n1\n"[..], field_count, 2),
+            (b"a\n\"open\n", ParseErrorKind::UnclosedQuote, 2),
+            (b"a\n\xff\n", ParseErrorKind::InvalidUtf8, 2),
+            (b"", ParseErrorKind::Empty, 1),
+        ];
+        for (text, kind, line) in cases {
+            std::fs::write(&path, text).unwrap();
+            let file = File::open(&path).unwrap();
+            match MappedCsv::open(&file, &CsvFormat::default()) {
+                Err(CsvIoError::Parse(error)) => {
+                    assert_eq!(error.kind, kind);
+                    assert_eq!(error.position.line, line);
+                }
+                _ => panic!("{:?} should not open", text),
+            }
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_schema_is_inferred_from_a_sample() {
+        let mut text = "n\n".to_string();
+        text.push_str(&"1\n".repeat(crate::schema::FILE_SAMPLE_ROWS));
+        text.push_str("x\n");
+        let mapped_csv = mapped("mapped-sample", &text, &CsvFormat::default());
+        assert_eq!(Schema::infer(&mapped_csv).column(1).to_string(), "integer");
+    }
+}
@@ -0,0 +1,188 @@
+use std::{error::Error, fmt::Display};
+use unicode_width::UnicodeWidthChar;
+
+/// Where something lies in a piece of text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Position {
+    /// The line it is on, counting from 1.
+    pub line: usize,
+    /// How far into that line it is, in bytes.
+    pub column: usize,
+    /// How far into the whole text it is, in bytes.
+    pub byte_offset: usize,
+}
+
+impl Position {
+    /// The position of byte `byte_offset` of `text`, along with the line it is on.
+    pub fn locate(text: &str, byte_offset: usize) -> (Self, &str) {
+        let line_start = text[..byte_offset].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = match text[byte_offset..].find('\n') {
+            Some(i) => byte_offset + i,
+            None => text.len(),
+        };
+        let line = &text[line_start..line_end];
+        let position = Position {
+            line: text[..line_start].matches('\n').count() + 1,
+            column: byte_offset - line_start,
+            byte_offset,
+        };
+        (position, line.strip_suffix('\r').unwrap_or(line))
+    }
+}
+
+/// What is wrong with the text a `ParseError` was found in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// A row has `found` fields where the first row has `expected`.
+    FieldCount { expected: usize, found: usize },
+    /// Something other than a delimiter follows the quote that closes a field.
+    TextAfterQuote,
+    /// A quoted field is still open at the end of the input.
+    UnclosedQuote,
+    /// There is no header line, or no row to count the columns of.
+    Empty,
+    /// The text is not valid UTF-8.
+    InvalidUtf8,
+}
+
+impl Display for ParseErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ParseErrorKind::FieldCount { expected, found } => {
+                write!(f, "expected {} fields, found {}", expected, found)
+            }
+            ParseErrorKind::TextAfterQuote => write!(f, "text after the closing quote of a field"),
+            ParseErrorKind::UnclosedQuote => write!(f, "quoted field is never closed"),
+            ParseErrorKind::Empty => write!(f, "there are no rows"),
+            ParseErrorKind::InvalidUtf8 => write!(f, "text is not valid UTF-8"),
+        }
+    }
+}
+
+/// A problem with the text of a file, along with where it was found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub position: Position,
+    /// The line the problem is on, without its line ending.
+    pub line_text: String,
+}
+
+impl ParseError {
+    /// The error for byte `byte_offset` of `text`.
+    pub fn at(kind: ParseErrorKind, text: &str, byte_offset: usize) -> Self {
+        let (position, line) = Position::locate(text, byte_offset);
+        Self {
+            kind,
+            position,
+            line_text: line.to_string(),
+        }
+    }
+
+    /// The error for `bytes` that are valid UTF-8 only up to byte `valid_up_to`. Only the line
+    /// with the first invalid byte is copied, with the invalid bytes replaced.
+    pub fn invalid_utf8(bytes: &[u8], valid_up_to: usize) -> Self {
+        let valid = std::str::from_utf8(&bytes[..valid_up_to]).expect("valid up to here");
+        let (position, _) = Position::locate(valid, valid_up_to);
+        let line_start = valid_up_to - position.column;
+        let line_end = bytes[valid_up_to..]
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .map_or(bytes.len(), |i| valid_up_to + i);
+        let line = String::from_utf8_lossy(&bytes[line_start..line_end]);
+        Self {
+            kind: ParseErrorKind::InvalidUtf8,
+            position,
+            line_text: line.strip_suffix('\r').unwrap_or(&line).to_string(),
+        }
+    }
+
+    /// The same error, found in a piece of text that starts at the beginning of line
+    /// `start.line` of a larger one.
+    pub fn offset_by(mut self, start: Position) -> Self {
+        self.position.line += start.line - 1;
+        self.position.byte_offset += start.byte_offset;
+        self
+    }
+
+    /// The line the problem is on, numbered, with a caret under where it lies.
+    pub fn snippet(&self) -> String {
+        let number = self.position.line.to_string();
+        let gutter = " ".repeat(number.len());
+        let column = self.position.column.min(self.line_text.len());
+        // Tabs are kept so that the caret lines up however wide they are shown.
+        let indent = self.line_text[..column]
+            .chars()
+            .map(|c| match c {
+                '\t' => "\t".to_string(),
+                c => " ".repeat(c.width().unwrap_or(0)),
+            })
+            .collect::<String>();
+        format!(
+            "{} | {}\n{} | {}^\n",
+            number, self.line_text, gutter, indent
+        )
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "line {}, byte {}: {}",
+            self.position.line, self.position.byte_offset, self.kind
+        )
+    }
+}
+
+impl Error for ParseError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locate() {
+        let text = "a,b\r\nc,d\ne";
+        let (position, line) = Position::locate(text, 7);
+        assert_eq!(
+            position,
+            Position {
+                line: 2,
+                column: 2,
+                byte_offset: 7
+            }
+        );
+        assert_eq!(line, "c,d");
+        assert_eq!(Position::locate(text, 10).1, "e");
+        assert_eq!(Position::locate(text, 0).0.line, 1);
+    }
+
+    #[test]
+    fn test_snippet() {
+        let kind = ParseErrorKind::FieldCount {
+            expected: 3,
+            found: 2,
+        };
+        let error = ParseError::at(kind, "a,b,c\n1,2\n", 9).offset_by(Position {
+            line: 10,
+            column: 0,
+            byte_offset: 100,
+        });
+        assert_eq!(
+            error.to_string(),
+            "line 11, byte 109: expected 3 fields, found 2"
+        );
+        assert_eq!(error.snippet(), "11 | 1,2\n   |    ^\n");
+        let error = ParseError::at(ParseErrorKind::TextAfterQuote, "\t日\"a\"b", 7);
+        assert_eq!(error.snippet(), "1 | \t日\"a\"b\n  | \t     ^\n");
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        let error = ParseError::invalid_utf8(b"a,b\r\nc,\xffd\r\ne\xfe", 7);
+        assert_eq!(error.to_string(), "line 2, byte 7: text is not valid UTF-8");
+        assert_eq!(error.line_text, "c,\u{fffd}d");
+        assert_eq!(error.snippet(), "2 | c,\u{fffd}d\n  |   ^\n");
+    }
+}
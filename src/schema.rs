@@ -0,0 +1,545 @@
+use crate::{
+    csv_data_handle::{CsvDataHandle, CsvError, CsvResult, Index},
+    sort::{parse_date, SortKey},
+};
+use std::fmt::Display;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ColumnType {
+    Integer,
+    Float,
+    Bool,
+    Date,
+    /// Only the listed values are allowed.
+    Enum(Vec<String>),
+    String,
+}
+
+impl ColumnType {
+    /// Reads a type as the `schema` command writes it, e.g. `integer` or `enum` followed by the
+    /// allowed values.
+    pub fn from_words(name: &str, values: Vec<String>) -> Option<Self> {
+        let column_type = match name {
+            "integer" => ColumnType::Integer,
+            "float" => ColumnType::Float,
+            "bool" => ColumnType::Bool,
+            "date" => ColumnType::Date,
+            "string" => ColumnType::String,
+            "enum" if !values.is_empty() => return Some(ColumnType::Enum(values)),
+            _ => return None,
+        };
+        values.is_empty().then_some(column_type)
+    }
+
+    fn accepts(&self, value: &str) -> bool {
+        match self {
+            ColumnType::Integer => value.parse::<i64>().is_ok(),
+            ColumnType::Float => {
+                value.contains(|c: char| c.is_ascii_digit()) && value.parse::<f64>().is_ok()
+            }
+            ColumnType::Bool => {
+                value.eq_ignore_ascii_case("true") || value.eq_ignore_ascii_case("false")
+            }
+            ColumnType::Date => parse_date(value).is_some(),
+            ColumnType::Enum(values) => values.iter().any(|allowed| allowed == value),
+            ColumnType::String => true,
+        }
+    }
+
+    /// What a cell of this type has to be, for error messages.
+    fn expectation(&self) -> String {
+        match self {
+            ColumnType::Integer => "an integer".to_string(),
+            ColumnType::Float => "a number".to_string(),
+            ColumnType::Bool => "true or false".to_string(),
+            ColumnType::Date => "a date".to_string(),
+            ColumnType::Enum(values) => format!("one of {}", values.join(", ")),
+            ColumnType::String => "text".to_string(),
+        }
+    }
+}
+
+impl Display for ColumnType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ColumnType::Integer => write!(f, "integer"),
+            ColumnType::Float => write!(f, "float"),
+            ColumnType::Bool => write!(f, "bool"),
+            ColumnType::Date => write!(f, "date"),
+            ColumnType::Enum(_) => write!(f, "enum"),
+            ColumnType::String => write!(f, "string"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnSchema {
+    pub column_type: ColumnType,
+    /// Whether cells may be left empty.
+    pub nullable: bool,
+}
+
+impl Default for ColumnSchema {
+    fn default() -> Self {
+        Self {
+            column_type: ColumnType::String,
+            nullable: true,
+        }
+    }
+}
+
+impl ColumnSchema {
+    /// The narrowest type that fits every value in the column. Enums are never inferred, as
+    /// there is no telling whether a column that happens to repeat a few values is limited to
+    /// them.
+    pub fn infer<'a>(cells: impl IntoIterator<Item = Option<&'a str>>) -> Self {
+        let mut inference = Inference::new();
+        for cell in cells {
+            inference.add(cell);
+        }
+        inference.finish()
+    }
+
+    /// Checks what is about to be written to the cell at `index`; `None` is a null.
+    pub fn check(&self, index: Index, value: Option<&str>) -> CsvResult<()> {
+        let valid = match value {
+            None => self.nullable,
+            Some("") if self.column_type != ColumnType::String => self.nullable,
+            Some(value) => self.column_type.accepts(value),
+        };
+        if valid {
+            return Ok(());
+        }
+        let expected = match value {
+            None | Some("") => "filled in".to_string(),
+            Some(_) => self.column_type.expectation(),
+        };
+        Err(CsvError::SchemaViolation { index, expected })
+    }
+}
+
+impl Display for ColumnSchema {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.column_type)?;
+        if self.nullable {
+            write!(f, "?")?;
+        }
+        if let ColumnType::Enum(values) = &self.column_type {
+            for value in values {
+                if value.contains(char::is_whitespace) || value.is_empty() {
+                    write!(f, " \"{}\"", value)?;
+                } else {
+                    write!(f, " {}", value)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Works out a `ColumnSchema` from the cells of a column, given one at a time.
+#[derive(Debug, Clone)]
+struct Inference {
+    candidates: Vec<ColumnType>,
+    nullable: bool,
+    any_values: bool,
+}
+
+impl Inference {
+    fn new() -> Self {
+        Self {
+            candidates: vec![
+                ColumnType::Integer,
+                ColumnType::Float,
+                ColumnType::Bool,
+                ColumnType::Date,
+            ],
+            nullable: false,
+            any_values: false,
+        }
+    }
+
+    fn add(&mut self, cell: Option<&str>) {
+        match cell {
+            None | Some("") => self.nullable = true,
+            Some(value) => {
+                self.any_values = true;
+                self.candidates.retain(|candidate| candidate.accepts(value));
+            }
+        }
+    }
+
+    fn finish(self) -> ColumnSchema {
+        let column_type = match self.candidates.into_iter().next() {
+            Some(column_type) if self.any_values => column_type,
+            _ => ColumnType::String,
+        };
+        ColumnSchema {
+            column_type,
+            nullable: self.nullable,
+        }
+    }
+}
+
+/// How many rows of a file-backed sheet a schema is inferred from.
+pub const FILE_SAMPLE_ROWS: usize = 10_000;
+
+/// The type of each column, in order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Schema {
+    columns: Vec<ColumnSchema>,
+}
+
+impl Schema {
+    /// Infers the schema from every row, or for a file-backed sheet from its first
+    /// `FILE_SAMPLE_ROWS` rows, which later rows may not fit.
+    pub fn infer(data_handle: &(impl CsvDataHandle + ?Sized)) -> Self {
+        let row_count = match data_handle.is_file_backed() {
+            true => data_handle.row_count().min(FILE_SAMPLE_ROWS),
+            false => data_handle.row_count(),
+        };
+        // Read row by row, as that is the order a sheet read from a file can give them in
+        // without reading it once for each column.
+        let mut inferences = vec![Inference::new(); data_handle.column_count()];
+        for row in 1..row_count + 1 {
+            let Ok(cells) = data_handle.row_cells(row) else {
+                // Left to take any text, rather than refusing every edit.
+                return Self::default();
+            };
+            for (inference, cell) in inferences.iter_mut().zip(cells) {
+                inference.add(cell);
+            }
+        }
+        let columns = inferences.into_iter().map(Inference::finish).collect();
+        Self { columns }
+    }
+
+    /// The schema of `column`; columns the schema does not know about take any text.
+    pub fn column(&self, column: usize) -> ColumnSchema {
+        self.columns
+            .get(column.wrapping_sub(1))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Changes the schema of a column that `data_handle` already holds, if every cell in it
+    /// fits the new one.
+    pub fn set_column(
+        &mut self,
+        data_handle: &(impl CsvDataHandle + ?Sized),
+        column: usize,
+        column_schema: ColumnSchema,
+    ) -> CsvResult<()> {
+        for (i, cell) in data_handle.column_cells(column)?.into_iter().enumerate() {
+            column_schema.check(Index { row: i + 1, column }, cell)?;
+        }
+        self.fit(data_handle.column_count());
+        self.columns[column - 1] = column_schema;
+        Ok(())
+    }
+
+    /// Gives `column` back the schema it had before it was deleted, without checking its cells
+    /// again, as they are the ones the column held then.
+    pub(crate) fn restore_column(&mut self, column: usize, column_schema: ColumnSchema) {
+        self.fit(column);
+        self.columns[column - 1] = column_schema;
+    }
+
+    fn check_row(&self, row: usize, new_data: &[String]) -> CsvResult<()> {
+        for (i, value) in new_data.iter().enumerate() {
+            let column = i + 1;
+            self.column(column)
+                .check(Index { row, column }, Some(value))?;
+        }
+        Ok(())
+    }
+
+    fn check_column(&self, column: usize, new_data: &[String]) -> CsvResult<()> {
+        let column_schema = self.column(column);
+        for (i, value) in new_data.iter().enumerate() {
+            column_schema.check(Index { row: i + 1, column }, Some(value))?;
+        }
+        Ok(())
+    }
+
+    /// Pads the schema out with columns that take any text until it covers `column_count`
+    /// columns.
+    fn fit(&mut self, column_count: usize) {
+        if self.columns.len() < column_count {
+            self.columns.resize(column_count, ColumnSchema::default());
+        }
+    }
+}
+
+/// A `CsvDataHandle` that refuses writes that do not fit `schema`, and keeps `schema` in line
+/// as columns are added and removed.
+pub struct SchemaChecked<'a, H: CsvDataHandle> {
+    pub data: &'a mut H,
+    pub schema: &'a mut Schema,
+}
+
+impl<H: CsvDataHandle> CsvDataHandle for SchemaChecked<'_, H> {
+    fn data_at(&self, index: Index) -> CsvResult<&str> {
+        self.data.data_at(index)
+    }
+
+    fn is_null(&self, index: Index) -> CsvResult<bool> {
+        self.data.is_null(index)
+    }
+
+    fn row(&self, row: usize) -> CsvResult<Vec<&str>> {
+        self.data.row(row)
+    }
+
+    fn column(&self, column: usize) -> CsvResult<Vec<&str>> {
+        self.data.column(column)
+    }
+
+    fn headers(&self) -> Vec<&str> {
+        self.data.headers()
+    }
+
+    fn property_count(&self) -> usize {
+        self.data.property_count()
+    }
+
+    fn row_count(&self) -> usize {
+        self.data.row_count()
+    }
+
+    fn column_count(&self) -> usize {
+        self.data.column_count()
+    }
+
+    fn column_of_field(&self, field: &str) -> CsvResult<usize> {
+        self.data.column_of_field(field)
+    }
+
+    fn replace_data_at(&mut self, index: Index, new_data: String) -> CsvResult<()> {
+        self.schema
+            .column(index.column)
+            .check(index, Some(&new_data))?;
+        self.data.replace_data_at(index, new_data)
+    }
+
+    fn set_null(&mut self, index: Index) -> CsvResult<()> {
+        self.schema.column(index.column).check(index, None)?;
+        self.data.set_null(index)
+    }
+
+    fn replace_column(&mut self, column: usize, new_data: Vec<String>) -> CsvResult<()> {
+        self.schema.check_column(column, &new_data)?;
+        self.data.replace_column(column, new_data)
+    }
+
+    fn replace_row(&mut self, row: usize, new_data: Vec<String>) -> CsvResult<()> {
+        self.schema.check_row(row, &new_data)?;
+        self.data.replace_row(row, new_data)
+    }
+
+    fn insert_row(&mut self, row: usize, new_data: Vec<String>) -> CsvResult<()> {
+        self.schema.check_row(row, &new_data)?;
+        self.data.insert_row(row, new_data)
+    }
+
+    /// The new column gets whatever schema its data suggests.
+    fn insert_column(
+        &mut self,
+        column: usize,
+        header: String,
+        new_data: Vec<String>,
+    ) -> CsvResult<()> {
+        let column_schema = ColumnSchema::infer(new_data.iter().map(|value| Some(value.as_str())));
+        self.data.insert_column(column, header, new_data)?;
+        self.schema.fit(self.data.column_count() - 1);
+        self.schema.columns.insert(column - 1, column_schema);
+        Ok(())
+    }
+
+    fn sorted_order(&self, keys: &[SortKey]) -> CsvResult<Vec<usize>> {
+        self.data.sorted_order(keys)
+    }
+
+    fn reorder_rows(&mut self, order: &[usize]) -> CsvResult<()> {
+        self.data.reorder_rows(order)
+    }
+
+    fn delete_row(&mut self, row: usize) -> CsvResult<()> {
+        self.data.delete_row(row)
+    }
+
+    fn delete_column(&mut self, column: usize) -> CsvResult<()> {
+        self.data.delete_column(column)?;
+        self.schema.fit(self.data.column_count() + 1);
+        self.schema.columns.remove(column - 1);
+        Ok(())
+    }
+
+    fn is_file_backed(&self) -> bool {
+        self.data.is_file_backed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        csv_data_handle::tests::cells,
+        csv_holder::CsvHolder,
+        edit_history::{Edit, EditHistory},
+    };
+
+    fn csv_holder() -> CsvHolder {
+        CsvHolder::from_strs(
+            &["id", "price", "paid", "due", "note"],
+            &[
+                &["1", "2.5", "true", "2024-03-01", "x"],
+                &["2", "3", "FALSE", "1 Mar 2024", ""],
+            ],
+            &[Index { row: 2, column: 5 }],
+        )
+    }
+
+    #[test]
+    fn test_infer() {
+        let schema = Schema::infer(&csv_holder());
+        let types = (1..=5)
+            .map(|column| schema.column(column).to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(types, ["integer", "float", "bool", "date", "string?"]);
+        assert_eq!(
+            ColumnSchema::infer([Some("1"), Some(""), None]).to_string(),
+            "integer?"
+        );
+        assert_eq!(ColumnSchema::infer([None]).to_string(), "string?");
+        assert_eq!(ColumnSchema::infer([Some("nan")]).to_string(), "string");
+    }
+
+    #[test]
+    fn test_rejects_violations() {
+        let mut csv_holder = csv_holder();
+        let mut schema = Schema::infer(&csv_holder);
+        let mut checked = SchemaChecked {
+            data: &mut csv_holder,
+            schema: &mut schema,
+        };
+        let index = Index { row: 1, column: 1 };
+        let error = checked
+            .replace_data_at(index, "abc".to_string())
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Cell (1,1) must be an integer");
+        assert!(checked.set_null(index).is_err());
+        assert!(checked.replace_data_at(index, "7".to_string()).is_ok());
+        let row = ["3", "4", "yes", "2024-01-01", ""]
+            .map(String::from)
+            .to_vec();
+        assert!(matches!(
+            checked.append_row(row),
+            Err(CsvError::SchemaViolation {
+                index: Index { row: 3, column: 3 },
+                ..
+            })
+        ));
+        assert_eq!(checked.row_count(), 2);
+    }
+
+    #[test]
+    fn test_follows_column_changes() {
+        let mut csv_holder = csv_holder();
+        let mut schema = Schema::infer(&csv_holder);
+        let mut checked = SchemaChecked {
+            data: &mut csv_holder,
+            schema: &mut schema,
+        };
+        checked
+            .insert_column(1, "n".to_string(), vec!["1".to_string(), "2".to_string()])
+            .unwrap();
+        checked.delete_column(3).unwrap();
+        assert!(checked
+            .replace_data_at(Index { row: 1, column: 1 }, "x".to_string())
+            .is_err());
+        assert!(checked
+            .replace_data_at(Index { row: 1, column: 3 }, "x".to_string())
+            .is_err());
+        assert_eq!(schema.column(3).to_string(), "bool");
+    }
+
+    #[test]
+    fn test_set_column() {
+        let csv_holder = csv_holder();
+        let mut schema = Schema::infer(&csv_holder);
+        let status = ColumnSchema {
+            column_type: ColumnType::Enum(vec!["x".to_string(), "in progress".to_string()]),
+            nullable: true,
+        };
+        assert_eq!(status.to_string(), "enum? x \"in progress\"");
+        schema.set_column(&csv_holder, 5, status.clone()).unwrap();
+        assert_eq!(schema.column(5), status);
+        let required = ColumnSchema {
+            nullable: false,
+            ..status.clone()
+        };
+        assert!(matches!(
+            schema.set_column(&csv_holder, 5, required),
+            Err(CsvError::SchemaViolation {
+                index: Index { row: 2, column: 5 },
+                ..
+            })
+        ));
+        let mut csv_holder = csv_holder;
+        let mut history = EditHistory::default();
+        let mut checked = SchemaChecked {
+            data: &mut csv_holder,
+            schema: &mut schema,
+        };
+        history.apply(&mut checked, Edit::DeleteColumn(5)).unwrap();
+        history.undo(&mut checked).unwrap();
+        assert_eq!(schema.column(5), status);
+
+        assert_eq!(ColumnType::from_words("enum", vec![]), None);
+        assert_eq!(
+            ColumnType::from_words("date", vec![]),
+            Some(ColumnType::Date)
+        );
+    }
+
+    #[test]
+    fn test_refused_edits_change_nothing() {
+        let mut csv_holder =
+            CsvHolder::from_strs(&["name", "id"], &[&["a1", "1"], &["b1", "2"]], &[]);
+        let before = cells(&csv_holder);
+        let mut schema = Schema::infer(&csv_holder);
+        let mut history = EditHistory::default();
+        let mut checked = SchemaChecked {
+            data: &mut csv_holder,
+            schema: &mut schema,
+        };
+        let replace = Edit::ReplaceCells(vec![
+            (Index { row: 1, column: 1 }, Some("ax".to_string())),
+            (Index { row: 1, column: 2 }, Some("x".to_string())),
+        ]);
+        assert!(matches!(
+            history.apply(&mut checked, replace),
+            Err(CsvError::SchemaViolation {
+                index: Index { row: 1, column: 2 },
+                ..
+            })
+        ));
+        let insert = Edit::InsertRows {
+            row: 2,
+            new_data: vec![
+                vec![Some("c1".to_string()), Some("3".to_string())],
+                vec![Some("d1".to_string()), None],
+            ],
+        };
+        assert!(matches!(
+            history.apply(&mut checked, insert),
+            Err(CsvError::SchemaViolation {
+                index: Index { row: 3, column: 2 },
+                ..
+            })
+        ));
+        assert!(history.undo(&mut checked).unwrap().is_none());
+        assert_eq!(cells(&csv_holder), before);
+    }
+}
@@ -0,0 +1,190 @@
+use crate::csv_data_handle::{ColumnRef, CsvDataHandle, CsvError, CsvResult, Index};
+use regex::{NoExpand, Regex, RegexBuilder};
+use std::borrow::Cow;
+
+/// The cells a `find` or `replace` looks at: every cell, unless narrowed down to a column, a
+/// range of rows or both.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Scope {
+    pub column: Option<ColumnRef>,
+    /// First and last row, inclusive.
+    pub rows: Option<(usize, usize)>,
+}
+
+impl Scope {
+    /// The cells in scope, row by row.
+    pub fn indexes(&self, data_handle: &(impl CsvDataHandle + ?Sized)) -> CsvResult<Vec<Index>> {
+        let columns = match &self.column {
+            Some(column) => {
+                let column = column.resolve(data_handle)?;
+                column..column + 1
+            }
+            None => 1..data_handle.column_count() + 1,
+        };
+        let rows = match self.rows {
+            Some((first, last)) => {
+                for row in [first, last] {
+                    if row == 0 || row > data_handle.row_count() {
+                        return Err(CsvError::NoSuchRow(row));
+                    }
+                }
+                first..=last
+            }
+            None => 1..=data_handle.row_count(),
+        };
+        Ok(rows
+            .flat_map(|row| columns.clone().map(move |column| Index { row, column }))
+            .collect())
+    }
+}
+
+/// What to look for in a cell: either literal text or a regular expression.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    regex: Regex,
+    is_regex: bool,
+}
+
+impl Pattern {
+    pub fn new(pattern: &str, is_regex: bool, ignore_case: bool) -> Result<Self, regex::Error> {
+        let source = if is_regex {
+            Cow::Borrowed(pattern)
+        } else {
+            Cow::Owned(regex::escape(pattern))
+        };
+        let regex = RegexBuilder::new(&source)
+            .case_insensitive(ignore_case)
+            .build()?;
+        Ok(Self { regex, is_regex })
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.regex.is_match(text)
+    }
+
+    /// Replaces every match in `text`. For a regular expression, `$1` or `${name}` in
+    /// `replacement` stand for what a capture group matched.
+    pub fn replace<'a>(&self, text: &'a str, replacement: &str) -> Cow<'a, str> {
+        if self.is_regex {
+            self.regex.replace_all(text, replacement)
+        } else {
+            self.regex.replace_all(text, NoExpand(replacement))
+        }
+    }
+}
+
+/// The cells in `scope` that `pattern` matches. Cells that hold no value never match.
+pub fn find(
+    data_handle: &(impl CsvDataHandle + ?Sized),
+    pattern: &Pattern,
+    scope: &Scope,
+) -> CsvResult<Vec<Index>> {
+    let mut found = Vec::new();
+    for index in scope.indexes(data_handle)? {
+        if data_handle
+            .cell(index)?
+            .is_some_and(|cell| pattern.is_match(cell))
+        {
+            found.push(index);
+        }
+    }
+    Ok(found)
+}
+
+/// The new values of the cells in `scope` that replacing `pattern` would change.
+pub fn replacements(
+    data_handle: &(impl CsvDataHandle + ?Sized),
+    pattern: &Pattern,
+    replacement: &str,
+    scope: &Scope,
+) -> CsvResult<Vec<(Index, String)>> {
+    let mut changes = Vec::new();
+    for index in scope.indexes(data_handle)? {
+        let Some(cell) = data_handle.cell(index)? else {
+            continue;
+        };
+        if let Cow::Owned(new_value) = pattern.replace(cell, replacement) {
+            if new_value != cell {
+                changes.push((index, new_value));
+            }
+        }
+    }
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::csv_holder::CsvHolder;
+
+    fn csv_holder() -> CsvHolder {
+        CsvHolder::from_strs(
+            &["name", "city"],
+            &[
+                &["Brendan", "Dublni"],
+                &["Aoife", "dublni"],
+                &["Dublni", "Cork"],
+            ],
+            &[],
+        )
+    }
+
+    #[test]
+    fn test_find_in_scope() {
+        let csv_holder = csv_holder();
+        let pattern = Pattern::new("Dublni", false, false).unwrap();
+        let index = |row, column| Index { row, column };
+        assert_eq!(
+            find(&csv_holder, &pattern, &Scope::default()).unwrap(),
+            vec![index(1, 2), index(3, 1)]
+        );
+        let scope = Scope {
+            column: Some(ColumnRef::Name("city".to_string())),
+            rows: None,
+        };
+        let pattern = Pattern::new("dublni", false, true).unwrap();
+        assert_eq!(
+            find(&csv_holder, &pattern, &scope).unwrap(),
+            vec![index(1, 2), index(2, 2)]
+        );
+        let scope = Scope {
+            column: None,
+            rows: Some((2, 3)),
+        };
+        assert_eq!(
+            find(&csv_holder, &pattern, &scope).unwrap(),
+            vec![index(2, 2), index(3, 1)]
+        );
+        let scope = Scope {
+            column: None,
+            rows: Some((2, 4)),
+        };
+        assert!(find(&csv_holder, &pattern, &scope).is_err());
+    }
+
+    #[test]
+    fn test_replacements() {
+        let csv_holder = csv_holder();
+        let pattern = Pattern::new("(?i)d(ub)lni", true, false).unwrap();
+        let changes = replacements(&csv_holder, &pattern, "D${1}lin", &Scope::default()).unwrap();
+        assert_eq!(
+            changes,
+            vec![
+                (Index { row: 1, column: 2 }, "Dublin".to_string()),
+                (Index { row: 2, column: 2 }, "Dublin".to_string()),
+                (Index { row: 3, column: 1 }, "Dublin".to_string()),
+            ]
+        );
+
+        let pattern = Pattern::new("a", false, false).unwrap();
+        let changes = replacements(&csv_holder, &pattern, "$1", &Scope::default()).unwrap();
+        assert_eq!(
+            changes,
+            vec![(Index { row: 1, column: 1 }, "Brend$1n".to_string())]
+        );
+
+        let pattern = Pattern::new("Cork", false, false).unwrap();
+        let changes = replacements(&csv_holder, &pattern, "Cork", &Scope::default()).unwrap();
+        assert!(changes.is_empty());
+    }
+}
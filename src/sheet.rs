@@ -0,0 +1,185 @@
+use crate::{
+    columnar_csv::ColumnarCsv, csv_data_handle::*, csv_holder::CsvHolder, mapped_csv::MappedCsv,
+    sort::SortKey,
+};
+
+/// How the rows of a loaded file are kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Storage {
+    /// Every row is read into memory when the file is loaded.
+    #[default]
+    Rows,
+    /// Rows are read from the file as they are needed; see `MappedCsv`.
+    File,
+    /// Every column is read into memory when the file is loaded; see `ColumnarCsv`.
+    Columns,
+}
+
+impl Storage {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "rows" => Some(Storage::Rows),
+            "file" => Some(Storage::File),
+            "columns" => Some(Storage::Columns),
+            _ => None,
+        }
+    }
+}
+
+/// A sheet open for editing, kept in whichever storage it was loaded into.
+pub enum Sheet {
+    Rows(CsvHolder),
+    File(MappedCsv),
+    Columns(ColumnarCsv),
+}
+
+impl Sheet {
+    fn handle(&self) -> &dyn CsvDataHandle {
+        match self {
+            Sheet::Rows(csv_holder) => csv_holder,
+            Sheet::File(mapped_csv) => mapped_csv,
+            Sheet::Columns(columnar_csv) => columnar_csv,
+        }
+    }
+
+    fn handle_mut(&mut self) -> &mut dyn CsvDataHandle {
+        match self {
+            Sheet::Rows(csv_holder) => csv_holder,
+            Sheet::File(mapped_csv) => mapped_csv,
+            Sheet::Columns(columnar_csv) => columnar_csv,
+        }
+    }
+
+    pub fn has_unsaved_changes(&self) -> bool {
+        match self {
+            Sheet::Rows(csv_holder) => csv_holder.has_unsaved_changes(),
+            Sheet::File(mapped_csv) => mapped_csv.has_unsaved_changes(),
+            Sheet::Columns(columnar_csv) => columnar_csv.has_unsaved_changes(),
+        }
+    }
+
+    pub fn mark_saved(&mut self) {
+        match self {
+            Sheet::Rows(csv_holder) => csv_holder.mark_saved(),
+            Sheet::File(mapped_csv) => mapped_csv.mark_saved(),
+            Sheet::Columns(columnar_csv) => columnar_csv.mark_saved(),
+        }
+    }
+}
+
+impl From<CsvHolder> for Sheet {
+    fn from(csv_holder: CsvHolder) -> Self {
+        Sheet::Rows(csv_holder)
+    }
+}
+
+impl From<MappedCsv> for Sheet {
+    fn from(mapped_csv: MappedCsv) -> Self {
+        Sheet::File(mapped_csv)
+    }
+}
+
+impl From<ColumnarCsv> for Sheet {
+    fn from(columnar_csv: ColumnarCsv) -> Self {
+        Sheet::Columns(columnar_csv)
+    }
+}
+
+impl CsvDataHandle for Sheet {
+    fn data_at(&self, index: Index) -> CsvResult<&str> {
+        self.handle().data_at(index)
+    }
+
+    fn is_null(&self, index: Index) -> CsvResult<bool> {
+        self.handle().is_null(index)
+    }
+
+    fn cell(&self, index: Index) -> CsvResult<Option<&str>> {
+        self.handle().cell(index)
+    }
+
+    fn row(&self, row: usize) -> CsvResult<Vec<&str>> {
+        self.handle().row(row)
+    }
+
+    fn row_cells(&self, row: usize) -> CsvResult<Vec<Option<&str>>> {
+        self.handle().row_cells(row)
+    }
+
+    fn column(&self, column: usize) -> CsvResult<Vec<&str>> {
+        self.handle().column(column)
+    }
+
+    fn column_cells(&self, column: usize) -> CsvResult<Vec<Option<&str>>> {
+        self.handle().column_cells(column)
+    }
+
+    fn headers(&self) -> Vec<&str> {
+        self.handle().headers()
+    }
+
+    fn property_count(&self) -> usize {
+        self.handle().property_count()
+    }
+
+    fn row_count(&self) -> usize {
+        self.handle().row_count()
+    }
+
+    fn column_count(&self) -> usize {
+        self.handle().column_count()
+    }
+
+    fn column_of_field(&self, field: &str) -> CsvResult<usize> {
+        self.handle().column_of_field(field)
+    }
+
+    fn replace_data_at(&mut self, index: Index, new_data: String) -> CsvResult<()> {
+        self.handle_mut().replace_data_at(index, new_data)
+    }
+
+    fn set_null(&mut self, index: Index) -> CsvResult<()> {
+        self.handle_mut().set_null(index)
+    }
+
+    fn replace_column(&mut self, column: usize, new_data: Vec<String>) -> CsvResult<()> {
+        self.handle_mut().replace_column(column, new_data)
+    }
+
+    fn replace_row(&mut self, row: usize, new_data: Vec<String>) -> CsvResult<()> {
+        self.handle_mut().replace_row(row, new_data)
+    }
+
+    fn insert_row(&mut self, row: usize, new_data: Vec<String>) -> CsvResult<()> {
+        self.handle_mut().insert_row(row, new_data)
+    }
+
+    fn insert_column(
+        &mut self,
+        column: usize,
+        header: String,
+        new_data: Vec<String>,
+    ) -> CsvResult<()> {
+        self.handle_mut().insert_column(column, header, new_data)
+    }
+
+    fn sorted_order(&self, keys: &[SortKey]) -> CsvResult<Vec<usize>> {
+        self.handle().sorted_order(keys)
+    }
+
+    fn reorder_rows(&mut self, order: &[usize]) -> CsvResult<()> {
+        self.handle_mut().reorder_rows(order)
+    }
+
+    fn delete_row(&mut self, row: usize) -> CsvResult<()> {
+        self.handle_mut().delete_row(row)
+    }
+
+    fn delete_column(&mut self, column: usize) -> CsvResult<()> {
+        self.handle_mut().delete_column(column)
+    }
+
+    fn is_file_backed(&self) -> bool {
+        self.handle().is_file_backed()
+    }
+}
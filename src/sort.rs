@@ -0,0 +1,331 @@
+use crate::csv_data_handle::{CsvDataHandle, CsvResult};
+use std::{cmp::Ordering, fmt::Display};
+
+/// How the cells of a sort column are compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortMode {
+    /// Character by character.
+    #[default]
+    Lexical,
+    /// As numbers; cells that are not numbers come last.
+    Numeric,
+    /// As text, but with runs of digits compared as numbers, so `file2` comes before `file10`.
+    Natural,
+    /// As dates such as `2024-03-01`, `2024-03-01 14:30` or `1 Mar 2024`; cells that are not
+    /// dates come last.
+    Date,
+}
+
+impl SortMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "lexical" => Some(SortMode::Lexical),
+            "numeric" => Some(SortMode::Numeric),
+            "natural" => Some(SortMode::Natural),
+            "date" => Some(SortMode::Date),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortKey {
+    pub column: usize,
+    pub descending: bool,
+    pub mode: SortMode,
+}
+
+impl Display for SortKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "column {}", self.column)?;
+        if self.descending {
+            write!(f, " desc")?;
+        }
+        match self.mode {
+            SortMode::Lexical => Ok(()),
+            SortMode::Numeric => write!(f, " numeric"),
+            SortMode::Natural => write!(f, " natural"),
+            SortMode::Date => write!(f, " date"),
+        }
+    }
+}
+
+/// The rows of `data_handle` in the order `keys` puts them, earlier keys taking precedence.
+/// Rows that compare equal on every key keep their current order.
+pub fn sorted_order(
+    data_handle: &(impl CsvDataHandle + ?Sized),
+    keys: &[SortKey],
+) -> CsvResult<Vec<usize>> {
+    let mut columns = Vec::new();
+    for key in keys {
+        let cells = data_handle.column(key.column)?;
+        let sort_values = cells
+            .into_iter()
+            .map(|cell| SortValue::new(cell, key.mode))
+            .collect::<Vec<_>>();
+        columns.push((key, sort_values));
+    }
+    let mut order = (1..data_handle.row_count() + 1).collect::<Vec<_>>();
+    order.sort_by(|&a, &b| {
+        columns
+            .iter()
+            .map(|(key, values)| values[a - 1].compare(&values[b - 1], key.descending))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    });
+    Ok(order)
+}
+
+/// A cell turned into whatever its sort mode compares.
+enum SortValue<'a> {
+    Text(&'a str),
+    Number(f64),
+    Chunks(Vec<Chunk<'a>>),
+    Date([u32; 6]),
+    /// A cell that could not be read the way its sort mode asks for.
+    Unreadable(&'a str),
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum Chunk<'a> {
+    /// Digits without their leading zeros, ordered by length first so that they compare as
+    /// numbers however long they are.
+    Digits(usize, &'a str),
+    Text(String),
+}
+
+impl<'a> SortValue<'a> {
+    fn new(cell: &'a str, mode: SortMode) -> Self {
+        let readable = match mode {
+            SortMode::Lexical => Some(SortValue::Text(cell)),
+            SortMode::Numeric => cell.trim().parse::<f64>().ok().map(SortValue::Number),
+            SortMode::Natural => Some(SortValue::Chunks(natural_chunks(cell))),
+            SortMode::Date => parse_date(cell.trim()).map(SortValue::Date),
+        };
+        readable.unwrap_or(SortValue::Unreadable(cell))
+    }
+
+    /// Unreadable cells come last whichever way the column is sorted.
+    fn compare(&self, other: &Self, descending: bool) -> Ordering {
+        let ordering = match (self, other) {
+            (SortValue::Unreadable(a), SortValue::Unreadable(b)) => return a.cmp(b),
+            (SortValue::Unreadable(_), _) => return Ordering::Greater,
+            (_, SortValue::Unreadable(_)) => return Ordering::Less,
+            (SortValue::Text(a), SortValue::Text(b)) => a.cmp(b),
+            (SortValue::Number(a), SortValue::Number(b)) => a.total_cmp(b),
+            (SortValue::Chunks(a), SortValue::Chunks(b)) => a.cmp(b),
+            (SortValue::Date(a), SortValue::Date(b)) => a.cmp(b),
+            _ => Ordering::Equal,
+        };
+        if descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+}
+
+fn natural_chunks(cell: &str) -> Vec<Chunk<'_>> {
+    let mut chunks = Vec::new();
+    let mut rest = cell;
+    while let Some(first) = rest.chars().next() {
+        let is_digit = first.is_ascii_digit();
+        let end = rest
+            .find(|c: char| c.is_ascii_digit() != is_digit)
+            .unwrap_or(rest.len());
+        let (chunk, after) = rest.split_at(end);
+        chunks.push(if is_digit {
+            let digits = chunk.trim_start_matches('0');
+            Chunk::Digits(digits.len(), digits)
+        } else {
+            Chunk::Text(chunk.to_lowercase())
+        });
+        rest = after;
+    }
+    chunks
+}
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+/// Reads `YYYY-MM-DD` (or with `/`), optionally followed by a time, or a date with the month
+/// spelled out such as `1 Mar 2024` or `March 1, 2024`, as year, month, day, hour, minute and
+/// second.
+pub fn parse_date(cell: &str) -> Option<[u32; 6]> {
+    let (date, time) = match cell.split_once(['T', ' ']) {
+        Some((date, time)) if date.contains(['-', '/']) => (date, Some(time)),
+        _ => (cell, None),
+    };
+    let [year, month, day] = if date.contains(['-', '/']) {
+        let parts = date
+            .split(['-', '/'])
+            .map(|part| part.parse::<u32>().ok())
+            .collect::<Option<Vec<_>>>()?;
+        match parts[..] {
+            [year, month, day] if year > 999 => [year, month, day],
+            _ => return None,
+        }
+    } else {
+        spelled_out_date(cell)?
+    };
+    let [hour, minute, second] = match time {
+        Some(time) => {
+            let time = time.trim_end_matches('Z');
+            let time = time.split_once('.').map_or(time, |(time, _)| time);
+            let parts = time
+                .split(':')
+                .map(|part| part.parse::<u32>().ok())
+                .collect::<Option<Vec<_>>>()?;
+            match parts[..] {
+                [hour, minute] => [hour, minute, 0],
+                [hour, minute, second] => [hour, minute, second],
+                _ => return None,
+            }
+        }
+        None => [0, 0, 0],
+    };
+    // A leap second is written as second 60.
+    let valid = (1..=12).contains(&month)
+        && (1..=31).contains(&day)
+        && hour < 24
+        && minute < 60
+        && second <= 60;
+    valid.then_some([year, month, day, hour, minute, second])
+}
+
+fn spelled_out_date(cell: &str) -> Option<[u32; 3]> {
+    let words = cell
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>();
+    let month_of = |word: &str| {
+        let word = word.to_lowercase();
+        MONTHS
+            .iter()
+            .position(|month| word.get(..3).is_some_and(|start| month.starts_with(start)))
+            .map(|i| i as u32 + 1)
+    };
+    let (day, month, year) = match words[..] {
+        [day, month, year] if day.parse::<u32>().is_ok() => (day, month_of(month)?, year),
+        [month, day, year] => (day, month_of(month)?, year),
+        _ => return None,
+    };
+    Some([year.parse().ok()?, month, day.parse().ok()?])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::csv_holder::CsvHolder;
+
+    fn sorted(cells: &[&str], mode: SortMode, descending: bool) -> Vec<String> {
+        let rows = cells.iter().map(std::slice::from_ref).collect::<Vec<_>>();
+        let csv_holder = CsvHolder::from_strs(&["value"], &rows, &[]);
+        let key = SortKey {
+            column: 1,
+            descending,
+            mode,
+        };
+        sorted_order(&csv_holder, &[key])
+            .unwrap()
+            .into_iter()
+            .map(|row| cells[row - 1].to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_modes() {
+        let cells = ["10", "9", "x", "-1.5", ""];
+        assert_eq!(
+            sorted(&cells, SortMode::Lexical, false),
+            ["", "-1.5", "10", "9", "x"]
+        );
+        assert_eq!(
+            sorted(&cells, SortMode::Numeric, false),
+            ["-1.5", "9", "10", "", "x"]
+        );
+        assert_eq!(
+            sorted(&cells, SortMode::Numeric, true),
+            ["10", "9", "-1.5", "", "x"]
+        );
+        assert_eq!(
+            sorted(
+                &["file10", "File2", "file1", "file02b"],
+                SortMode::Natural,
+                false
+            ),
+            ["file1", "File2", "file02b", "file10"]
+        );
+        assert_eq!(
+            sorted(
+                &[
+                    "2024-03-01",
+                    "1 Feb 2024",
+                    "soon",
+                    "March 1, 2023",
+                    "2024-03-01 09:30"
+                ],
+                SortMode::Date,
+                false
+            ),
+            [
+                "March 1, 2023",
+                "1 Feb 2024",
+                "2024-03-01",
+                "2024-03-01 09:30",
+                "soon"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_multiple_keys_are_stable() {
+        let csv_holder = CsvHolder::from_strs(
+            &["team", "score"],
+            &[
+                &["b", "2"],
+                &["a", "10"],
+                &["b", "10"],
+                &["a", "2"],
+                &["a", "10"],
+            ],
+            &[],
+        );
+        let keys = [
+            SortKey {
+                column: 1,
+                descending: false,
+                mode: SortMode::Lexical,
+            },
+            SortKey {
+                column: 2,
+                descending: true,
+                mode: SortMode::Numeric,
+            },
+        ];
+        assert_eq!(
+            sorted_order(&csv_holder, &keys).unwrap(),
+            vec![2, 5, 4, 3, 1]
+        );
+    }
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(parse_date("2024/3/1"), Some([2024, 3, 1, 0, 0, 0]));
+        assert_eq!(
+            parse_date("2024-03-01T14:30:05Z"),
+            Some([2024, 3, 1, 14, 30, 5])
+        );
+        assert_eq!(parse_date("1 September 2024"), Some([2024, 9, 1, 0, 0, 0]));
+        assert_eq!(parse_date("01/03/2024"), None);
+        assert_eq!(parse_date("2024-13-01"), None);
+        assert_eq!(parse_date("2024-03-01 12:99:00"), None);
+        assert_eq!(parse_date("2024-03-01 12:30:99"), None);
+        assert_eq!(parse_date("2024-03-01 12:99"), None);
+        assert_eq!(
+            parse_date("2016-12-31T23:59:60Z"),
+            Some([2016, 12, 31, 23, 59, 60])
+        );
+    }
+}
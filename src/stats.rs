@@ -0,0 +1,248 @@
+use crate::csv_data_handle::{CsvDataHandle, CsvError, CsvResult, Index};
+use std::{collections::HashMap, fmt::Display};
+
+/// How many of the most common values `stats` lists unless told otherwise.
+pub const DEFAULT_TOP_VALUES: usize = 5;
+
+/// A summary of the values in one column. Cells that hold no value, or an empty one, are counted
+/// as nulls and otherwise left out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnStats {
+    pub count: usize,
+    pub nulls: usize,
+    pub distinct: usize,
+    /// Compared as numbers for a numeric column and as text otherwise.
+    pub min: Option<String>,
+    pub max: Option<String>,
+    /// Only for columns where every value is a number.
+    pub numeric: Option<NumericStats>,
+    /// The most common values and how often each appears, most common first.
+    pub top: Vec<(String, usize)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NumericStats {
+    pub sum: f64,
+    pub mean: f64,
+    pub median: f64,
+    /// The sample standard deviation, which needs at least two values.
+    pub std_dev: Option<f64>,
+}
+
+impl ColumnStats {
+    /// Summarises `column`, listing its `top` most common values.
+    pub fn compute(
+        data_handle: &(impl CsvDataHandle + ?Sized),
+        column: usize,
+        top: usize,
+    ) -> CsvResult<Self> {
+        let cells = data_handle.column_cells(column)?;
+        let values = cells
+            .iter()
+            .filter_map(|cell| cell.filter(|value| !value.is_empty()))
+            .collect::<Vec<_>>();
+
+        let mut frequencies = HashMap::<&str, usize>::new();
+        for value in &values {
+            *frequencies.entry(value).or_default() += 1;
+        }
+        let distinct = frequencies.len();
+        let mut top_values = frequencies.into_iter().collect::<Vec<_>>();
+        top_values.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        top_values.truncate(top);
+
+        let numbers = values
+            .iter()
+            .map(|value| value.trim().parse::<f64>().ok().filter(|n| n.is_finite()))
+            .collect::<Option<Vec<_>>>();
+        let (min, max, numeric) = match numbers {
+            Some(numbers) if !numbers.is_empty() => {
+                let by_number = |a: &(&&str, &f64), b: &(&&str, &f64)| a.1.total_cmp(b.1);
+                let min = values.iter().zip(&numbers).min_by(by_number);
+                let max = values.iter().zip(&numbers).max_by(by_number);
+                (
+                    min.map(|(value, _)| *value),
+                    max.map(|(value, _)| *value),
+                    Some(NumericStats::compute(numbers)),
+                )
+            }
+            _ => (
+                values.iter().min().copied(),
+                values.iter().max().copied(),
+                None,
+            ),
+        };
+
+        Ok(Self {
+            count: values.len(),
+            nulls: cells.len() - values.len(),
+            distinct,
+            min: min.map(str::to_string),
+            max: max.map(str::to_string),
+            numeric,
+            top: top_values
+                .into_iter()
+                .map(|(value, count)| (value.to_string(), count))
+                .collect(),
+        })
+    }
+}
+
+impl NumericStats {
+    /// `numbers` must not be empty.
+    fn compute(mut numbers: Vec<f64>) -> Self {
+        let n = numbers.len() as f64;
+        let sum = numbers.iter().sum::<f64>();
+        let mean = sum / n;
+        let std_dev = (numbers.len() > 1).then(|| {
+            let squares = numbers.iter().map(|x| (x - mean).powi(2)).sum::<f64>();
+            (squares / (n - 1.0)).sqrt()
+        });
+        numbers.sort_by(f64::total_cmp);
+        let middle = numbers.len() / 2;
+        let median = if numbers.len().is_multiple_of(2) {
+            (numbers[middle - 1] + numbers[middle]) / 2.0
+        } else {
+            numbers[middle]
+        };
+        Self {
+            sum,
+            mean,
+            median,
+            std_dev,
+        }
+    }
+}
+
+impl Display for ColumnStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "count: {}", self.count)?;
+        writeln!(f, "nulls: {}", self.nulls)?;
+        writeln!(f, "distinct: {}", self.distinct)?;
+        if let (Some(min), Some(max)) = (&self.min, &self.max) {
+            writeln!(f, "min: {}", min)?;
+            writeln!(f, "max: {}", max)?;
+        }
+        if let Some(numeric) = &self.numeric {
+            writeln!(f, "sum: {}", numeric.sum)?;
+            writeln!(f, "mean: {}", numeric.mean)?;
+            writeln!(f, "median: {}", numeric.median)?;
+            if let Some(std_dev) = numeric.std_dev {
+                writeln!(f, "std dev: {}", std_dev)?;
+            }
+        }
+        let top = self
+            .top
+            .iter()
+            .map(|(value, count)| format!("{} ({})", value, count))
+            .collect::<Vec<_>>();
+        write!(f, "top: {}", top.join(", "))
+    }
+}
+
+/// The cells of `column` that hold a value, read as numbers.
+fn numbers(data_handle: &(impl CsvDataHandle + ?Sized), column: usize) -> CsvResult<Vec<f64>> {
+    let mut numbers = Vec::new();
+    for (i, cell) in data_handle.column_cells(column)?.into_iter().enumerate() {
+        let Some(value) = cell.filter(|value| !value.is_empty()) else {
+            continue;
+        };
+        match value.trim().parse::<f64>() {
+            Ok(n) if n.is_finite() => numbers.push(n),
+            _ => return Err(CsvError::NotANumber(Index { row: i + 1, column })),
+        }
+    }
+    Ok(numbers)
+}
+
+/// The total of the values in `column`, every one of which must be a number.
+pub fn sum(data_handle: &(impl CsvDataHandle + ?Sized), column: usize) -> CsvResult<f64> {
+    Ok(numbers(data_handle, column)?.into_iter().sum())
+}
+
+/// The mean of the values in `column`, or `None` when it holds none.
+pub fn average(
+    data_handle: &(impl CsvDataHandle + ?Sized),
+    column: usize,
+) -> CsvResult<Option<f64>> {
+    let numbers = numbers(data_handle, column)?;
+    Ok((!numbers.is_empty()).then(|| numbers.iter().sum::<f64>() / numbers.len() as f64))
+}
+
+/// How many cells of `column` hold a value.
+pub fn count(data_handle: &(impl CsvDataHandle + ?Sized), column: usize) -> CsvResult<usize> {
+    let cells = data_handle.column_cells(column)?;
+    Ok(cells
+        .into_iter()
+        .filter(|cell| cell.is_some_and(|value| !value.is_empty()))
+        .count())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::csv_holder::CsvHolder;
+
+    fn csv_holder() -> CsvHolder {
+        CsvHolder::from_strs(
+            &["city", "price"],
+            &[
+                &["Cork", "10"],
+                &["Dublin", "2.5"],
+                &["Cork", ""],
+                &["Galway", "-4"],
+                &["Cork", "10"],
+            ],
+            &[Index { row: 4, column: 1 }],
+        )
+    }
+
+    #[test]
+    fn test_text_column() {
+        let stats = ColumnStats::compute(&csv_holder(), 1, 2).unwrap();
+        assert_eq!(
+            stats,
+            ColumnStats {
+                count: 4,
+                nulls: 1,
+                distinct: 2,
+                min: Some("Cork".to_string()),
+                max: Some("Dublin".to_string()),
+                numeric: None,
+                top: vec![("Cork".to_string(), 3), ("Dublin".to_string(), 1)],
+            }
+        );
+        assert_eq!(
+            stats.to_string(),
+            "count: 4\nnulls: 1\ndistinct: 2\nmin: Cork\nmax: Dublin\ntop: Cork (3), Dublin (1)"
+        );
+    }
+
+    #[test]
+    fn test_numeric_column() {
+        let stats = ColumnStats::compute(&csv_holder(), 2, 5).unwrap();
+        assert_eq!(stats.count, 4);
+        assert_eq!(stats.nulls, 1);
+        assert_eq!(stats.distinct, 3);
+        assert_eq!(stats.min.as_deref(), Some("-4"));
+        assert_eq!(stats.max.as_deref(), Some("10"));
+        let numeric = stats.numeric.unwrap();
+        assert_eq!(numeric.sum, 18.5);
+        assert_eq!(numeric.mean, 4.625);
+        assert_eq!(numeric.median, 6.25);
+        assert_eq!(numeric.std_dev, Some(6.75));
+    }
+
+    #[test]
+    fn test_aggregates() {
+        let csv_holder = csv_holder();
+        assert_eq!(sum(&csv_holder, 2).unwrap(), 18.5);
+        assert_eq!(average(&csv_holder, 2).unwrap(), Some(4.625));
+        assert_eq!(count(&csv_holder, 1).unwrap(), 4);
+        assert_eq!(
+            sum(&csv_holder, 1).unwrap_err().to_string(),
+            "Cell (1,1) is not a number"
+        );
+        assert!(sum(&csv_holder, 3).is_err());
+    }
+}
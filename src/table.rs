@@ -0,0 +1,133 @@
+use crate::{csv_data_handle::CsvDataHandle, csv_io::CsvIoError};
+use std::io::Write;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+pub const DEFAULT_MAX_COLUMN_WIDTH: usize = 30;
+
+/// Writes the given rows as an aligned grid, headed by the column names and with each row
+/// labelled by its 1-based index. Cells wider than `max_column_width` are cut short with `…`.
+pub fn write_table(
+    writer: &mut impl Write,
+    data_provider: &impl CsvDataHandle,
+    rows: impl IntoIterator<Item = usize>,
+    max_column_width: usize,
+) -> Result<(), CsvIoError> {
+    let max_column_width = max_column_width.max(1);
+    let headers = data_provider
+        .headers()
+        .into_iter()
+        .map(|header| fit(header, max_column_width))
+        .collect::<Vec<_>>();
+    let mut lines = Vec::new();
+    for row in rows {
+        let cells = data_provider
+            .row(row)?
+            .into_iter()
+            .map(|cell| fit(cell, max_column_width))
+            .collect::<Vec<_>>();
+        lines.push((row.to_string(), cells));
+    }
+
+    let label_width = lines
+        .iter()
+        .map(|(label, _)| label.width())
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let mut widths = headers.iter().map(|h| h.width()).collect::<Vec<_>>();
+    for (_, cells) in &lines {
+        for (width, cell) in widths.iter_mut().zip(cells) {
+            *width = (*width).max(cell.width());
+        }
+    }
+
+    write_table_line(writer, "#", &headers, label_width, &widths)?;
+    let separator = std::iter::once(label_width)
+        .chain(widths.iter().copied())
+        .map(|width| "-".repeat(width + 2))
+        .collect::<Vec<_>>()
+        .join("+");
+    writeln!(writer, "{}", separator.trim_end())?;
+    for (label, cells) in &lines {
+        write_table_line(writer, label, cells, label_width, &widths)?;
+    }
+    Ok(())
+}
+
+fn write_table_line(
+    writer: &mut impl Write,
+    label: &str,
+    cells: &[String],
+    label_width: usize,
+    widths: &[usize],
+) -> std::io::Result<()> {
+    let mut line = format!(" {}{} ", " ".repeat(label_width - label.width()), label);
+    for (cell, width) in cells.iter().zip(widths) {
+        line.push_str("| ");
+        line.push_str(cell);
+        line.push_str(&" ".repeat(width - cell.width() + 1));
+    }
+    writeln!(writer, "{}", line.trim_end())
+}
+
+/// Makes `cell` fit on one line in at most `max_width` columns of the terminal.
+fn fit(cell: &str, max_width: usize) -> String {
+    let one_line = cell
+        .chars()
+        .map(|c| match c {
+            '\n' | '\r' | '\t' => ' ',
+            c => c,
+        })
+        .collect::<String>();
+    if one_line.width() <= max_width {
+        return one_line;
+    }
+    let mut fitted = String::new();
+    let mut width = 0;
+    for c in one_line.chars() {
+        let char_width = c.width().unwrap_or(0);
+        if width + char_width > max_width - 1 {
+            break;
+        }
+        fitted.push(c);
+        width += char_width;
+    }
+    fitted.push('…');
+    fitted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::csv_holder::CsvHolder;
+
+    #[test]
+    fn test_write_table() {
+        let csv_holder = CsvHolder::from_strs(
+            &["name", "city"],
+            &[&["Brendan", "Dublin, Ireland"], &["東京", ""]],
+            &[],
+        );
+        let mut written = Vec::new();
+        write_table(&mut written, &csv_holder, 1..3, 10).unwrap();
+        assert_eq!(
+            String::from_utf8(written).unwrap(),
+            [
+                " # | name    | city",
+                "---+---------+------------",
+                " 1 | Brendan | Dublin, I…",
+                " 2 | 東京    |",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_fit() {
+        assert_eq!(fit("short", 10), "short");
+        assert_eq!(fit("two\nlines", 10), "two lines");
+        assert_eq!(fit("abcdef", 4), "abc…");
+        assert_eq!(fit("東京都庁", 5), "東京…");
+    }
+}
@@ -0,0 +1,36 @@
+use std::{
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+/// Runs the editor on `input`, typing `typed` into it, and returns its exit status.
+fn run_editor(input: &Path, typed: &str) -> Option<i32> {
+    let mut editor = Command::new(env!("CARGO_BIN_EXE_bootleg_editor_3000"))
+        .arg(input)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+    editor
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(typed.as_bytes())
+        .unwrap();
+    editor.wait().unwrap().code()
+}
+
+#[test]
+fn test_end_of_input_exit_status() {
+    let path = std::env::temp_dir().join(format!("cli-eof-{}.csv", std::process::id()));
+    std::fs::write(&path, "a,b\n1,2\n3,4\n").unwrap();
+    assert_eq!(run_editor(&path, "display\n"), Some(0));
+    assert_eq!(run_editor(&path, "delete_row 1\n"), Some(6));
+    assert_eq!(
+        run_editor(&path, "delete_row 1\nundo\nredo\nsave\n"),
+        Some(0)
+    );
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "a,b\n3,4\n");
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,326 @@
+use bootleg_editor_3000::{
+    batch, dialect::EscapeStyle, handle_buffer_request, handle_csv_request, handle_sheet_request,
+    parse_csv_request, read_csv, sniff_dialect, write_csv, write_csv_file, Buffer, Buffers,
+    ColumnarCsv, CsvDataHandle, CsvError, CsvFormat, CsvHolder, CsvIoError, CsvRequest, CsvResult,
+    Index, MappedCsv, ParseErrorKind, QuotePolicy, RequestErrorKind, RequestOptions, Session,
+    Sheet,
+};
+use std::path::PathBuf;
+
+const TEXT: &str = "name,qty,note\npear,3,\"ripe, soft\"\nplum,,\"\"\nfig,12,dried\n";
+
+fn written(data_handle: &impl CsvDataHandle, format: &CsvFormat) -> String {
+    let mut out = Vec::new();
+    write_csv(&mut out, data_handle, format, QuotePolicy::default()).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+/// Runs `line` on `sheet` the way the editor would, returning what it printed.
+fn run(line: &str, sheet: &mut Sheet, session: &mut Session) -> Result<String, CsvIoError> {
+    let options = RequestOptions::default();
+    let csv_request = parse_csv_request(line, &options.format).expect("a valid request");
+    let mut out = Vec::new();
+    handle_sheet_request(csv_request, sheet, session, &options, &mut out)?;
+    Ok(String::from_utf8(out).unwrap())
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_read_and_write() {
+    let format = CsvFormat::default();
+    let csv_holder = read_csv(TEXT.as_bytes(), &format).unwrap();
+    assert_eq!(csv_holder.headers(), ["name", "qty", "note"]);
+    assert_eq!(csv_holder.row_count(), 3);
+    assert_eq!(
+        csv_holder.row_cells(2).unwrap(),
+        [Some("plum"), None, Some("")]
+    );
+    assert_eq!(written(&csv_holder, &format), TEXT);
+    let Err(CsvIoError::Parse(error)) = read_csv("a,b\nx,y\n1\n".as_bytes(), &format) else {
+        panic!("expected a parse error");
+    };
+    let kind = ParseErrorKind::FieldCount {
+        expected: 2,
+        found: 1,
+    };
+    assert_eq!(error.kind, kind);
+    assert_eq!((error.position.line, error.position.byte_offset), (3, 9));
+    assert_eq!(error.snippet(), "3 | 1\n  |  ^\n");
+
+    let mut reader = "a;'b;c'\n1;'it''s'\n".as_bytes();
+    let dialect = sniff_dialect(&mut reader).unwrap();
+    assert_eq!((dialect.delimiter, dialect.quote), (';', '\''));
+    assert_eq!(dialect.escape, EscapeStyle::Doubled);
+    let format = CsvFormat {
+        dialect,
+        has_header: false,
+        ..CsvFormat::default()
+    };
+    let csv_holder = read_csv(reader, &format).unwrap();
+    assert_eq!(csv_holder.headers(), ["column_1", "column_2"]);
+    assert_eq!(csv_holder.row(2).unwrap(), ["1", "it's"]);
+}
+
+#[test]
+fn test_storages_agree() {
+    let dir = temp_dir("library-storages");
+    let path = dir.join("fruit.csv");
+    std::fs::write(&path, TEXT).unwrap();
+    let format = CsvFormat::default();
+    let sheets: [Sheet; 3] = [
+        read_csv(TEXT.as_bytes(), &format).unwrap().into(),
+        ColumnarCsv::read(TEXT.as_bytes(), &format).unwrap().into(),
+        MappedCsv::open(&std::fs::File::open(&path).unwrap(), &format)
+            .unwrap()
+            .into(),
+    ];
+    for mut sheet in sheets {
+        let mut session = Session::new(&sheet);
+        run("set 3 qty 13", &mut sheet, &mut session).unwrap();
+        run("sort qty desc numeric", &mut sheet, &mut session).unwrap();
+        run("delete_row 2", &mut sheet, &mut session).unwrap();
+        assert!(sheet.has_unsaved_changes());
+        write_csv_file(&path, &sheet, &format, QuotePolicy::default()).unwrap();
+        sheet.mark_saved();
+        let saved = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(saved, "name,qty,note\nfig,13,dried\nplum,,\"\"\n");
+        std::fs::write(&path, TEXT).unwrap();
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_requests() {
+    let format = CsvFormat::default();
+    let mut csv_holder = read_csv(TEXT.as_bytes(), &format).unwrap();
+    let mut session = Session::new(&csv_holder);
+    let options = RequestOptions::default();
+    let mut handle = |line: &str, csv_holder: &mut CsvHolder| {
+        let csv_request = parse_csv_request(line, &format).expect("a valid request");
+        let mut out = Vec::new();
+        handle_csv_request(csv_request, csv_holder, &mut session, &options, &mut out)?;
+        Ok::<_, CsvIoError>(String::from_utf8(out).unwrap())
+    };
+
+    handle("append_row kiwi,7,", &mut csv_holder).unwrap();
+    assert_eq!(handle("get 4 name", &mut csv_holder).unwrap(), "kiwi\n");
+    assert_eq!(handle("sum qty", &mut csv_holder).unwrap(), "22\n");
+    // `qty` holds integers, so the schema refuses anything else.
+    assert!(handle("set 1 qty many", &mut csv_holder).is_err());
+    handle("undo", &mut csv_holder).unwrap();
+    assert_eq!(csv_holder.row_count(), 3);
+    let error = handle("get 9 name", &mut csv_holder).unwrap_err();
+    let source = std::error::Error::source(&error).unwrap();
+    assert_eq!(source.to_string(), "Index (9,1) does not exist");
+    assert!(matches!(
+        handle("use_result", &mut csv_holder),
+        Err(CsvIoError::CannotSwitchSheets)
+    ));
+
+    let Err(error) = parse_csv_request("dleete_row 2", &format) else {
+        panic!("dleete_row parsed");
+    };
+    assert_eq!(
+        error.to_string(),
+        "unknown command \"dleete_row\"; did you mean \"delete_row\"?"
+    );
+    let Err(error) = parse_csv_request("delete_row two", &format) else {
+        panic!("delete_row two parsed");
+    };
+    assert!(matches!(
+        error.kind,
+        RequestErrorKind::NotANumber {
+            argument: "<row>",
+            ..
+        }
+    ));
+    assert_eq!(error.usage(), ["delete_row <row>"]);
+    assert!(matches!(
+        parse_csv_request("delete_row 2", &format),
+        Ok(CsvRequest::DeleteRow(2))
+    ));
+
+    let mut sheet = Sheet::from(csv_holder);
+    let mut session = Session::new(&sheet);
+    let grouped = run("group_by note agg sum(qty)", &mut sheet, &mut session).unwrap();
+    assert!(grouped.contains("sum(qty)"));
+    run("use_result", &mut sheet, &mut session).unwrap();
+    assert_eq!(sheet.headers(), ["note", "sum(qty)"]);
+    run("use_original", &mut sheet, &mut session).unwrap();
+    assert_eq!(sheet.headers(), ["name", "qty", "note"]);
+}
+
+#[test]
+fn test_buffers() {
+    let dir = temp_dir("library-buffers");
+    let other_path = dir.join("other.csv");
+    std::fs::write(&other_path, "name,qty,note\n").unwrap();
+    let format = CsvFormat::default();
+    let csv_holder = read_csv(TEXT.as_bytes(), &format).unwrap();
+    let options = RequestOptions::default();
+    let mut buffers = Buffers::new(Buffer::new("fruit".to_string(), csv_holder.into(), options));
+
+    let open = format!("open {}", other_path.display());
+    let commands = batch::script_commands(
+        "script",
+        &format!(
+            "{}\nswitch fruit\ncopy_rows 1 1 to other\ncopy_rows 3 3 to other\n",
+            open
+        ),
+    );
+    let (mut out, mut err) = (Vec::new(), Vec::new());
+    assert!(batch::run_commands(&commands, &mut buffers, false, &mut out, &mut err).unwrap());
+    assert_eq!(buffers.active().name, "fruit");
+
+    let csv_request = parse_csv_request("switch other", &format).unwrap();
+    handle_buffer_request(csv_request, &mut buffers, &mut out).unwrap();
+    let other = &buffers.active().data;
+    assert_eq!(other.column(1).unwrap(), ["pear", "fig"]);
+    assert!(buffers.active().has_unsaved_changes());
+
+    let csv_request = parse_csv_request("switch missing", &format).unwrap();
+    assert!(matches!(
+        handle_buffer_request(csv_request, &mut buffers, &mut out),
+        Err(CsvIoError::NoSuchBuffer(name)) if name == "missing"
+    ));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+/// A sheet stored outside the library: a single column of squares.
+struct Squares {
+    cells: Vec<String>,
+}
+
+impl CsvDataHandle for Squares {
+    fn data_at(&self, index: Index) -> CsvResult<&str> {
+        match self.cells.get(index.row.wrapping_sub(1)) {
+            Some(cell) if index.column == 1 => Ok(cell),
+            _ => Err(CsvError::NoSuchIndex(index)),
+        }
+    }
+
+    fn is_null(&self, index: Index) -> CsvResult<bool> {
+        self.data_at(index).map(|_| false)
+    }
+
+    fn row(&self, row: usize) -> CsvResult<Vec<&str>> {
+        let cell = self.data_at(Index { row, column: 1 });
+        cell.map(|cell| vec![cell])
+            .map_err(|_| CsvError::NoSuchRow(row))
+    }
+
+    fn column(&self, column: usize) -> CsvResult<Vec<&str>> {
+        match column {
+            1 => Ok(self.cells.iter().map(String::as_str).collect()),
+            _ => Err(CsvError::NoSuchColumn(column)),
+        }
+    }
+
+    fn headers(&self) -> Vec<&str> {
+        vec!["square"]
+    }
+
+    fn property_count(&self) -> usize {
+        1
+    }
+
+    fn row_count(&self) -> usize {
+        self.cells.len()
+    }
+
+    fn column_count(&self) -> usize {
+        1
+    }
+
+    fn column_of_field(&self, field: &str) -> CsvResult<usize> {
+        match field {
+            "square" => Ok(1),
+            _ => Err(CsvError::NoSuchField(field.to_string())),
+        }
+    }
+
+    fn replace_data_at(&mut self, index: Index, new_data: String) -> CsvResult<()> {
+        self.data_at(index)?;
+        self.cells[index.row - 1] = new_data;
+        Ok(())
+    }
+
+    fn set_null(&mut self, index: Index) -> CsvResult<()> {
+        self.replace_data_at(index, String::new())
+    }
+
+    fn replace_column(&mut self, column: usize, new_data: Vec<String>) -> CsvResult<()> {
+        if column != 1 || new_data.len() != self.cells.len() {
+            return Err(CsvError::FailedToReplaceColumn(column));
+        }
+        self.cells = new_data;
+        Ok(())
+    }
+
+    fn replace_row(&mut self, row: usize, new_data: Vec<String>) -> CsvResult<()> {
+        let [cell] =
+            <[String; 1]>::try_from(new_data).map_err(|_| CsvError::FailedToReplaceRow(row))?;
+        self.replace_data_at(Index { row, column: 1 }, cell)
+    }
+
+    fn insert_row(&mut self, row: usize, new_data: Vec<String>) -> CsvResult<()> {
+        let [cell] =
+            <[String; 1]>::try_from(new_data).map_err(|_| CsvError::FailedToInsertRow(row))?;
+        if row == 0 || row > self.cells.len() + 1 {
+            return Err(CsvError::NoSuchRow(row));
+        }
+        self.cells.insert(row - 1, cell);
+        Ok(())
+    }
+
+    fn insert_column(&mut self, column: usize, _: String, _: Vec<String>) -> CsvResult<()> {
+        Err(CsvError::FailedToInsertColumn(column))
+    }
+
+    fn reorder_rows(&mut self, order: &[usize]) -> CsvResult<()> {
+        let cells = order
+            .iter()
+            .map(|&row| self.cells.get(row.wrapping_sub(1)).cloned());
+        self.cells = cells
+            .collect::<Option<_>>()
+            .ok_or(CsvError::FailedToReorderRows)?;
+        Ok(())
+    }
+
+    fn delete_row(&mut self, row: usize) -> CsvResult<()> {
+        self.row(row)?;
+        self.cells.remove(row - 1);
+        Ok(())
+    }
+
+    fn delete_column(&mut self, column: usize) -> CsvResult<()> {
+        Err(CsvError::NoSuchColumn(column))
+    }
+}
+
+#[test]
+fn test_own_data_handle() {
+    let format = CsvFormat::default();
+    let mut squares = Squares {
+        cells: [16, 1, 9, 4].map(|n: i32| n.to_string()).to_vec(),
+    };
+    let mut session = Session::new(&squares);
+    let options = RequestOptions::default();
+    for line in ["sort square numeric", "append_row 25", "delete_row 1"] {
+        let csv_request = parse_csv_request(line, &format).unwrap();
+        handle_csv_request(
+            csv_request,
+            &mut squares,
+            &mut session,
+            &options,
+            &mut Vec::new(),
+        )
+        .unwrap();
+    }
+    assert_eq!(written(&squares, &format), "square\n4\n9\n16\n25\n");
+}